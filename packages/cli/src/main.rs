@@ -0,0 +1,386 @@
+//! `clipbridge`: scripts a running ClipBridge app or daemon through its
+//! control socket.
+
+use clipbridge_engine::control::{self, DeviceInfo, Request};
+use clipbridge_engine::events::preview;
+use clipbridge_engine::history::HistoryItem;
+use clipbridge_engine::sync::InboxItem;
+use serde_json::Value;
+use std::env;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: clipbridge [--socket PATH] [--json] COMMAND
+
+Commands:
+  start                              Start syncing
+  stop                               Stop syncing
+  status                             Show the device id and whether sync is running
+  events [NAME]...                   Print events as they happen, all or only NAME
+  copy [TEXT]                        Put TEXT (or stdin) on the clipboard
+  send [--to DEVICE]... [TEXT]       Send TEXT (or stdin) to all or some devices
+  paste [--from DEVICE]              Print the clipboard, or the latest item from DEVICE
+  devices                            List connected devices
+  inbox                              List items from devices waiting to be applied
+  accept [ID]                        Put an inbox item, or the newest one, on the clipboard
+  discard [ID]                       Drop an inbox item, or the newest one
+  history [--grep TEXT] [--limit N]  List or search recent history
+
+DEVICE is a device id, its name or a unique id prefix. Put -- before a
+TEXT or ID that starts with -, e.g. clipbridge copy -- -x.";
+
+/// Characters of an item shown per history line
+const PREVIEW_LEN: usize = 60;
+
+struct Options {
+    socket: PathBuf,
+    json: bool,
+    request: Request,
+}
+
+/// Parse the arguments after the program name
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut socket = None;
+    let mut json = false;
+    let mut args = args.into_iter().peekable();
+
+    while let Some(arg) = args.next_if(|arg| arg.starts_with('-')) {
+        match arg.as_str() {
+            "--socket" => {
+                socket = Some(PathBuf::from(args.next().ok_or("--socket needs a value")?))
+            }
+            "--json" => json = true,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ => return Err(format!("Unknown argument {}\n{}", arg, USAGE)),
+        }
+    }
+
+    let command = args.next().ok_or(USAGE)?;
+    let mut text = None;
+    let mut to = Vec::new();
+    let mut from = None;
+    let mut grep = None;
+    let mut limit = None;
+    let mut events = Vec::new();
+    let mut id = None;
+    // Set by `--`: the remaining arguments are values, even if they start with `-`
+    let mut positional = false;
+
+    while let Some(arg) = args.next() {
+        if arg == "--" && !positional {
+            positional = true;
+            continue;
+        }
+        let option = !positional && arg.starts_with('-');
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match (command.as_str(), arg.as_str(), option) {
+            (_, "--json", true) => json = true,
+            ("send", "--to", true) => to.push(value()?),
+            ("paste", "--from", true) => from = Some(value()?),
+            ("history", "--grep", true) => grep = Some(value()?),
+            ("history", "--limit", true) => {
+                limit = Some(
+                    value()?
+                        .parse()
+                        .map_err(|e| format!("Invalid --limit: {}", e))?,
+                )
+            }
+            ("copy" | "send", _, false) if text.is_none() => text = Some(arg),
+            ("events", _, false) => events.push(arg),
+            ("accept" | "discard", _, false) if id.is_none() => id = Some(arg),
+            _ => return Err(format!("Unknown argument {}\n{}", arg, USAGE)),
+        }
+    }
+
+    let request = match command.as_str() {
+        "start" => Request::StartSync,
+        "stop" => Request::StopSync,
+        "status" => Request::Status,
+        "events" => Request::Subscribe { events },
+        "copy" => Request::Copy {
+            text: text_or_stdin(text)?,
+        },
+        "send" => Request::Send {
+            text: text_or_stdin(text)?,
+            to,
+        },
+        "paste" => Request::Paste { from },
+        "devices" => Request::Devices,
+        "inbox" => Request::Inbox,
+        "accept" => Request::Accept { id },
+        "discard" => Request::Discard { id },
+        "history" => Request::History { grep, limit },
+        _ => return Err(format!("Unknown command {}\n{}", command, USAGE)),
+    };
+
+    let socket = match socket {
+        Some(path) => path,
+        None => control::default_socket_path()
+            .map_err(|e| format!("Cannot use the default control socket: {}", e))?,
+    };
+    Ok(Options {
+        socket,
+        json,
+        request,
+    })
+}
+
+fn text_or_stdin(text: Option<String>) -> Result<String, String> {
+    if let Some(text) = text {
+        return Ok(text);
+    }
+    let mut text = String::new();
+    io::stdin()
+        .read_to_string(&mut text)
+        .map_err(|e| format!("Failed to read stdin: {}", e))?;
+    Ok(text)
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(env::args().skip(1)).and_then(|options| {
+        let value = request(&options)?;
+        print(&options, value).map_err(|e| e.to_string())
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(unix)]
+fn request(options: &Options) -> Result<Value, String> {
+    use clipbridge_engine::control::{ControlClient, Response};
+
+    let mut client = ControlClient::connect(&options.socket).map_err(|e| {
+        format!(
+            "Cannot reach ClipBridge at {}: {}\nIs the app or clipbridged running?",
+            options.socket.display(),
+            e
+        )
+    })?;
+    match client
+        .request(&options.request)
+        .map_err(|e| e.to_string())?
+    {
+        Response::Ok(value) if matches!(options.request, Request::Subscribe { .. }) => {
+            print_events(options, &mut client).map_err(|e| e.to_string())?;
+            Ok(value)
+        }
+        Response::Ok(value) => Ok(value),
+        Response::Error(e) => Err(e),
+    }
+}
+
+#[cfg(not(unix))]
+fn request(_options: &Options) -> Result<Value, String> {
+    Err("The control socket is only available on Unix".to_string())
+}
+
+/// Print events until the instance goes away
+#[cfg(unix)]
+fn print_events(
+    options: &Options,
+    client: &mut clipbridge_engine::control::ControlClient,
+) -> io::Result<()> {
+    loop {
+        let event = match client.next_event() {
+            Ok(event) => event,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut out = io::stdout().lock();
+        if options.json {
+            writeln!(out, "{}", event)?;
+        } else {
+            writeln!(
+                out,
+                "{}\t{}",
+                event["event"].as_str().unwrap_or_default(),
+                event["payload"]
+            )?;
+        }
+        out.flush()?;
+    }
+}
+
+fn print(options: &Options, value: Value) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    if matches!(options.request, Request::Subscribe { .. }) {
+        // Events were printed as they came
+        return Ok(());
+    }
+    if options.json {
+        return writeln!(out, "{}", value);
+    }
+
+    match &options.request {
+        Request::Status => writeln!(
+            out,
+            "{}\t{}",
+            value["deviceId"].as_str().unwrap_or_default(),
+            if value["syncing"] == Value::Bool(true) {
+                "syncing"
+            } else {
+                "stopped"
+            }
+        ),
+        Request::Copy { .. }
+        | Request::Accept { .. }
+        | Request::Discard { .. }
+        | Request::StartSync
+        | Request::StopSync
+        | Request::Subscribe { .. }
+        | Request::Auth { .. } => Ok(()),
+        Request::Send { .. } => {
+            if value["held"] == Value::Bool(true) {
+                eprintln!("Held by the sensitive filter until approved in the app");
+            }
+            Ok(())
+        }
+        Request::Paste { .. } => write!(out, "{}", value.as_str().unwrap_or_default()),
+        Request::Devices => {
+            let devices: Vec<DeviceInfo> = serde_json::from_value(value)?;
+            for device in devices {
+                let path = serde_json::to_value(device.path)?;
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}",
+                    device.device_id,
+                    device.name.as_deref().unwrap_or("-"),
+                    path.as_str().unwrap_or_default(),
+                    if device.online { "online" } else { "offline" }
+                )?;
+            }
+            Ok(())
+        }
+        Request::Inbox => {
+            let items: Vec<InboxItem> = serde_json::from_value(value)?;
+            for item in items {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}",
+                    item.id,
+                    item.device_name.as_deref().unwrap_or(&item.origin_device),
+                    preview(&item.content, PREVIEW_LEN)
+                )?;
+            }
+            Ok(())
+        }
+        Request::History { .. } => {
+            let items: Vec<HistoryItem> = serde_json::from_value(value)?;
+            for item in items {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}",
+                    item.id,
+                    item.origin_device,
+                    preview(&item.content, PREVIEW_LEN)
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_global_and_command_flags() {
+        let options = parse(&[
+            "--socket",
+            "/tmp/cb.sock",
+            "send",
+            "--to",
+            "a",
+            "--to",
+            "b",
+            "hi",
+        ])
+        .unwrap();
+        assert_eq!(options.socket, PathBuf::from("/tmp/cb.sock"));
+        assert!(!options.json);
+        assert_eq!(
+            options.request,
+            Request::Send {
+                text: "hi".to_string(),
+                to: vec!["a".to_string(), "b".to_string()],
+            }
+        );
+
+        let options = parse(&["history", "--grep", "foo", "--limit", "5", "--json"]).unwrap();
+        assert!(options.json);
+        assert_eq!(
+            options.request,
+            Request::History {
+                grep: Some("foo".to_string()),
+                limit: Some(5),
+            }
+        );
+
+        let options = parse(&["events", "item-received", "inbox-changed"]).unwrap();
+        assert_eq!(
+            options.request,
+            Request::Subscribe {
+                events: vec!["item-received".to_string(), "inbox-changed".to_string()],
+            }
+        );
+        assert_eq!(
+            parse(&["accept"]).unwrap().request,
+            Request::Accept { id: None }
+        );
+    }
+
+    #[test]
+    fn test_double_dash_ends_options() {
+        let options = parse(&["copy", "--", "-x"]).unwrap();
+        assert_eq!(
+            options.request,
+            Request::Copy {
+                text: "-x".to_string()
+            }
+        );
+
+        let options = parse(&["send", "--to", "a", "--", "--json"]).unwrap();
+        assert!(!options.json);
+        assert_eq!(
+            options.request,
+            Request::Send {
+                text: "--json".to_string(),
+                to: vec!["a".to_string()],
+            }
+        );
+
+        let options = parse(&["discard", "--", "-1"]).unwrap();
+        assert_eq!(
+            options.request,
+            Request::Discard {
+                id: Some("-1".to_string())
+            }
+        );
+        assert!(parse(&["copy", "-x"]).is_err());
+    }
+
+    #[test]
+    fn test_value_errors() {
+        let missing = |args: &[&str]| parse(args).err().unwrap();
+        assert_eq!(missing(&["--socket"]), "--socket needs a value");
+        assert_eq!(missing(&["send", "--to"]), "--to needs a value");
+        assert_eq!(missing(&["paste", "--from"]), "--from needs a value");
+        assert!(missing(&["history", "--limit", "many"]).starts_with("Invalid --limit"));
+        assert!(missing(&["paste", "--to", "a"]).starts_with("Unknown argument --to"));
+        assert!(missing(&["copy", "a", "b"]).starts_with("Unknown argument b"));
+        assert!(missing(&["frobnicate"]).starts_with("Unknown command frobnicate"));
+        assert_eq!(missing(&[]), USAGE);
+    }
+}
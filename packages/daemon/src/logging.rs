@@ -0,0 +1,33 @@
+use log::Level;
+use std::env;
+use std::io::Write;
+
+/// Log to stderr at `info` unless `RUST_LOG` says otherwise. Under systemd
+/// the journal adds timestamps, so lines carry only an sd-daemon priority
+/// prefix, the target and the message.
+pub fn init() {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    if env::var_os("JOURNAL_STREAM").is_some() {
+        builder.format(|buf, record| {
+            writeln!(
+                buf,
+                "<{}>{}: {}",
+                priority(record.level()),
+                record.target(),
+                record.args()
+            )
+        });
+    }
+    builder.init();
+}
+
+/// syslog priority for a log level
+fn priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
@@ -0,0 +1,277 @@
+//! Headless ClipBridge daemon. Runs the same sync engine as the desktop app
+//! without a webview, so it pairs with desktop instances as a normal peer.
+
+mod logging;
+
+use clipbridge_engine::{control, Service};
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+const USAGE: &str = "Usage: clipbridged [--config FILE] [--data-dir DIR] [--socket PATH]";
+
+/// Directory name under the platform config and data directories
+const APP_DIR: &str = "clipbridged";
+
+struct Options {
+    config: PathBuf,
+    data_dir: PathBuf,
+    #[cfg_attr(not(unix), allow(dead_code))]
+    socket: PathBuf,
+}
+
+/// Looks up an environment variable, so tests need not change the real ones
+type EnvLookup<'a> = &'a dyn Fn(&str) -> Option<OsString>;
+
+/// Parse the arguments after the program name
+fn parse_args(args: impl IntoIterator<Item = String>, env: EnvLookup) -> Result<Options, String> {
+    let mut config = None;
+    let mut data_dir = None;
+    let mut socket = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--config" => config = Some(PathBuf::from(value()?)),
+            "--data-dir" => data_dir = Some(PathBuf::from(value()?)),
+            "--socket" => socket = Some(PathBuf::from(value()?)),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ => return Err(format!("Unknown argument {}\n{}", arg, USAGE)),
+        }
+    }
+
+    let config = match config {
+        Some(path) => path,
+        None => config_dir(env)?.join(APP_DIR).join("config.toml"),
+    };
+    let data_dir = match data_dir {
+        Some(path) => path,
+        None => data_dir_base(env)?.join(APP_DIR),
+    };
+    let socket = match socket {
+        Some(path) => path,
+        None => control::default_socket_path()
+            .map_err(|e| format!("Cannot use the default control socket: {}", e))?,
+    };
+    Ok(Options {
+        config,
+        data_dir,
+        socket,
+    })
+}
+
+#[cfg(unix)]
+fn config_dir(env: EnvLookup) -> Result<PathBuf, String> {
+    xdg_dir("XDG_CONFIG_HOME", ".config", env)
+}
+
+#[cfg(unix)]
+fn data_dir_base(env: EnvLookup) -> Result<PathBuf, String> {
+    xdg_dir("XDG_DATA_HOME", ".local/share", env)
+}
+
+#[cfg(unix)]
+fn xdg_dir(var: &str, fallback: &str, env: EnvLookup) -> Result<PathBuf, String> {
+    match env(var) {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        _ => env("HOME")
+            .map(|home| PathBuf::from(home).join(fallback))
+            .ok_or_else(|| format!("Neither {} nor HOME is set", var)),
+    }
+}
+
+#[cfg(windows)]
+fn config_dir(env: EnvLookup) -> Result<PathBuf, String> {
+    env("APPDATA")
+        .map(PathBuf::from)
+        .ok_or_else(|| "APPDATA is not set".to_string())
+}
+
+#[cfg(windows)]
+fn data_dir_base(env: EnvLookup) -> Result<PathBuf, String> {
+    env("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .ok_or_else(|| "LOCALAPPDATA is not set".to_string())
+}
+
+fn main() -> ExitCode {
+    logging::init();
+
+    let options = match parse_args(env::args().skip(1), &|var| env::var_os(var)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let service = match Service::open(options.config.clone(), &options.data_dir) {
+        Ok(service) => Arc::new(service),
+        Err(e) => {
+            log::error!("Failed to open {}: {}", options.data_dir.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    log::info!(
+        "Device {} using {}",
+        service.device_id,
+        options.config.display()
+    );
+
+    service.watch_config();
+    #[cfg(unix)]
+    let _control = match control::ControlServer::start(options.socket, Arc::downgrade(&service)) {
+        Ok(server) => Some(server),
+        Err(e) => {
+            log::warn!("Control socket unavailable: {}", e);
+            None
+        }
+    };
+    if let Err(e) = service.start_sync() {
+        log::error!("Failed to start sync: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    let result = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .and_then(|runtime| runtime.block_on(wait_for_shutdown(&service)));
+
+    service.stop_sync();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run until SIGTERM or SIGINT. SIGHUP reloads the config file and restarts
+/// sync if it had stopped.
+#[cfg(unix)]
+async fn wait_for_shutdown(service: &Service) -> io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    loop {
+        tokio::select! {
+            _ = terminate.recv() => break,
+            _ = interrupt.recv() => break,
+            _ = hangup.recv() => reload(service),
+        }
+    }
+    log::info!("Shutting down");
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn wait_for_shutdown(_service: &Service) -> io::Result<()> {
+    tokio::signal::ctrl_c().await?;
+    log::info!("Shutting down");
+    Ok(())
+}
+
+#[cfg(unix)]
+fn reload(service: &Service) {
+    log::info!("Reloading config");
+    let result = service.config.lock().reload_now();
+    if let Err(e) = result {
+        log::warn!("Keeping the current config: {}", e);
+    }
+
+    if !*service.is_syncing.lock() {
+        if let Err(e) = service.start_sync() {
+            log::error!("Failed to start sync: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn parse(args: &[&str], vars: &[(&str, &str)]) -> Result<Options, String> {
+        let vars: HashMap<String, OsString> = vars
+            .iter()
+            .map(|(var, value)| (var.to_string(), OsString::from(value)))
+            .collect();
+        parse_args(args.iter().map(|arg| arg.to_string()), &|var| {
+            vars.get(var).cloned()
+        })
+    }
+
+    #[test]
+    fn test_explicit_paths() {
+        let options = parse(
+            &[
+                "--config",
+                "/etc/cb.toml",
+                "--data-dir",
+                "/var/lib/cb",
+                "--socket",
+                "/run/cb.sock",
+            ],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(options.config, PathBuf::from("/etc/cb.toml"));
+        assert_eq!(options.data_dir, PathBuf::from("/var/lib/cb"));
+        assert_eq!(options.socket, PathBuf::from("/run/cb.sock"));
+    }
+
+    #[test]
+    fn test_argument_errors() {
+        let error = |args: &[&str]| parse(args, &[("HOME", "/home/me")]).err().unwrap();
+        assert_eq!(error(&["--config"]), "--config needs a value");
+        assert_eq!(error(&["--data-dir"]), "--data-dir needs a value");
+        assert_eq!(error(&["--help"]), USAGE);
+        assert!(error(&["--verbose"]).starts_with("Unknown argument --verbose"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_xdg_directories() {
+        let options = parse(
+            &[],
+            &[
+                ("HOME", "/home/me"),
+                ("XDG_CONFIG_HOME", "/xdg/config"),
+                ("XDG_DATA_HOME", "/xdg/data"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            options.config,
+            PathBuf::from("/xdg/config/clipbridged/config.toml")
+        );
+        assert_eq!(options.data_dir, PathBuf::from("/xdg/data/clipbridged"));
+
+        // Empty XDG variables fall back to HOME
+        let options = parse(&[], &[("HOME", "/home/me"), ("XDG_CONFIG_HOME", "")]).unwrap();
+        assert_eq!(
+            options.config,
+            PathBuf::from("/home/me/.config/clipbridged/config.toml")
+        );
+        assert_eq!(
+            options.data_dir,
+            PathBuf::from("/home/me/.local/share/clipbridged")
+        );
+
+        assert_eq!(
+            parse(&[], &[]).err().unwrap(),
+            "Neither XDG_CONFIG_HOME nor HOME is set"
+        );
+        // An explicit path needs no environment
+        let options = parse(&["--config", "c.toml", "--data-dir", "d"], &[]).unwrap();
+        assert_eq!(options.config, PathBuf::from("c.toml"));
+    }
+}
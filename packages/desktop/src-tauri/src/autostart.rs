@@ -0,0 +1,59 @@
+//! Starting the app at login, following `general.autoStart`. The login
+//! entry is a `~/.config/autostart` desktop file on Linux and a `Run`
+//! registry value on Windows.
+
+use clipbridge_engine::events::Event;
+use clipbridge_engine::Service;
+use parking_lot::Mutex;
+use std::env;
+use std::sync::Arc;
+use tauri::plugin::TauriPlugin;
+use tauri::{AppHandle, Wry};
+use tauri_plugin_autostart::ManagerExt;
+
+/// Passed by the login entry: stay in the tray and resume sync
+const MINIMIZED_ARG: &str = "--minimized";
+
+pub fn plugin() -> TauriPlugin<Wry> {
+    tauri_plugin_autostart::Builder::new()
+        .arg(MINIMIZED_ARG)
+        .build()
+}
+
+/// Whether this instance was started by the login entry
+pub fn launched_at_login() -> bool {
+    env::args().any(|arg| arg == MINIMIZED_ARG)
+}
+
+/// Add or remove the login entry now, and again whenever
+/// `general.autoStart` changes
+pub fn watch(app: &AppHandle, service: &Arc<Service>) {
+    let current = service.config.lock().get().general.auto_start;
+    apply(app, current);
+
+    let app = app.clone();
+    let last = Mutex::new(current);
+    service.events.subscribe(move |event| {
+        if let Event::ConfigChanged(config) = event {
+            let mut last = last.lock();
+            if *last != config.general.auto_start {
+                *last = config.general.auto_start;
+                apply(&app, *last);
+            }
+        }
+    });
+}
+
+fn apply(app: &AppHandle, enabled: bool) {
+    let manager = app.autolaunch();
+    let result = match manager.is_enabled() {
+        Ok(registered) if registered == enabled => Ok(()),
+        Ok(_) if enabled => manager.enable(),
+        Ok(_) => manager.disable(),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => log::debug!("Autostart {}", if enabled { "on" } else { "off" }),
+        Err(e) => log::warn!("Failed to update autostart: {}", e),
+    }
+}
@@ -0,0 +1,132 @@
+//! Global shortcuts configured in `[hotkeys]`
+
+use crate::tray;
+use clipbridge_engine::config::HotkeyConfig;
+use clipbridge_engine::events::Event;
+use clipbridge_engine::Service;
+use parking_lot::Mutex;
+use std::sync::mpsc;
+use std::sync::{Arc, Weak};
+use std::thread;
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+
+/// Tells the window to show the history quick-picker
+const HISTORY_PICKER_EVENT: &str = "open-history-picker";
+
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Push,
+    Pull,
+    ToggleSync,
+    HistoryPicker,
+}
+
+/// Register the configured shortcuts, and again whenever `[hotkeys]` changes
+pub fn watch(app: &AppHandle, service: &Arc<Service>) {
+    let current = service.config.lock().get().hotkeys.clone();
+    register(app, service, &current);
+
+    // The config store is locked while listeners run; register elsewhere
+    let (changed, changes) = mpsc::channel::<HotkeyConfig>();
+    let last = Mutex::new(current);
+    service.events.subscribe(move |event| {
+        if let Event::ConfigChanged(config) = event {
+            let mut last = last.lock();
+            if *last != config.hotkeys {
+                *last = config.hotkeys.clone();
+                let _ = changed.send(config.hotkeys.clone());
+            }
+        }
+    });
+
+    let app = app.clone();
+    let service = Arc::downgrade(service);
+    thread::spawn(move || {
+        while let Ok(mut hotkeys) = changes.recv() {
+            while let Ok(newer) = changes.try_recv() {
+                hotkeys = newer;
+            }
+            let Some(service) = service.upgrade() else {
+                break;
+            };
+            register(&app, &service, &hotkeys);
+        }
+    });
+}
+
+fn register(app: &AppHandle, service: &Arc<Service>, hotkeys: &HotkeyConfig) {
+    let shortcuts = app.global_shortcut();
+    if let Err(e) = shortcuts.unregister_all() {
+        log::warn!("Failed to unregister hotkeys: {}", e);
+    }
+    if !hotkeys.enabled {
+        return;
+    }
+
+    for (action, shortcut) in [
+        (Action::Push, &hotkeys.push),
+        (Action::Pull, &hotkeys.pull),
+        (Action::ToggleSync, &hotkeys.toggle_sync),
+        (Action::HistoryPicker, &hotkeys.history_picker),
+    ] {
+        if shortcut.is_empty() {
+            continue;
+        }
+        let service = Arc::downgrade(service);
+        let registered = shortcuts.on_shortcut(shortcut.as_str(), move |app, _, event| {
+            if event.state == ShortcutState::Pressed {
+                run(app, &service, action);
+            }
+        });
+        if let Err(e) = registered {
+            log::warn!("Cannot use {:?} for {:?}: {}", shortcut, action, e);
+        }
+    }
+}
+
+fn run(app: &AppHandle, service: &Weak<Service>, action: Action) {
+    let Some(service) = service.upgrade() else {
+        return;
+    };
+    log::debug!("Hotkey {:?}", action);
+
+    match action {
+        Action::Push => {
+            thread::spawn(move || {
+                let device = service.config.lock().get().hotkeys.device.clone();
+                tray::send_clipboard(&service, device);
+            });
+        }
+        Action::Pull => {
+            thread::spawn(move || pull(&service));
+        }
+        Action::ToggleSync => tray::toggle_sync(service),
+        Action::HistoryPicker => {
+            tray::show_main_window(app);
+            if let Err(e) = app.emit(HISTORY_PICKER_EVENT, ()) {
+                log::error!("Failed to open the history picker: {}", e);
+            }
+        }
+    }
+}
+
+/// Copy the latest item from the configured device without sending it back,
+/// accepting it if it is waiting in the inbox
+fn pull(service: &Service) {
+    let device = service.config.lock().get().hotkeys.device.clone();
+    match service.latest_item_from(device.as_deref()) {
+        Ok(Some(item)) => {
+            let applied = match service.resolve_inbox(&item.id, true) {
+                Ok(true) => Ok(()),
+                Ok(false) => service.apply_text(&item.content),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = applied {
+                log::error!("Failed to copy the pulled item: {}", e);
+            }
+        }
+        Ok(None) => log::info!("Nothing to pull"),
+        Err(e) => log::error!("Failed to pull: {}", e),
+    }
+}
@@ -1,87 +1,622 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod clipboard;
-mod network;
+mod autostart;
+mod hotkeys;
+mod notifications;
+mod tray;
 
-use clipboard::ClipboardMonitor;
-use parking_lot::Mutex;
+use clipbridge_engine::config::AppConfig;
+#[cfg(unix)]
+use clipbridge_engine::control::{default_socket_path, ControlServer};
+use clipbridge_engine::filter::AuditEntry;
+use clipbridge_engine::groups::SyncGroup;
+use clipbridge_engine::history::{HistoryItem, SearchQuery, SearchResult};
+use clipbridge_engine::network::{PeerLink, RelayDevice};
+use clipbridge_engine::peers::{ConfirmPolicy, PeerSettings, SyncDirection};
+use clipbridge_engine::sync::{HeldItem, InboxItem};
+use clipbridge_engine::{clipboard, Service};
 use std::sync::Arc;
+use std::thread;
+use tauri::{Emitter, Manager, WindowEvent};
 
-#[allow(dead_code)]
-#[derive(Clone, serde::Serialize)]
-struct ClipboardUpdate {
-    content: String,
-    timestamp: u64,
+type AppState = Arc<Service>;
+
+#[tauri::command]
+async fn start_sync(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    state.start_sync()
 }
 
-struct AppState {
-    #[allow(dead_code)]
-    clipboard_monitor: Arc<Mutex<Option<ClipboardMonitor>>>,
-    is_syncing: Arc<Mutex<bool>>,
+#[tauri::command]
+async fn stop_sync(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    state.stop_sync();
+    Ok(())
 }
 
 #[tauri::command]
-async fn start_sync(state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let mut is_syncing = state.is_syncing.lock();
-    if *is_syncing {
-        return Ok(());
+async fn get_clipboard_text() -> Result<String, String> {
+    clipboard::get_text().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_clipboard_text(text: String) -> Result<(), String> {
+    clipboard::set_text(&text).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn is_syncing(state: tauri::State<'_, AppState>) -> Result<bool, String> {
+    Ok(*state.is_syncing.lock())
+}
+
+#[tauri::command]
+async fn get_config(state: tauri::State<'_, AppState>) -> Result<AppConfig, String> {
+    Ok(state.config.lock().get().redacted())
+}
+
+/// Validate and save a new config. Changes apply immediately, including to
+/// a running sync engine. The UI never sees the relay account secret, so
+/// the current one is kept unless a new one is given to join an account.
+#[tauri::command]
+async fn update_config(
+    state: tauri::State<'_, AppState>,
+    mut config: AppConfig,
+) -> Result<AppConfig, String> {
+    let mut store = state.config.lock();
+    if config.network.relay_account.is_none() {
+        config.network.relay_account = store.get().network.relay_account.clone();
     }
+    store.update(config.clone()).map_err(|e| e.to_string())?;
+    Ok(config.redacted())
+}
+
+#[tauri::command]
+async fn list_history(
+    state: tauri::State<'_, AppState>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Result<Vec<HistoryItem>, String> {
+    state
+        .history
+        .lock()
+        .list(limit.unwrap_or(100), offset.unwrap_or(0))
+        .map_err(|e| e.to_string())
+}
 
-    log::info!("Starting clipboard sync");
-    *is_syncing = true;
+#[tauri::command]
+async fn search_history(
+    state: tauri::State<'_, AppState>,
+    query: SearchQuery,
+) -> Result<Vec<SearchResult>, String> {
+    state
+        .history
+        .lock()
+        .search(&query)
+        .map_err(|e| e.to_string())
+}
 
-    // TODO: Start network layer and clipboard monitoring
+#[tauri::command]
+async fn get_history_item(
+    state: tauri::State<'_, AppState>,
+    id: String,
+) -> Result<Option<HistoryItem>, String> {
+    state.history.lock().get(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_history_item(
+    state: tauri::State<'_, AppState>,
+    id: String,
+) -> Result<bool, String> {
+    state.history.lock().delete(&id).map_err(|e| e.to_string())
+}
 
+#[tauri::command]
+async fn clear_history(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    state
+        .history
+        .lock()
+        .clear()
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Erase all history, including pinned items, and vacuum the database
+#[tauri::command]
+async fn wipe_history(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    state.history.lock().wipe().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn list_snippets(state: tauri::State<'_, AppState>) -> Result<Vec<HistoryItem>, String> {
+    state
+        .history
+        .lock()
+        .list_pinned()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn pin_history_item(
+    state: tauri::State<'_, AppState>,
+    id: String,
+    name: Option<String>,
+) -> Result<bool, String> {
+    let pinned = state
+        .history
+        .lock()
+        .pin(&id, name)
+        .map_err(|e| e.to_string())?;
+    share_if_shared(&state, &id)?;
+    Ok(pinned)
+}
+
+#[tauri::command]
+async fn unpin_history_item(state: tauri::State<'_, AppState>, id: String) -> Result<bool, String> {
+    let was_shared = state
+        .history
+        .lock()
+        .get(&id)
+        .map_err(|e| e.to_string())?
+        .is_some_and(|item| item.shared);
+    let unpinned = state.history.lock().unpin(&id).map_err(|e| e.to_string())?;
+
+    if was_shared {
+        if let Some(engine) = state.sync_engine.lock().as_ref() {
+            engine.unshare_snippet(&id)?;
+        }
+    }
+    Ok(unpinned)
+}
+
+#[tauri::command]
+async fn rename_snippet(
+    state: tauri::State<'_, AppState>,
+    id: String,
+    name: Option<String>,
+) -> Result<bool, String> {
+    let renamed = state
+        .history
+        .lock()
+        .rename(&id, name)
+        .map_err(|e| e.to_string())?;
+    share_if_shared(&state, &id)?;
+    Ok(renamed)
+}
+
+#[tauri::command]
+async fn reorder_snippets(
+    state: tauri::State<'_, AppState>,
+    ids: Vec<String>,
+) -> Result<(), String> {
+    state
+        .history
+        .lock()
+        .reorder_pinned(&ids)
+        .map_err(|e| e.to_string())?;
+    for id in &ids {
+        share_if_shared(&state, id)?;
+    }
     Ok(())
 }
 
 #[tauri::command]
-async fn stop_sync(state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let mut is_syncing = state.is_syncing.lock();
-    if !*is_syncing {
-        return Ok(());
+async fn set_snippet_shared(
+    state: tauri::State<'_, AppState>,
+    id: String,
+    shared: bool,
+) -> Result<bool, String> {
+    let updated = state
+        .history
+        .lock()
+        .set_shared(&id, shared)
+        .map_err(|e| e.to_string())?;
+
+    if updated {
+        if shared {
+            share_if_shared(&state, &id)?;
+        } else if let Some(engine) = state.sync_engine.lock().as_ref() {
+            engine.unshare_snippet(&id)?;
+        }
     }
+    Ok(updated)
+}
 
-    log::info!("Stopping clipboard sync");
-    *is_syncing = false;
+/// Items the sensitive filter is holding for the user to allow or reject
+#[tauri::command]
+async fn list_held_items(state: tauri::State<'_, AppState>) -> Result<Vec<HeldItem>, String> {
+    Ok(state
+        .sync_engine
+        .lock()
+        .as_ref()
+        .map(|engine| engine.held_items())
+        .unwrap_or_default())
+}
 
-    // TODO: Stop monitoring
+#[tauri::command]
+async fn resolve_held_item(
+    state: tauri::State<'_, AppState>,
+    id: String,
+    allow: bool,
+) -> Result<bool, String> {
+    match state.sync_engine.lock().as_ref() {
+        Some(engine) => Ok(engine.resolve_held(&id, allow)),
+        None => Err("Sync is not running".to_string()),
+    }
+}
 
-    Ok(())
+/// Send `text` to every device, or to the devices named in `to`. Returns
+/// false if the sensitive filter held it.
+#[tauri::command]
+async fn send_text(
+    state: tauri::State<'_, AppState>,
+    text: String,
+    to: Vec<String>,
+) -> Result<bool, String> {
+    state.send_text(text, &to)
 }
 
+/// Items from peers waiting to be applied or discarded
 #[tauri::command]
-async fn get_clipboard_text() -> Result<String, String> {
-    clipboard::get_text().map_err(|e| e.to_string())
+async fn list_inbox(state: tauri::State<'_, AppState>) -> Result<Vec<InboxItem>, String> {
+    Ok(state.inbox())
 }
 
 #[tauri::command]
-async fn set_clipboard_text(text: String) -> Result<(), String> {
-    clipboard::set_text(&text).map_err(|e| e.to_string())
+async fn resolve_inbox_item(
+    state: tauri::State<'_, AppState>,
+    id: String,
+    accept: bool,
+) -> Result<bool, String> {
+    state.resolve_inbox(&id, accept)
 }
 
 #[tauri::command]
-async fn is_syncing(state: tauri::State<'_, AppState>) -> Result<bool, String> {
-    Ok(*state.is_syncing.lock())
+async fn list_audit_log(
+    state: tauri::State<'_, AppState>,
+    limit: usize,
+) -> Result<Vec<AuditEntry>, String> {
+    Ok(state.audit.lock().recent(limit))
 }
 
-fn main() {
-    env_logger::init();
+#[tauri::command]
+async fn list_peers(state: tauri::State<'_, AppState>) -> Result<Vec<PeerSettings>, String> {
+    Ok(state.peers.lock().list())
+}
+
+#[tauri::command]
+async fn set_peer_history_access(
+    state: tauri::State<'_, AppState>,
+    device_id: String,
+    allowed: bool,
+) -> Result<PeerSettings, String> {
+    state
+        .peers
+        .lock()
+        .update(&device_id, |peer| peer.allow_history = allowed)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn list_groups(state: tauri::State<'_, AppState>) -> Result<Vec<SyncGroup>, String> {
+    Ok(state
+        .groups
+        .lock()
+        .list()
+        .iter()
+        .map(SyncGroup::public)
+        .collect())
+}
+
+/// Create a sync group with this device as its first member
+#[tauri::command]
+async fn create_group(
+    state: tauri::State<'_, AppState>,
+    name: String,
+) -> Result<SyncGroup, String> {
+    let group = state
+        .groups
+        .lock()
+        .create(name, &state.device_id)
+        .map_err(|e| e.to_string())?;
+    announce_groups(&state);
+    Ok(group.public())
+}
+
+/// An invite string for another device to join the group. It contains the
+/// group key, so it should only be shared with trusted devices.
+#[tauri::command]
+async fn get_group_invite(
+    state: tauri::State<'_, AppState>,
+    group_id: String,
+) -> Result<String, String> {
+    state
+        .groups
+        .lock()
+        .invite(&group_id)
+        .ok_or_else(|| format!("Unknown group {}", group_id))
+}
+
+#[tauri::command]
+async fn join_group(
+    state: tauri::State<'_, AppState>,
+    invite: String,
+) -> Result<SyncGroup, String> {
+    let group = state
+        .groups
+        .lock()
+        .join(&invite, &state.device_id)
+        .map_err(|e| e.to_string())?;
+    announce_groups(&state);
+    Ok(group.public())
+}
+
+#[tauri::command]
+async fn leave_group(state: tauri::State<'_, AppState>, group_id: String) -> Result<(), String> {
+    state
+        .groups
+        .lock()
+        .leave(&group_id)
+        .map_err(|e| e.to_string())
+}
+
+/// Choose the group locally copied items are shared in, or `None` to share
+/// them with all peers
+#[tauri::command]
+async fn set_active_group(
+    state: tauri::State<'_, AppState>,
+    group_id: Option<String>,
+) -> Result<(), String> {
+    state
+        .groups
+        .lock()
+        .set_active(group_id)
+        .map_err(|e| e.to_string())
+}
 
-    let app_state = AppState {
-        clipboard_monitor: Arc::new(Mutex::new(None)),
-        is_syncing: Arc::new(Mutex::new(false)),
+fn announce_groups(state: &AppState) {
+    if let Some(engine) = state.sync_engine.lock().as_ref() {
+        engine.announce_groups();
+    }
+}
+
+/// Set which way clipboard items flow with a peer, and for which MIME types
+/// (empty for all)
+#[tauri::command]
+async fn set_peer_sync_policy(
+    state: tauri::State<'_, AppState>,
+    device_id: String,
+    direction: SyncDirection,
+    mime_types: Vec<String>,
+) -> Result<PeerSettings, String> {
+    state
+        .peers
+        .lock()
+        .update(&device_id, |peer| {
+            peer.direction = direction;
+            peer.mime_types = mime_types;
+        })
+        .map_err(|e| e.to_string())
+}
+
+/// Whether items from a peer wait in the inbox: `default` follows
+/// `security.confirmIncoming`, `trusted` applies them right away
+#[tauri::command]
+async fn set_peer_confirm_policy(
+    state: tauri::State<'_, AppState>,
+    device_id: String,
+    confirm: ConfirmPolicy,
+) -> Result<PeerSettings, String> {
+    state
+        .peers
+        .lock()
+        .update(&device_id, |peer| peer.confirm = confirm)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn request_history(
+    state: tauri::State<'_, AppState>,
+    device_id: String,
+    since: Option<u64>,
+) -> Result<(), String> {
+    match state.sync_engine.lock().as_ref() {
+        Some(engine) => engine.request_history(&device_id, since.unwrap_or(0)),
+        None => Err("Sync is not running".to_string()),
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RelayIdentity {
+    device_id: String,
+    public_key: String,
+    /// Shared with other devices to join this relay account
+    account_secret: String,
+}
+
+/// What another device needs to add this one to its relay account, and
+/// the secret of the account this device uses
+#[tauri::command]
+async fn get_relay_identity(state: tauri::State<'_, AppState>) -> Result<RelayIdentity, String> {
+    let key = state.identity.device_key().map_err(|e| e.to_string())?;
+    let account_secret = match state.config.lock().get().network.relay_account.clone() {
+        Some(secret) => secret,
+        None => state
+            .identity
+            .relay_account_secret()
+            .map_err(|e| e.to_string())?,
     };
 
+    Ok(RelayIdentity {
+        device_id: state.device_id.clone(),
+        public_key: hex::encode(key.verifying_key().as_bytes()),
+        account_secret,
+    })
+}
+
+#[tauri::command]
+async fn list_relay_devices(state: tauri::State<'_, AppState>) -> Result<Vec<RelayDevice>, String> {
+    Ok(state
+        .sync_engine
+        .lock()
+        .as_ref()
+        .map(|engine| engine.relay_devices())
+        .unwrap_or_default())
+}
+
+/// Whether each reachable peer is connected directly or through the relay
+#[tauri::command]
+async fn list_peer_links(state: tauri::State<'_, AppState>) -> Result<Vec<PeerLink>, String> {
+    Ok(state
+        .sync_engine
+        .lock()
+        .as_ref()
+        .map(|engine| engine.peer_links())
+        .unwrap_or_default())
+}
+
+/// Allow another device to sign in to this device's relay account
+#[tauri::command]
+async fn add_relay_device(
+    state: tauri::State<'_, AppState>,
+    device_id: String,
+    public_key: String,
+) -> Result<(), String> {
+    match state.sync_engine.lock().as_ref() {
+        Some(engine) => engine.add_relay_device(&device_id, &public_key),
+        None => Err("Sync is not running".to_string()),
+    }
+}
+
+#[tauri::command]
+async fn remove_relay_device(
+    state: tauri::State<'_, AppState>,
+    device_id: String,
+) -> Result<(), String> {
+    match state.sync_engine.lock().as_ref() {
+        Some(engine) => engine.remove_relay_device(&device_id),
+        None => Err("Sync is not running".to_string()),
+    }
+}
+
+/// Push the current version of a snippet to peers if it is shared and
+/// sync is running
+fn share_if_shared(state: &AppState, id: &str) -> Result<(), String> {
+    let item = state.history.lock().get(id).map_err(|e| e.to_string())?;
+    if let (Some(item), Some(engine)) = (item, state.sync_engine.lock().as_ref()) {
+        if item.shared {
+            engine.share_snippet(&item)?;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    env_logger::init();
+
     tauri::Builder::default()
-        .manage(app_state)
+        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(autostart::plugin())
+        .setup(|app| {
+            let service = Arc::new(Service::open(
+                app.path().app_config_dir()?.join("config.toml"),
+                &app.path().app_data_dir()?,
+            )?);
+
+            let handle = app.handle().clone();
+            service.events.subscribe(move |event| {
+                let payload = match serde_json::to_value(event) {
+                    Ok(mut value) => value["payload"].take(),
+                    Err(e) => {
+                        log::error!("Failed to serialize {}: {}", event.name(), e);
+                        return;
+                    }
+                };
+                if let Err(e) = handle.emit(event.name(), payload) {
+                    log::error!("Failed to emit {}: {}", event.name(), e);
+                }
+            });
+            service.watch_config();
+            tray::create(app.handle(), &service)?;
+            hotkeys::watch(app.handle(), &service);
+            notifications::watch(app.handle(), &service);
+            autostart::watch(app.handle(), &service);
+
+            #[cfg(unix)]
+            match default_socket_path()
+                .and_then(|path| ControlServer::start(path, Arc::downgrade(&service)))
+            {
+                Ok(control) => {
+                    app.manage(control);
+                }
+                Err(e) => log::warn!("Control socket unavailable: {}", e),
+            }
+
+            // Started at login: stay in the tray and resume syncing
+            if autostart::launched_at_login() {
+                let service = Arc::clone(&service);
+                thread::spawn(move || {
+                    if let Err(e) = service.start_sync() {
+                        log::error!("Failed to start sync: {}", e);
+                    }
+                });
+            } else {
+                tray::show_main_window(app.handle());
+            }
+
+            app.manage(service);
+            Ok(())
+        })
+        // Closing the window leaves the app running in the tray
+        .on_window_event(|window, event| {
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                api.prevent_close();
+                let _ = window.hide();
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             start_sync,
             stop_sync,
             get_clipboard_text,
             set_clipboard_text,
-            is_syncing
+            is_syncing,
+            get_config,
+            update_config,
+            list_history,
+            search_history,
+            get_history_item,
+            delete_history_item,
+            clear_history,
+            wipe_history,
+            list_snippets,
+            pin_history_item,
+            unpin_history_item,
+            rename_snippet,
+            reorder_snippets,
+            set_snippet_shared,
+            list_held_items,
+            resolve_held_item,
+            send_text,
+            list_inbox,
+            resolve_inbox_item,
+            list_audit_log,
+            list_peers,
+            set_peer_history_access,
+            set_peer_sync_policy,
+            set_peer_confirm_policy,
+            list_groups,
+            create_group,
+            get_group_invite,
+            join_group,
+            leave_group,
+            set_active_group,
+            request_history,
+            get_relay_identity,
+            list_relay_devices,
+            add_relay_device,
+            remove_relay_device,
+            list_peer_links
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,197 @@
+//! Native notifications for received items, pairing requests, disconnects
+//! and the sensitive filter, shown while `general.showNotifications` is on
+
+use clipbridge_engine::events::{preview, ChangeSource, Event};
+use clipbridge_engine::Service;
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+
+/// Characters of an item shown in a notification
+const BODY_PREVIEW_LEN: usize = 100;
+/// At most this many notifications are shown per `WINDOW`
+const BURST: usize = 3;
+const WINDOW: Duration = Duration::from_secs(10);
+/// Inbox items remembered so applying one later is not announced again
+const REMEMBERED_ITEMS: usize = 50;
+
+/// Limits how many notifications are shown in a row, counting the rest
+#[derive(Default)]
+struct RateLimit {
+    shown: VecDeque<Instant>,
+    skipped: usize,
+}
+
+impl RateLimit {
+    /// Whether one more may be shown now
+    fn allow(&mut self, now: Instant) -> bool {
+        while self
+            .shown
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= WINDOW)
+        {
+            self.shown.pop_front();
+        }
+        if self.shown.len() >= BURST {
+            self.skipped += 1;
+            return false;
+        }
+        self.shown.push_back(now);
+        true
+    }
+
+    /// `body` with a note about the notifications skipped since the last
+    /// one shown
+    fn with_skipped(&mut self, body: String) -> String {
+        match std::mem::take(&mut self.skipped) {
+            0 => body,
+            skipped => format!("{}\n（ほか {} 件の通知を省略しました）", body, skipped),
+        }
+    }
+}
+
+/// Show notifications for events from `service` for as long as it runs
+pub fn watch(app: &AppHandle, service: &Arc<Service>) {
+    // Showing one can block on the desktop's notification service
+    let (notify, events) = mpsc::channel::<Event>();
+    service.events.subscribe(move |event| {
+        if matches!(
+            event,
+            Event::ClipboardChanged {
+                source: ChangeSource::Remote,
+                ..
+            } | Event::ItemReceived(_)
+                | Event::PairingRequested { .. }
+                | Event::DeviceDisconnected(_)
+                | Event::SensitiveItemHeld(_)
+                | Event::SensitiveItemBlocked { .. }
+        ) {
+            let _ = notify.send(event.clone());
+        }
+    });
+
+    let app = app.clone();
+    let service = Arc::downgrade(service);
+    thread::spawn(move || {
+        let mut limit = RateLimit::default();
+        let mut staged: VecDeque<String> = VecDeque::new();
+
+        while let Ok(event) = events.recv() {
+            let Some(service) = service.upgrade() else {
+                break;
+            };
+            if !service.config.lock().get().general.show_notifications {
+                continue;
+            }
+
+            let (title, body) = match event {
+                Event::ClipboardChanged {
+                    id,
+                    origin_device,
+                    device_name,
+                    preview: text,
+                    ..
+                } => {
+                    // Already announced when it was put in the inbox
+                    if staged.contains(&id) {
+                        continue;
+                    }
+                    let device = device_name.unwrap_or(origin_device);
+                    (format!("{} から受信", device), text)
+                }
+                Event::ItemReceived(item) => {
+                    staged.push_back(item.id.clone());
+                    if staged.len() > REMEMBERED_ITEMS {
+                        staged.pop_front();
+                    }
+                    let action = if item.apply_at.is_some() {
+                        "破棄しなければ自動で反映されます"
+                    } else {
+                        "トレイまたはウィンドウから反映できます"
+                    };
+                    let device = item.device_name.unwrap_or(item.origin_device);
+                    (
+                        format!("{} から受信", device),
+                        format!("{}\n{}", preview(&item.content, BODY_PREVIEW_LEN), action),
+                    )
+                }
+                Event::PairingRequested { device_id } => (
+                    "新しいデバイス".to_string(),
+                    format!("{} が接続しました。設定からペアリングできます", device_id),
+                ),
+                Event::DeviceDisconnected(device) => {
+                    // Stopping sync disconnects every device at once
+                    if !*service.is_syncing.lock() {
+                        continue;
+                    }
+                    (
+                        "デバイスが切断されました".to_string(),
+                        device.name.unwrap_or(device.device_id),
+                    )
+                }
+                Event::SensitiveItemHeld(item) => (
+                    "機密情報の同期を保留しました".to_string(),
+                    format!(
+                        "{}\nアプリで許可または拒否してください",
+                        item.rules.join(", ")
+                    ),
+                ),
+                Event::SensitiveItemBlocked { rules } => (
+                    "機密情報の同期をブロックしました".to_string(),
+                    rules.join(", "),
+                ),
+                _ => continue,
+            };
+
+            if !limit.allow(Instant::now()) {
+                log::debug!("Notification skipped: {}", title);
+                continue;
+            }
+            let body = limit.with_skipped(body);
+            show(&app, title, body);
+        }
+    });
+}
+
+fn show(app: &AppHandle, title: String, body: String) {
+    let shown = app.notification().builder().title(title).body(body).show();
+    if let Err(e) = shown {
+        log::warn!("Failed to show a notification: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_skips_after_a_burst_until_the_window_passes() {
+        let mut limit = RateLimit::default();
+        let start = Instant::now();
+
+        for i in 0..BURST {
+            assert!(limit.allow(start + Duration::from_secs(i as u64)));
+        }
+        assert!(!limit.allow(start + Duration::from_secs(5)));
+        assert!(!limit.allow(start + Duration::from_secs(9)));
+
+        // The first notification leaves the window after `WINDOW`
+        assert!(limit.allow(start + WINDOW));
+        assert_eq!(
+            limit.with_skipped("body".to_string()),
+            "body\n（ほか 2 件の通知を省略しました）"
+        );
+        assert_eq!(limit.with_skipped("body".to_string()), "body");
+
+        // Once the whole window has passed a full burst is allowed again
+        let later = start + WINDOW * 3;
+        for _ in 0..BURST {
+            assert!(limit.allow(later));
+        }
+        assert!(!limit.allow(later));
+    }
+}
@@ -0,0 +1,390 @@
+//! The system tray icon and its menu. The menu is rebuilt whenever sync
+//! state, devices or history change.
+
+use clipbridge_engine::control::DeviceInfo;
+use clipbridge_engine::events::{preview, Event};
+use clipbridge_engine::history::HistoryItem;
+use clipbridge_engine::network::LinkPath;
+use clipbridge_engine::sync::InboxItem;
+use clipbridge_engine::{clipboard, Service};
+use std::collections::HashSet;
+use std::sync::mpsc;
+use std::sync::{Arc, Weak};
+use std::thread;
+use tauri::image::Image;
+use tauri::menu::{IsMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Manager};
+
+const TRAY_ID: &str = "main";
+/// History items offered for re-copying
+const RECENT_ITEMS: usize = 5;
+/// Characters of a history item shown in the menu
+const ITEM_LABEL_LEN: usize = 40;
+
+const OPEN_ID: &str = "open";
+const TOGGLE_SYNC_ID: &str = "toggle-sync";
+const QUIT_ID: &str = "quit";
+/// Prefix of "send current clipboard to" items, followed by a device id
+const SEND_PREFIX: &str = "send:";
+/// Prefix of history items, followed by the item id
+const COPY_PREFIX: &str = "copy:";
+/// Prefixes of inbox item actions, followed by the item id
+const ACCEPT_PREFIX: &str = "accept:";
+const DISCARD_PREFIX: &str = "discard:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SyncState {
+    Syncing,
+    Paused,
+    Error,
+}
+
+/// What the menu shows, read from the service before building it
+struct Snapshot {
+    state: SyncState,
+    error: Option<String>,
+    devices: Vec<DeviceInfo>,
+    inbox: Vec<InboxItem>,
+    recent: Vec<HistoryItem>,
+}
+
+/// Add the tray icon and keep it up to date for as long as the app runs
+pub fn create(app: &AppHandle, service: &Arc<Service>) -> tauri::Result<()> {
+    let snapshot = Snapshot::read(service);
+    let service_ref = Arc::downgrade(service);
+    TrayIconBuilder::with_id(TRAY_ID)
+        .icon(icon(app, snapshot.state))
+        .tooltip(tooltip(&snapshot))
+        .menu(&build_menu(app, &snapshot)?)
+        .on_menu_event(move |app, event| handle_menu_event(app, &service_ref, event))
+        .build(app)?;
+
+    // Rebuild on a separate thread so emitters never wait for the menu
+    let (refresh, requests) = mpsc::channel::<()>();
+    service.events.subscribe(move |event| {
+        if matches!(
+            event,
+            Event::SyncStateChanged { .. }
+                | Event::ClipboardChanged { .. }
+                | Event::DeviceConnected(_)
+                | Event::DeviceDisconnected(_)
+                | Event::TransferProgress { complete: true, .. }
+                | Event::InboxChanged(_)
+        ) {
+            let _ = refresh.send(());
+        }
+    });
+
+    let app = app.clone();
+    let service = Arc::downgrade(service);
+    thread::spawn(move || {
+        while requests.recv().is_ok() {
+            // Several events in a row need only one rebuild
+            while requests.try_recv().is_ok() {}
+            let Some(service) = service.upgrade() else {
+                break;
+            };
+            if let Err(e) = update(&app, &service) {
+                log::error!("Failed to update the tray: {}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn update(app: &AppHandle, service: &Service) -> tauri::Result<()> {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return Ok(());
+    };
+    let snapshot = Snapshot::read(service);
+    tray.set_menu(Some(build_menu(app, &snapshot)?))?;
+    tray.set_icon(Some(icon(app, snapshot.state)))?;
+    tray.set_tooltip(Some(tooltip(&snapshot)))
+}
+
+impl Snapshot {
+    fn read(service: &Service) -> Self {
+        let syncing = *service.is_syncing.lock();
+        let error = service.sync_error.lock().clone();
+        let state = match (syncing, &error) {
+            (true, _) => SyncState::Syncing,
+            (false, Some(_)) => SyncState::Error,
+            (false, None) => SyncState::Paused,
+        };
+
+        let mut seen = HashSet::new();
+        // Direct links come first; list each device once
+        let devices = service
+            .devices()
+            .into_iter()
+            .filter(|device| device.online && seen.insert(device.device_id.clone()))
+            .collect();
+        let inbox = service.inbox();
+
+        let recent = service
+            .history
+            .lock()
+            .list(RECENT_ITEMS, 0)
+            .unwrap_or_else(|e| {
+                log::warn!("Failed to read history for the tray: {}", e);
+                Vec::new()
+            });
+
+        Self {
+            state,
+            error,
+            devices,
+            inbox,
+            recent,
+        }
+    }
+}
+
+fn build_menu(app: &AppHandle, snapshot: &Snapshot) -> tauri::Result<Menu<tauri::Wry>> {
+    let status = match (snapshot.state, &snapshot.error) {
+        (SyncState::Syncing, _) => "同期中".to_string(),
+        (SyncState::Error, Some(error)) => format!("エラー: {}", preview(error, ITEM_LABEL_LEN)),
+        _ => "一時停止中".to_string(),
+    };
+    let toggle_label = if snapshot.state == SyncState::Syncing {
+        "一時停止"
+    } else {
+        "再開"
+    };
+
+    let mut items: Vec<Box<dyn IsMenuItem<tauri::Wry>>> = vec![
+        Box::new(MenuItem::with_id(
+            app,
+            OPEN_ID,
+            "ClipBridge を開く",
+            true,
+            None::<&str>,
+        )?),
+        Box::new(PredefinedMenuItem::separator(app)?),
+        Box::new(MenuItem::new(app, status, false, None::<&str>)?),
+        Box::new(MenuItem::with_id(
+            app,
+            TOGGLE_SYNC_ID,
+            toggle_label,
+            true,
+            None::<&str>,
+        )?),
+        Box::new(PredefinedMenuItem::separator(app)?),
+    ];
+
+    if snapshot.devices.is_empty() {
+        items.push(Box::new(MenuItem::new(
+            app,
+            "接続中のデバイスはありません",
+            false,
+            None::<&str>,
+        )?));
+    }
+    // `&` marks a mnemonic in menu labels, hence the escaping below
+    for device in &snapshot.devices {
+        let path = match device.path {
+            LinkPath::Direct => "直接",
+            LinkPath::Relay => "リレー",
+        };
+        let label = format!(
+            "{} ({})",
+            device.name.as_deref().unwrap_or(&device.device_id),
+            path
+        )
+        .replace('&', "&&");
+        let send = MenuItem::with_id(
+            app,
+            format!("{}{}", SEND_PREFIX, device.device_id),
+            "現在のクリップボードを送信",
+            true,
+            None::<&str>,
+        )?;
+        items.push(Box::new(Submenu::with_items(app, label, true, &[&send])?));
+    }
+
+    if !snapshot.inbox.is_empty() {
+        items.push(Box::new(PredefinedMenuItem::separator(app)?));
+        items.push(Box::new(MenuItem::new(
+            app,
+            format!("受信トレイ（{} 件）", snapshot.inbox.len()),
+            false,
+            None::<&str>,
+        )?));
+    }
+    // Newest first, like history
+    for item in snapshot.inbox.iter().rev() {
+        let label = format!(
+            "{}: {}",
+            item.device_name.as_deref().unwrap_or(&item.origin_device),
+            preview(&item.content, ITEM_LABEL_LEN)
+        )
+        .replace('&', "&&");
+        let accept = MenuItem::with_id(
+            app,
+            format!("{}{}", ACCEPT_PREFIX, item.id),
+            "クリップボードに反映",
+            true,
+            None::<&str>,
+        )?;
+        let discard = MenuItem::with_id(
+            app,
+            format!("{}{}", DISCARD_PREFIX, item.id),
+            "破棄",
+            true,
+            None::<&str>,
+        )?;
+        items.push(Box::new(Submenu::with_items(
+            app,
+            label,
+            true,
+            &[&accept, &discard],
+        )?));
+    }
+
+    items.push(Box::new(PredefinedMenuItem::separator(app)?));
+    if snapshot.recent.is_empty() {
+        items.push(Box::new(MenuItem::new(
+            app,
+            "履歴がありません",
+            false,
+            None::<&str>,
+        )?));
+    }
+    for item in &snapshot.recent {
+        items.push(Box::new(MenuItem::with_id(
+            app,
+            format!("{}{}", COPY_PREFIX, item.id),
+            preview(&item.content, ITEM_LABEL_LEN).replace('&', "&&"),
+            true,
+            None::<&str>,
+        )?));
+    }
+
+    items.push(Box::new(PredefinedMenuItem::separator(app)?));
+    items.push(Box::new(MenuItem::with_id(
+        app,
+        QUIT_ID,
+        "終了",
+        true,
+        None::<&str>,
+    )?));
+
+    let items: Vec<&dyn IsMenuItem<tauri::Wry>> = items.iter().map(|item| item.as_ref()).collect();
+    Menu::with_items(app, &items)
+}
+
+fn tooltip(snapshot: &Snapshot) -> String {
+    match snapshot.state {
+        SyncState::Syncing => format!("ClipBridge - 同期中（{} 台接続）", snapshot.devices.len()),
+        SyncState::Paused => "ClipBridge - 一時停止中".to_string(),
+        SyncState::Error => "ClipBridge - エラー".to_string(),
+    }
+}
+
+/// The app icon, greyed out while paused and tinted red on error
+fn icon(app: &AppHandle, state: SyncState) -> Image<'static> {
+    let Some(base) = app.default_window_icon() else {
+        return Image::new_owned(vec![0; 4], 1, 1);
+    };
+    let mut rgba = base.rgba().to_vec();
+    for pixel in rgba.chunks_exact_mut(4) {
+        let [r, g, b, a] = [pixel[0], pixel[1], pixel[2], pixel[3]];
+        match state {
+            SyncState::Syncing => {}
+            SyncState::Paused => {
+                let grey = ((r as u16 * 3 + g as u16 * 6 + b as u16) / 10) as u8;
+                pixel.copy_from_slice(&[grey, grey, grey, a / 2]);
+            }
+            SyncState::Error => {
+                pixel.copy_from_slice(&[r.max(200), g / 3, b / 3, a]);
+            }
+        }
+    }
+    Image::new_owned(rgba, base.width(), base.height())
+}
+
+fn handle_menu_event(app: &AppHandle, service: &Weak<Service>, event: MenuEvent) {
+    let Some(service) = service.upgrade() else {
+        return;
+    };
+    let id = event.id.0;
+
+    match id.as_str() {
+        OPEN_ID => show_main_window(app),
+        QUIT_ID => app.exit(0),
+        TOGGLE_SYNC_ID => toggle_sync(service),
+        _ => {
+            if let Some(device_id) = id.strip_prefix(SEND_PREFIX) {
+                let device_id = device_id.to_string();
+                thread::spawn(move || send_clipboard(&service, Some(device_id)));
+            } else if let Some(item_id) = id.strip_prefix(COPY_PREFIX) {
+                copy_history_item(&service, item_id);
+            } else if let Some(item_id) = id.strip_prefix(ACCEPT_PREFIX) {
+                resolve_inbox_item(&service, item_id, true);
+            } else if let Some(item_id) = id.strip_prefix(DISCARD_PREFIX) {
+                resolve_inbox_item(&service, item_id, false);
+            }
+        }
+    }
+}
+
+/// Pause or resume sync in the background: starting binds ports and may
+/// contact the relay
+pub fn toggle_sync(service: Arc<Service>) {
+    thread::spawn(move || {
+        if *service.is_syncing.lock() {
+            service.stop_sync();
+        } else if let Err(e) = service.start_sync() {
+            log::error!("Failed to start sync: {}", e);
+        }
+    });
+}
+
+pub fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Send the clipboard to `device_id`, or to every device
+pub fn send_clipboard(service: &Service, device_id: Option<String>) {
+    let text = match clipboard::get_text() {
+        Ok(text) => text,
+        Err(e) => {
+            log::error!("Failed to read the clipboard: {}", e);
+            return;
+        }
+    };
+
+    let to: Vec<String> = device_id.into_iter().collect();
+    match service.send_text(text, &to) {
+        Ok(true) => log::info!("Sent the clipboard"),
+        Ok(false) => log::info!("Clipboard held by the sensitive filter"),
+        Err(e) => log::error!("Failed to send the clipboard: {}", e),
+    }
+}
+
+fn resolve_inbox_item(service: &Service, id: &str, accept: bool) {
+    if let Err(e) = service.resolve_inbox(id, accept) {
+        log::error!("Failed to apply inbox item {}: {}", id, e);
+    }
+}
+
+/// Put a history item back on the clipboard; it syncs like any copy
+fn copy_history_item(service: &Service, id: &str) {
+    let item = match service.history.lock().get(id) {
+        Ok(Some(item)) => item,
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("Failed to read history item {}: {}", id, e);
+            return;
+        }
+    };
+    if let Err(e) = clipboard::set_text(&item.content) {
+        log::error!("Failed to copy history item {}: {}", id, e);
+    }
+}
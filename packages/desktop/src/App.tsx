@@ -1,21 +1,64 @@
 import { useState, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { onEvent, type DeviceInfo, type InboxItem } from './events';
+import HistoryPicker from './HistoryPicker';
 import './App.css';
 
-interface ClipboardItem {
+/** Items shown in the history section */
+const HISTORY_LIMIT = 20;
+
+interface HistoryItem {
   id: string;
   content: string;
-  timestamp: number;
+  created_at: number;
 }
 
 function App() {
   const [isSyncing, setIsSyncing] = useState(false);
-  const [clipboardHistory] = useState<ClipboardItem[]>([]);
+  const [clipboardHistory, setClipboardHistory] = useState<HistoryItem[]>([]);
   const [currentClipboard, setCurrentClipboard] = useState('');
+  const [devices, setDevices] = useState<DeviceInfo[]>([]);
+  const [inbox, setInbox] = useState<InboxItem[]>([]);
+  const [showPicker, setShowPicker] = useState(false);
 
   useEffect(() => {
     checkSyncStatus();
     loadCurrentClipboard();
+    loadHistory();
+    loadDevices();
+    loadInbox();
+
+    const listeners = [
+      onEvent('sync-state-changed', ({ syncing }) => {
+        setIsSyncing(syncing);
+        if (!syncing) {
+          setDevices([]);
+        }
+      }),
+      onEvent('clipboard-changed', () => {
+        loadCurrentClipboard();
+        loadHistory();
+      }),
+      onEvent('device-connected', (device) => {
+        setDevices((current) => [
+          ...current.filter((known) => known.deviceId !== device.deviceId),
+          device,
+        ]);
+      }),
+      onEvent('device-disconnected', (device) => {
+        setDevices((current) => current.filter((known) => known.deviceId !== device.deviceId));
+      }),
+      onEvent('inbox-changed', setInbox),
+      onEvent('open-history-picker', () => setShowPicker(true)),
+      onEvent('transfer-progress', ({ complete }) => {
+        if (complete) {
+          loadHistory();
+        }
+      }),
+    ];
+    return () => {
+      listeners.forEach((unlisten) => unlisten.then((stop) => stop()));
+    };
   }, []);
 
   const checkSyncStatus = async () => {
@@ -36,14 +79,65 @@ function App() {
     }
   };
 
+  const loadHistory = async () => {
+    try {
+      const items = await invoke<HistoryItem[]>('list_history', { limit: HISTORY_LIMIT });
+      setClipboardHistory(items);
+    } catch (error) {
+      console.error('Failed to load history:', error);
+    }
+  };
+
+  const loadDevices = async () => {
+    try {
+      const links = await invoke<Omit<DeviceInfo, 'name'>[]>('list_peer_links');
+      // Direct links are listed first; keep one entry per device
+      const online = links.filter((link) => link.online);
+      setDevices(
+        online
+          .filter((link, index) => online.findIndex((other) => other.deviceId === link.deviceId) === index)
+          .map((link) => ({ ...link, name: null })),
+      );
+    } catch (error) {
+      console.error('Failed to load devices:', error);
+    }
+  };
+
+  const loadInbox = async () => {
+    try {
+      setInbox(await invoke<InboxItem[]>('list_inbox'));
+    } catch (error) {
+      console.error('Failed to load inbox:', error);
+    }
+  };
+
+  // The updated inbox arrives as inbox-changed
+  const resolveInboxItem = async (id: string, accept: boolean) => {
+    try {
+      await invoke('resolve_inbox_item', { id, accept });
+    } catch (error) {
+      console.error('Failed to resolve inbox item:', error);
+    }
+  };
+
+  const sendToDevices = async (text: string) => {
+    try {
+      const sent = await invoke<boolean>('send_text', { text, to: [] });
+      if (!sent) {
+        console.info('Held by the sensitive filter');
+      }
+    } catch (error) {
+      console.error('Failed to send:', error);
+    }
+  };
+
+  // The new state arrives as sync-state-changed
   const toggleSync = async () => {
     try {
       if (isSyncing) {
         await invoke('stop_sync');
-        setIsSyncing(false);
       } else {
         await invoke('start_sync');
-        setIsSyncing(true);
       }
     } catch (error) {
       console.error('Failed to toggle sync:', error);
@@ -53,7 +147,6 @@ function App() {
   const copyToClipboard = async (text: string) => {
     try {
       await invoke('set_clipboard_text', { text });
-      setCurrentClipboard(text);
     } catch (error) {
       console.error('Failed to copy to clipboard:', error);
     }
@@ -93,6 +186,51 @@ function App() {
           </button>
         </section>
 
+        <section className="devices">
+          <h2>接続中のデバイス</h2>
+          {devices.length > 0 ? (
+            <ul className="device-list">
+              {devices.map((device) => (
+                <li key={device.deviceId}>
+                  {device.name ?? device.deviceId}
+                  <span className="device-path">
+                    {device.path === 'direct' ? '直接' : 'リレー'}
+                  </span>
+                </li>
+              ))}
+            </ul>
+          ) : (
+            <p className="empty-message">接続中のデバイスはありません</p>
+          )}
+        </section>
+
+        {inbox.length > 0 && (
+          <section className="inbox">
+            <h2>受信トレイ</h2>
+            <div className="history-list">
+              {[...inbox].reverse().map((item) => (
+                <div key={item.id} className="history-item">
+                  <div className="history-content">{item.content}</div>
+                  <div className="history-timestamp">
+                    {item.deviceName ?? item.originDevice} ・{' '}
+                    {new Date(item.timestamp).toLocaleString('ja-JP')}
+                    {item.applyAt &&
+                      ` ・ ${new Date(item.applyAt).toLocaleTimeString('ja-JP')} に自動で反映`}
+                  </div>
+                  <div className="item-actions">
+                    <button onClick={() => resolveInboxItem(item.id, true)} className="btn-primary">
+                      反映
+                    </button>
+                    <button onClick={() => resolveInboxItem(item.id, false)} className="btn-secondary">
+                      破棄
+                    </button>
+                  </div>
+                </div>
+              ))}
+            </div>
+          </section>
+        )}
+
         <section className="clipboard-history">
           <h2>クリップボード履歴</h2>
           {clipboardHistory.length > 0 ? (
@@ -105,8 +243,21 @@ function App() {
                 >
                   <div className="history-content">{item.content}</div>
                   <div className="history-timestamp">
-                    {new Date(item.timestamp).toLocaleString('ja-JP')}
+                    {new Date(item.created_at).toLocaleString('ja-JP')}
                   </div>
+                  {isSyncing && (
+                    <div className="item-actions">
+                      <button
+                        onClick={(event) => {
+                          event.stopPropagation();
+                          sendToDevices(item.content);
+                        }}
+                        className="btn-secondary"
+                      >
+                        送信
+                      </button>
+                    </div>
+                  )}
                 </div>
               ))}
             </div>
@@ -116,6 +267,8 @@ function App() {
         </section>
       </main>
 
+      {showPicker && <HistoryPicker onClose={() => setShowPicker(false)} />}
+
       <footer className="app-footer">
         <p>ClipBridge v0.1.0</p>
       </footer>
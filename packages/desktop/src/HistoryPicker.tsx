@@ -0,0 +1,108 @@
+import { useEffect, useRef, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { getCurrentWindow } from '@tauri-apps/api/window';
+
+/** Items listed by the picker */
+const PICKER_LIMIT = 10;
+
+interface HistoryItem {
+  id: string;
+  content: string;
+  origin_device: string;
+}
+
+interface SearchResult {
+  item: HistoryItem;
+}
+
+interface HistoryPickerProps {
+  onClose: () => void;
+}
+
+/** Search history from the keyboard and copy the chosen item */
+function HistoryPicker({ onClose }: HistoryPickerProps) {
+  const [text, setText] = useState('');
+  const [items, setItems] = useState<HistoryItem[]>([]);
+  const [selected, setSelected] = useState(0);
+  const input = useRef<HTMLInputElement>(null);
+
+  useEffect(() => {
+    input.current?.focus();
+  }, []);
+
+  useEffect(() => {
+    const load = async () => {
+      try {
+        if (text.trim()) {
+          const results = await invoke<SearchResult[]>('search_history', {
+            query: { text, limit: PICKER_LIMIT },
+          });
+          setItems(results.map((result) => result.item));
+        } else {
+          setItems(await invoke<HistoryItem[]>('list_history', { limit: PICKER_LIMIT }));
+        }
+        setSelected(0);
+      } catch (error) {
+        console.error('Failed to search history:', error);
+      }
+    };
+    load();
+  }, [text]);
+
+  // Copy and get out of the way, back to the app the user came from
+  const choose = async (item: HistoryItem) => {
+    try {
+      await invoke('set_clipboard_text', { text: item.content });
+      onClose();
+      await getCurrentWindow().hide();
+    } catch (error) {
+      console.error('Failed to copy history item:', error);
+    }
+  };
+
+  const onKeyDown = (event: React.KeyboardEvent) => {
+    if (event.key === 'ArrowDown') {
+      event.preventDefault();
+      setSelected((index) => Math.min(index + 1, items.length - 1));
+    } else if (event.key === 'ArrowUp') {
+      event.preventDefault();
+      setSelected((index) => Math.max(index - 1, 0));
+    } else if (event.key === 'Enter' && items[selected]) {
+      choose(items[selected]);
+    } else if (event.key === 'Escape') {
+      onClose();
+    }
+  };
+
+  return (
+    <div className="picker-backdrop" onClick={onClose}>
+      <div className="picker" onClick={(event) => event.stopPropagation()}>
+        <input
+          ref={input}
+          value={text}
+          onChange={(event) => setText(event.target.value)}
+          onKeyDown={onKeyDown}
+          placeholder="履歴を検索"
+        />
+        {items.length > 0 ? (
+          <ul>
+            {items.map((item, index) => (
+              <li
+                key={item.id}
+                className={index === selected ? 'selected' : undefined}
+                onMouseEnter={() => setSelected(index)}
+                onClick={() => choose(item)}
+              >
+                {item.content}
+              </li>
+            ))}
+          </ul>
+        ) : (
+          <p className="empty-message">見つかりません</p>
+        )}
+      </div>
+    </div>
+  );
+}
+
+export default HistoryPicker;
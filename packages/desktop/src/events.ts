@@ -0,0 +1,55 @@
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+
+/** How a device is reached */
+export type LinkPath = 'direct' | 'relay';
+
+export interface DeviceInfo {
+  deviceId: string;
+  name: string | null;
+  path: LinkPath;
+  online: boolean;
+}
+
+/** An item from a peer waiting to be applied or discarded */
+export interface InboxItem {
+  id: string;
+  originDevice: string;
+  deviceName: string | null;
+  content: string;
+  timestamp: number;
+  /** When it is applied automatically, in milliseconds since the epoch */
+  applyAt?: number;
+  /** The group it was sealed for, if any */
+  groupId?: string;
+}
+
+/** Payloads of the events emitted by the backend, by event name */
+export interface EventPayloads {
+  'sync-state-changed': { syncing: boolean; error?: string };
+  'clipboard-changed': {
+    id: string;
+    source: 'local' | 'remote';
+    originDevice: string;
+    deviceName: string | null;
+    preview: string;
+  };
+  'device-connected': DeviceInfo;
+  'device-disconnected': DeviceInfo;
+  'pairing-requested': { deviceId: string };
+  'transfer-progress': { deviceId: string; received: number; complete: boolean };
+  'item-received': InboxItem;
+  'inbox-changed': InboxItem[];
+  'sensitive-item-blocked': { rules: string[] };
+  /** Sent by the history picker hotkey */
+  'open-history-picker': null;
+}
+
+export type EventName = keyof EventPayloads;
+
+/** Listen for a backend event. Resolves to a function that stops listening. */
+export function onEvent<E extends EventName>(
+  name: E,
+  handler: (payload: EventPayloads[E]) => void,
+): Promise<UnlistenFn> {
+  return listen<EventPayloads[E]>(name, (event) => handler(event.payload));
+}
@@ -0,0 +1,275 @@
+use super::{ClipboardError, Result, SourceApp};
+use arboard::Clipboard;
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::time::Duration;
+use x11_clipboard::{Atom, Window};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+
+/// Targets password managers offer alongside secrets
+const CONCEALED_TARGETS: &[&str] = &[
+    // KDE convention, also used by KeePassXC
+    "x-kde-passwordManagerHint",
+    // macOS convention, used by some cross-platform password managers
+    "application/x-nspasteboard-concealed-type",
+];
+const TARGETS_TIMEOUT: Duration = Duration::from_millis(500);
+/// How far up the window tree to look for the owner's WM_CLASS
+const MAX_OWNER_DEPTH: usize = 8;
+
+// Use a global clipboard instance to avoid X11 connection issues
+lazy_static::lazy_static! {
+    /// Connected on first use, so a missing display is an error rather
+    /// than a panic
+    static ref CLIPBOARD: Mutex<Option<Clipboard>> = Mutex::new(None);
+    /// Raw X11 access for reading the selection's targets
+    static ref SELECTION: Mutex<Option<x11_clipboard::Clipboard>> = Mutex::new(None);
+}
+
+/// Get text from Linux clipboard
+pub fn get_text() -> Result<String> {
+    with_clipboard(|clipboard| {
+        clipboard
+            .get_text()
+            .map_err(|e| ClipboardError::Unknown(format!("Failed to read clipboard: {}", e)))
+    })
+}
+
+/// Set text to Linux clipboard
+pub fn set_text(text: &str) -> Result<()> {
+    with_clipboard(|clipboard| {
+        clipboard
+            .set_text(text.to_string())
+            .map_err(|e| ClipboardError::Unknown(format!("Failed to write clipboard: {}", e)))
+    })
+}
+
+/// Run `f` with the shared clipboard, connecting on first use
+fn with_clipboard<T>(f: impl FnOnce(&mut Clipboard) -> Result<T>) -> Result<T> {
+    let mut clipboard = CLIPBOARD.lock().unwrap();
+    if clipboard.is_none() {
+        let connected = Clipboard::new().map_err(|e| {
+            ClipboardError::Unknown(format!("Failed to initialize clipboard: {}", e))
+        })?;
+        *clipboard = Some(connected);
+    }
+    f(clipboard.as_mut().unwrap())
+}
+
+/// Run `f` with the shared raw X11 connection, connecting on first use
+fn with_selection<T>(f: impl FnOnce(&x11_clipboard::Clipboard) -> Result<T>) -> Result<T> {
+    let mut selection = SELECTION.lock().unwrap();
+    if selection.is_none() {
+        let clipboard = x11_clipboard::Clipboard::new()
+            .map_err(|e| x11_error("Failed to connect to X11", e))?;
+        *selection = Some(clipboard);
+    }
+    f(selection.as_ref().unwrap())
+}
+
+fn x11_error(context: &str, e: impl Display) -> ClipboardError {
+    ClipboardError::Unknown(format!("{}: {}", context, e))
+}
+
+/// Whether the clipboard owner marked the current content as a secret
+pub fn is_concealed() -> Result<bool> {
+    with_selection(|clipboard| {
+        let atoms = &clipboard.getter.atoms;
+        let targets: Vec<Atom> = clipboard
+            .load(
+                atoms.clipboard,
+                atoms.targets,
+                atoms.property,
+                TARGETS_TIMEOUT,
+            )
+            .map_err(|e| x11_error("Failed to read targets", e))?
+            .chunks_exact(4)
+            .map(|chunk| Atom::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+
+        for name in CONCEALED_TARGETS {
+            let atom = clipboard
+                .getter
+                .get_atom(name)
+                .map_err(|e| x11_error("Failed to intern atom", e))?;
+            if targets.contains(&atom) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    })
+}
+
+/// The application owning the clipboard, from its WM_CLASS and _NET_WM_PID
+pub fn source_app() -> Result<Option<SourceApp>> {
+    with_selection(|clipboard| {
+        let conn = &clipboard.getter.connection;
+        let owner = conn
+            .get_selection_owner(clipboard.getter.atoms.clipboard)
+            .map_err(|e| x11_error("Failed to query selection owner", e))?
+            .reply()
+            .map_err(|e| x11_error("Failed to query selection owner", e))?
+            .owner;
+        if owner == x11rb::NONE {
+            return Ok(None);
+        }
+
+        let pid_atom = clipboard
+            .getter
+            .get_atom("_NET_WM_PID")
+            .map_err(|e| x11_error("Failed to intern atom", e))?;
+        let leader_atom = clipboard
+            .getter
+            .get_atom("WM_CLIENT_LEADER")
+            .map_err(|e| x11_error("Failed to intern atom", e))?;
+
+        // Toolkits usually own the selection from a hidden helper window, so
+        // fall back to its client leader and then its ancestors
+        let mut source = SourceApp::default();
+        let mut window = owner;
+        for _ in 0..MAX_OWNER_DEPTH {
+            if source.class.is_none() {
+                let wm_class = property(conn, window, AtomEnum::WM_CLASS.into())?;
+                let mut names = wm_class
+                    .split(|&b| b == 0)
+                    .map(|name| String::from_utf8_lossy(name).into_owned());
+                source.instance = names.next().filter(|name| !name.is_empty());
+                source.class = names.next().filter(|name| !name.is_empty());
+            }
+            if source.pid.is_none() {
+                let pid = property(conn, window, pid_atom)?;
+                source.pid = pid
+                    .get(..4)
+                    .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]));
+            }
+            if source.class.is_some() && source.pid.is_some() {
+                break;
+            }
+
+            match next_owner_candidate(conn, window, leader_atom)? {
+                Some(next) => window = next,
+                None => break,
+            }
+        }
+
+        source.executable = source.pid.and_then(|pid| {
+            std::fs::read_link(format!("/proc/{}/exe", pid))
+                .ok()
+                .map(|path| path.to_string_lossy().into_owned())
+        });
+
+        if source == SourceApp::default() {
+            Ok(None)
+        } else {
+            Ok(Some(source))
+        }
+    })
+}
+
+fn property(conn: &impl Connection, window: Window, atom: Atom) -> Result<Vec<u8>> {
+    let reply = conn
+        .get_property(false, window, atom, AtomEnum::ANY, 0, 1024)
+        .map_err(|e| x11_error("Failed to read window property", e))?
+        .reply()
+        .map_err(|e| x11_error("Failed to read window property", e))?;
+    Ok(reply.value)
+}
+
+/// The client leader of `window` if it has one, otherwise its parent
+fn next_owner_candidate(
+    conn: &impl Connection,
+    window: Window,
+    leader_atom: Atom,
+) -> Result<Option<Window>> {
+    let leader = property(conn, window, leader_atom)?;
+    if let Some(b) = leader.get(..4) {
+        let leader = Window::from_ne_bytes([b[0], b[1], b[2], b[3]]);
+        if leader != window && leader != x11rb::NONE {
+            return Ok(Some(leader));
+        }
+    }
+
+    let tree = conn
+        .query_tree(window)
+        .map_err(|e| x11_error("Failed to query window tree", e))?
+        .reply()
+        .map_err(|e| x11_error("Failed to query window tree", e))?;
+    if tree.parent == x11rb::NONE || tree.parent == tree.root {
+        Ok(None)
+    } else {
+        Ok(Some(tree.parent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::TEST_CLIPBOARD;
+
+    #[test]
+    fn test_set_and_get_text() {
+        let _clipboard = TEST_CLIPBOARD.lock();
+        let test_text = "Hello, ClipBridge on Linux!";
+        set_text(test_text).unwrap();
+        let retrieved = get_text().unwrap();
+        assert_eq!(retrieved, test_text);
+    }
+
+    #[test]
+    #[ignore = "needs an X server, e.g. xvfb-run cargo test -- --ignored"]
+    fn test_password_manager_hint_marks_content_concealed() {
+        let _clipboard = TEST_CLIPBOARD.lock();
+        // A second connection acts as the password manager owning the selection
+        let owner = x11_clipboard::Clipboard::new().unwrap();
+        let atoms = owner.setter.atoms.clone();
+
+        let hint = owner.setter.get_atom(CONCEALED_TARGETS[0]).unwrap();
+        owner.store(atoms.clipboard, hint, "secret").unwrap();
+        assert!(is_concealed().unwrap());
+
+        owner
+            .store(atoms.clipboard, atoms.utf8_string, "not a secret")
+            .unwrap();
+        assert!(!is_concealed().unwrap());
+    }
+
+    #[test]
+    #[ignore = "needs an X server, e.g. xvfb-run cargo test -- --ignored"]
+    fn test_source_app_reads_owner_wm_class() {
+        use x11rb::wrapper::ConnectionExt as _;
+
+        let _clipboard = TEST_CLIPBOARD.lock();
+        let owner = x11_clipboard::Clipboard::new().unwrap();
+        let conn = &owner.setter.connection;
+        conn.change_property8(
+            x11rb::protocol::xproto::PropMode::REPLACE,
+            owner.setter.window,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            b"keepassxc\0KeePassXC\0",
+        )
+        .unwrap();
+        let pid_atom = owner.setter.get_atom("_NET_WM_PID").unwrap();
+        conn.change_property32(
+            x11rb::protocol::xproto::PropMode::REPLACE,
+            owner.setter.window,
+            pid_atom,
+            AtomEnum::CARDINAL,
+            &[std::process::id()],
+        )
+        .unwrap();
+        conn.flush().unwrap();
+
+        let atoms = owner.setter.atoms.clone();
+        owner
+            .store(atoms.clipboard, atoms.utf8_string, "hunter2")
+            .unwrap();
+
+        let source = source_app().unwrap().unwrap();
+        assert_eq!(source.class.as_deref(), Some("KeePassXC"));
+        assert_eq!(source.instance.as_deref(), Some("keepassxc"));
+        assert_eq!(source.pid, Some(std::process::id()));
+        assert!(source.executable.is_some());
+    }
+}
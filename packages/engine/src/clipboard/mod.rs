@@ -0,0 +1,259 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(target_os = "windows")]
+mod windows;
+
+#[cfg(target_os = "linux")]
+mod linux;
+
+#[derive(Debug)]
+pub enum ClipboardError {
+    #[allow(dead_code)]
+    AccessDenied,
+    #[allow(dead_code)]
+    UnsupportedFormat,
+    Unknown(String),
+}
+
+impl fmt::Display for ClipboardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClipboardError::AccessDenied => write!(f, "Clipboard access denied"),
+            ClipboardError::UnsupportedFormat => write!(f, "Unsupported clipboard format"),
+            ClipboardError::Unknown(msg) => write!(f, "Clipboard error: {}", msg),
+        }
+    }
+}
+
+impl Error for ClipboardError {}
+
+pub type Result<T> = std::result::Result<T, ClipboardError>;
+
+/// Get text from clipboard
+pub fn get_text() -> Result<String> {
+    #[cfg(target_os = "windows")]
+    return windows::get_text();
+
+    #[cfg(target_os = "linux")]
+    return linux::get_text();
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    Err(ClipboardError::Unknown(
+        "Platform not supported".to_string(),
+    ))
+}
+
+/// Set text to clipboard
+pub fn set_text(text: &str) -> Result<()> {
+    #[cfg(target_os = "windows")]
+    return windows::set_text(text);
+
+    #[cfg(target_os = "linux")]
+    return linux::set_text(text);
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    Err(ClipboardError::Unknown(
+        "Platform not supported".to_string(),
+    ))
+}
+
+/// Whether the current content was marked secret by its owner, e.g. a
+/// password manager
+pub fn is_concealed() -> Result<bool> {
+    #[cfg(target_os = "windows")]
+    return windows::is_concealed();
+
+    #[cfg(target_os = "linux")]
+    return linux::is_concealed();
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    Ok(false)
+}
+
+/// The application that put the current content on the clipboard. Only
+/// resolved on Linux (X11).
+pub fn source_app() -> Result<Option<SourceApp>> {
+    #[cfg(target_os = "linux")]
+    return linux::source_app();
+
+    #[cfg(not(target_os = "linux"))]
+    Ok(None)
+}
+
+/// The application a clipboard item came from
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceApp {
+    /// WM_CLASS class, e.g. "KeePassXC"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// WM_CLASS instance, e.g. "keepassxc"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executable: Option<String>,
+}
+
+impl SourceApp {
+    /// Whether `name` (an `excludedApps` entry) refers to this app. Matches
+    /// the WM_CLASS class or instance, or the executable's path or file
+    /// name, ignoring case.
+    pub fn matches(&self, name: &str) -> bool {
+        let file_name = self
+            .executable
+            .as_deref()
+            .and_then(|path| path.rsplit('/').next());
+
+        [
+            self.class.as_deref(),
+            self.instance.as_deref(),
+            self.executable.as_deref(),
+            file_name,
+        ]
+        .into_iter()
+        .flatten()
+        .any(|candidate| candidate.eq_ignore_ascii_case(name))
+    }
+}
+
+/// A change seen by the monitor
+#[derive(Debug, Clone)]
+pub struct ClipboardItem {
+    pub text: String,
+    /// Marked secret by its owner; must not be stored or synced
+    pub concealed: bool,
+    pub source: Option<SourceApp>,
+}
+
+/// Clipboard monitor for detecting changes
+pub struct ClipboardMonitor {
+    last_content: Arc<Mutex<String>>,
+    is_running: Arc<Mutex<bool>>,
+}
+
+impl ClipboardMonitor {
+    pub fn new() -> Self {
+        Self {
+            last_content: Arc::new(Mutex::new(String::new())),
+            is_running: Arc::new(Mutex::new(false)),
+        }
+    }
+
+    /// Start monitoring clipboard changes
+    pub fn start<F>(&self, callback: F) -> Result<()>
+    where
+        F: Fn(ClipboardItem) + Send + 'static,
+    {
+        let mut is_running = self.is_running.lock();
+        if *is_running {
+            return Ok(());
+        }
+
+        *is_running = true;
+        let is_running_clone = Arc::clone(&self.is_running);
+        let last_content_clone = Arc::clone(&self.last_content);
+
+        thread::spawn(move || {
+            log::info!("Clipboard monitor started");
+            // Only the first of a run of read errors is a warning, so a
+            // machine without a display doesn't flood the log
+            let mut failing = false;
+
+            while *is_running_clone.lock() {
+                match get_text() {
+                    Ok(current_content) => {
+                        failing = false;
+                        let mut last_content = last_content_clone.lock();
+                        if current_content != *last_content && !current_content.is_empty() {
+                            log::debug!("Clipboard changed: {} bytes", current_content.len());
+                            *last_content = current_content.clone();
+                            drop(last_content); // Release lock before callback
+
+                            let concealed = is_concealed().unwrap_or_else(|e| {
+                                log::debug!("Failed to read clipboard hints: {}", e);
+                                false
+                            });
+                            let source = source_app().unwrap_or_else(|e| {
+                                log::debug!("Failed to resolve clipboard owner: {}", e);
+                                None
+                            });
+                            callback(ClipboardItem {
+                                text: current_content,
+                                concealed,
+                                source,
+                            });
+                        }
+                    }
+                    Err(e) if failing => log::debug!("Failed to read clipboard: {}", e),
+                    Err(e) => {
+                        log::warn!("Failed to read clipboard: {}", e);
+                        failing = true;
+                    }
+                }
+
+                thread::sleep(Duration::from_millis(500));
+            }
+
+            log::info!("Clipboard monitor stopped");
+        });
+
+        Ok(())
+    }
+
+    /// Stop monitoring
+    pub fn stop(&self) {
+        let mut is_running = self.is_running.lock();
+        *is_running = false;
+    }
+
+    /// Treat `content` as already seen, so writing it to the clipboard
+    /// does not trigger the change callback
+    pub fn set_last_content(&self, content: String) {
+        *self.last_content.lock() = content;
+    }
+
+    /// Check if monitoring is active
+    #[allow(dead_code)]
+    pub fn is_running(&self) -> bool {
+        *self.is_running.lock()
+    }
+}
+
+impl Default for ClipboardMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Held by tests that use the system clipboard, which every test thread
+/// shares
+#[cfg(test)]
+pub(crate) static TEST_CLIPBOARD: Mutex<()> = Mutex::new(());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_app_matches_class_or_executable() {
+        let source = SourceApp {
+            class: Some("KeePassXC".to_string()),
+            instance: Some("keepassxc".to_string()),
+            pid: Some(42),
+            executable: Some("/usr/bin/keepassxc".to_string()),
+        };
+
+        assert!(source.matches("keepassxc"));
+        assert!(source.matches("KEEPASSXC"));
+        assert!(source.matches("/usr/bin/keepassxc"));
+        assert!(!source.matches("keepass"));
+        assert!(!SourceApp::default().matches(""));
+    }
+}
@@ -0,0 +1,62 @@
+use super::{ClipboardError, Result};
+use clipboard_win::{formats, get_clipboard, is_format_avail, register_format, set_clipboard};
+
+/// Present when the owner asks clipboard monitors to ignore the content
+const EXCLUDE_FORMAT: &str = "ExcludeClipboardContentFromMonitorProcessing";
+/// A DWORD of 0 keeps the content out of the Windows clipboard history
+const HISTORY_FORMAT: &str = "CanIncludeInClipboardHistory";
+
+/// Get text from Windows clipboard
+pub fn get_text() -> Result<String> {
+    get_clipboard(formats::Unicode)
+        .map_err(|e| ClipboardError::Unknown(format!("Failed to read clipboard: {}", e)))
+}
+
+/// Set text to Windows clipboard
+pub fn set_text(text: &str) -> Result<()> {
+    set_clipboard(formats::Unicode, text)
+        .map_err(|e| ClipboardError::Unknown(format!("Failed to write clipboard: {}", e)))
+}
+
+/// Whether the clipboard owner marked the current content as a secret
+pub fn is_concealed() -> Result<bool> {
+    if register_format(EXCLUDE_FORMAT).is_some_and(|format| is_format_avail(format.get())) {
+        return Ok(true);
+    }
+
+    match register_format(HISTORY_FORMAT) {
+        Some(format) if is_format_avail(format.get()) => {
+            let value: Vec<u8> = get_clipboard(formats::RawData(format.get())).map_err(|e| {
+                ClipboardError::Unknown(format!("Failed to read {}: {}", HISTORY_FORMAT, e))
+            })?;
+            Ok(value == [0, 0, 0, 0])
+        }
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::TEST_CLIPBOARD;
+
+    #[test]
+    fn test_set_and_get_text() {
+        let _clipboard = TEST_CLIPBOARD.lock();
+        let test_text = "Hello, ClipBridge on Windows!";
+        set_text(test_text).unwrap();
+        let retrieved = get_text().unwrap();
+        assert_eq!(retrieved, test_text);
+    }
+
+    #[test]
+    fn test_exclusion_format_marks_content_concealed() {
+        let _clipboard = TEST_CLIPBOARD.lock();
+        let format = register_format(EXCLUDE_FORMAT).unwrap();
+        set_clipboard(formats::RawData(format.get()), [0u8]).unwrap();
+        assert!(is_concealed().unwrap());
+
+        set_text("not a secret").unwrap();
+        assert!(!is_concealed().unwrap());
+    }
+}
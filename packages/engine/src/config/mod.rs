@@ -0,0 +1,637 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Upper bound for `general.history_size` (SPECIFICATION.md §8)
+const MAX_HISTORY_SIZE: usize = 1000;
+/// Upper bound for `sync.max_item_size`
+const MAX_ITEM_SIZE_LIMIT: u64 = 100 * 1024 * 1024;
+/// Shortest accepted relay account secret
+const MIN_RELAY_ACCOUNT_LEN: usize = 16;
+/// How often the config file is checked for edits
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "Config I/O error: {}", e),
+            ConfigError::Parse(msg) => write!(f, "Config parse error: {}", msg),
+            ConfigError::Invalid(msg) => write!(f, "Invalid config: {}", msg),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ConfigError>;
+
+/// Mirrors `AppConfig` in `@clipbridge/protocol`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AppConfig {
+    pub general: GeneralConfig,
+    pub sync: SyncConfig,
+    pub security: SecurityConfig,
+    pub network: NetworkConfig,
+    pub hotkeys: HotkeyConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GeneralConfig {
+    pub auto_start: bool,
+    pub show_notifications: bool,
+    pub history_size: usize,
+    /// Delete items older than this many days
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age_days: Option<u64>,
+    /// Delete the oldest items once their content exceeds this many bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_total_bytes: Option<u64>,
+}
+
+impl Default for GeneralConfig {
+    fn default() -> Self {
+        Self {
+            auto_start: true,
+            show_notifications: true,
+            history_size: 100,
+            max_age_days: None,
+            max_total_bytes: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncMode {
+    #[default]
+    P2p,
+    Cloud,
+    Hybrid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SyncConfig {
+    pub mode: SyncMode,
+    /// Send every local copy and apply every incoming item. When off,
+    /// copies are only recorded in history and incoming items wait in the
+    /// sync engine's inbox.
+    pub auto_sync: bool,
+    pub sync_images: bool,
+    pub sync_files: bool,
+    /// Largest clipboard item that is sent or accepted, in bytes
+    pub max_item_size: u64,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            mode: SyncMode::P2p,
+            auto_sync: true,
+            sync_images: true,
+            sync_files: false,
+            max_item_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SecurityConfig {
+    pub enable_encryption: bool,
+    pub require_device_approval: bool,
+    pub enable_sensitive_filter: bool,
+    /// Rules checked before a local item is stored or broadcast. Not part
+    /// of the TypeScript config.
+    pub sensitive_rules: Vec<SensitiveRule>,
+    pub excluded_apps: Vec<String>,
+    pub confirm_incoming: ConfirmConfig,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            enable_encryption: true,
+            require_device_approval: true,
+            enable_sensitive_filter: true,
+            sensitive_rules: SensitiveRule::defaults(),
+            excluded_apps: Vec::new(),
+            confirm_incoming: ConfirmConfig::default(),
+        }
+    }
+}
+
+/// Staging of items from peers in the inbox until the user applies or
+/// discards them. Peers can override this with their `confirm` policy.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ConfirmConfig {
+    pub enabled: bool,
+    /// MIME types staged, e.g. `text/plain` or `text/*`. Empty means all
+    /// types.
+    pub mime_types: Vec<String>,
+    /// Apply a staged item after this many seconds; 0 waits for the user
+    pub auto_apply_secs: u64,
+}
+
+/// What a sensitive data rule looks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Detector {
+    /// Card numbers that pass the Luhn check
+    CreditCard,
+    AwsKey,
+    GithubToken,
+    Jwt,
+    /// PEM encoded private keys
+    PrivateKey,
+    /// Long random-looking tokens
+    HighEntropy,
+    /// The rule's own `pattern`
+    Regex,
+}
+
+/// What happens to an item a rule matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    /// Replace the matched text before syncing
+    Redact,
+    /// Hold the item until the user allows or rejects it
+    Ask,
+    /// Never store or sync the item
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SensitiveRule {
+    /// Shown in the audit log. Defaults to the detector name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub detector: Detector,
+    /// Regular expression for `Detector::Regex`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    pub action: FilterAction,
+}
+
+impl SensitiveRule {
+    fn builtin(detector: Detector, action: FilterAction) -> Self {
+        Self {
+            name: None,
+            detector,
+            pattern: None,
+            action,
+        }
+    }
+
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::builtin(Detector::PrivateKey, FilterAction::Block),
+            Self::builtin(Detector::AwsKey, FilterAction::Block),
+            Self::builtin(Detector::GithubToken, FilterAction::Block),
+            Self::builtin(Detector::Jwt, FilterAction::Block),
+            Self::builtin(Detector::CreditCard, FilterAction::Redact),
+            Self::builtin(Detector::HighEntropy, FilterAction::Ask),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NetworkConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relay_server_url: Option<String>,
+    /// Secret shared by the devices of one relay account. Unset uses this
+    /// device's own account. Not part of the TypeScript config.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relay_account: Option<String>,
+    /// TCP port for P2P connections
+    pub p2p_port: u16,
+    /// UDP port for device discovery. Not part of the TypeScript config.
+    pub discovery_port: u16,
+    pub discovery_enabled: bool,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            relay_server_url: None,
+            relay_account: None,
+            p2p_port: 7879,
+            discovery_port: 7878,
+            discovery_enabled: true,
+        }
+    }
+}
+
+/// Global shortcuts of the desktop app, e.g. "CmdOrCtrl+Alt+S". An empty
+/// string disables one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HotkeyConfig {
+    pub enabled: bool,
+    /// Send the clipboard to `device`, or to every device
+    pub push: String,
+    /// Copy the latest item from `device`, or from any other device
+    pub pull: String,
+    pub toggle_sync: String,
+    pub history_picker: String,
+    /// Device id or name for `push` and `pull`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+}
+
+impl Default for HotkeyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            push: "CmdOrCtrl+Alt+S".to_string(),
+            pull: "CmdOrCtrl+Alt+G".to_string(),
+            toggle_sync: "CmdOrCtrl+Alt+P".to_string(),
+            history_picker: "CmdOrCtrl+Alt+H".to_string(),
+            device: None,
+        }
+    }
+}
+
+impl AppConfig {
+    /// A copy without secrets, for events and the UI
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        config.network.relay_account = None;
+        config
+    }
+
+    /// Check that all values are usable
+    pub fn validate(&self) -> Result<()> {
+        if self.general.history_size == 0 || self.general.history_size > MAX_HISTORY_SIZE {
+            return Err(ConfigError::Invalid(format!(
+                "general.historySize must be between 1 and {}",
+                MAX_HISTORY_SIZE
+            )));
+        }
+
+        if self.general.max_age_days == Some(0) {
+            return Err(ConfigError::Invalid(
+                "general.maxAgeDays must be at least 1".to_string(),
+            ));
+        }
+
+        if self.general.max_total_bytes == Some(0) {
+            return Err(ConfigError::Invalid(
+                "general.maxTotalBytes must be at least 1".to_string(),
+            ));
+        }
+
+        if self.sync.max_item_size == 0 || self.sync.max_item_size > MAX_ITEM_SIZE_LIMIT {
+            return Err(ConfigError::Invalid(format!(
+                "sync.maxItemSize must be between 1 and {} bytes",
+                MAX_ITEM_SIZE_LIMIT
+            )));
+        }
+
+        if self.network.p2p_port == 0 || self.network.discovery_port == 0 {
+            return Err(ConfigError::Invalid(
+                "network ports must not be 0".to_string(),
+            ));
+        }
+
+        for rule in &self.security.sensitive_rules {
+            match (&rule.detector, &rule.pattern) {
+                (Detector::Regex, Some(pattern)) => {
+                    if let Err(e) = regex::Regex::new(pattern) {
+                        return Err(ConfigError::Invalid(format!(
+                            "security.sensitiveRules: bad pattern {:?}: {}",
+                            pattern, e
+                        )));
+                    }
+                }
+                (Detector::Regex, None) => {
+                    return Err(ConfigError::Invalid(
+                        "security.sensitiveRules: regex rules need a pattern".to_string(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        match &self.network.relay_server_url {
+            Some(url) if !(url.starts_with("ws://") || url.starts_with("wss://")) => {
+                return Err(ConfigError::Invalid(
+                    "network.relayServerUrl must be a ws:// or wss:// URL".to_string(),
+                ));
+            }
+            None if self.sync.mode != SyncMode::P2p => {
+                return Err(ConfigError::Invalid(
+                    "network.relayServerUrl is required for cloud and hybrid mode".to_string(),
+                ));
+            }
+            _ => {}
+        }
+
+        if self
+            .network
+            .relay_account
+            .as_ref()
+            .is_some_and(|secret| secret.trim().len() < MIN_RELAY_ACCOUNT_LEN)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "network.relayAccount must be at least {} characters",
+                MIN_RELAY_ACCOUNT_LEN
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Called with the new config after every change. Listeners run while the
+/// store is locked and must not lock it again.
+pub type ConfigCallback = Arc<dyn Fn(&AppConfig) + Send + Sync>;
+
+/// The app configuration, persisted as TOML
+pub struct ConfigStore {
+    path: PathBuf,
+    config: AppConfig,
+    modified: Option<SystemTime>,
+    listeners: Vec<ConfigCallback>,
+}
+
+impl ConfigStore {
+    /// Load the config from `path`. A missing file is created with defaults.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let config = match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let config = AppConfig::default();
+                Self::write(&path, &config)?;
+                config
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let modified = Self::modified(&path);
+        Ok(Self {
+            path,
+            config,
+            modified,
+            listeners: Vec::new(),
+        })
+    }
+
+    pub fn get(&self) -> &AppConfig {
+        &self.config
+    }
+
+    /// Register a listener for config changes
+    pub fn subscribe(&mut self, listener: ConfigCallback) {
+        self.listeners.push(listener);
+    }
+
+    /// Validate and save a new config
+    pub fn update(&mut self, config: AppConfig) -> Result<()> {
+        config.validate()?;
+        Self::write(&self.path, &config)?;
+        self.modified = Self::modified(&self.path);
+        self.apply(config);
+        Ok(())
+    }
+
+    /// Pick up edits made to the config file by hand. An invalid file is
+    /// logged and the current config kept.
+    pub fn reload(&mut self) -> Result<()> {
+        let modified = Self::modified(&self.path);
+        if modified == self.modified {
+            return Ok(());
+        }
+        self.modified = modified;
+
+        let config = Self::parse(&fs::read_to_string(&self.path)?)?;
+        if config != self.config {
+            log::info!("Reloaded config from {}", self.path.display());
+            self.apply(config);
+        }
+        Ok(())
+    }
+
+    /// Re-read the config file even if it looks unchanged, e.g. on SIGHUP
+    pub fn reload_now(&mut self) -> Result<()> {
+        self.modified = None;
+        self.reload()
+    }
+
+    /// Poll the config file for edits until the store is dropped
+    pub fn watch_file(store: Weak<Mutex<ConfigStore>>) {
+        thread::spawn(move || loop {
+            thread::sleep(WATCH_INTERVAL);
+
+            let Some(store) = store.upgrade() else {
+                break;
+            };
+            let result = store.lock().reload();
+            if let Err(e) = result {
+                log::warn!("Ignoring config file change: {}", e);
+            }
+        });
+    }
+
+    fn apply(&mut self, config: AppConfig) {
+        self.config = config;
+        for listener in &self.listeners {
+            listener(&self.config);
+        }
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    fn parse(text: &str) -> Result<AppConfig> {
+        let config: AppConfig =
+            toml::from_str(text).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn write(path: &Path, config: &AppConfig) -> Result<()> {
+        let text = toml::to_string_pretty(config).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // The file may hold the relay account secret: create it private to
+        // this user and swap it in whole
+        let tmp = path.with_extension("toml.tmp");
+        match fs::remove_file(&tmp) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_are_valid_and_round_trip() {
+        let config = AppConfig::default();
+        config.validate().unwrap();
+
+        let text = toml::to_string_pretty(&config).unwrap();
+        assert!(text.contains("p2pPort = 7879"));
+        assert_eq!(ConfigStore::parse(&text).unwrap(), config);
+    }
+
+    #[test]
+    fn test_partial_file_uses_defaults() {
+        let config = ConfigStore::parse("[general]\nhistorySize = 500\n").unwrap();
+        assert_eq!(config.general.history_size, 500);
+        assert_eq!(config.general.max_age_days, None);
+        assert_eq!(config.network, NetworkConfig::default());
+
+        let config =
+            ConfigStore::parse("[general]\nmaxAgeDays = 30\nmaxTotalBytes = 1048576\n").unwrap();
+        assert_eq!(config.general.max_age_days, Some(30));
+        assert_eq!(config.general.max_total_bytes, Some(1048576));
+
+        let config = ConfigStore::parse("[security.confirmIncoming]\nenabled = true\n").unwrap();
+        assert!(config.security.confirm_incoming.enabled);
+        assert_eq!(config.security.confirm_incoming.auto_apply_secs, 0);
+        assert_eq!(
+            config.security.sensitive_rules,
+            SecurityConfig::default().sensitive_rules
+        );
+
+        let config = ConfigStore::parse("[hotkeys]\npull = \"\"\ndevice = \"laptop\"\n").unwrap();
+        assert_eq!(config.hotkeys.pull, "");
+        assert_eq!(config.hotkeys.device.as_deref(), Some("laptop"));
+        assert_eq!(config.hotkeys.push, HotkeyConfig::default().push);
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut config = AppConfig::default();
+        config.general.history_size = 5000;
+        assert!(config.validate().is_err());
+
+        let mut config = AppConfig::default();
+        config.general.max_age_days = Some(0);
+        assert!(config.validate().is_err());
+
+        let mut config = AppConfig::default();
+        config.sync.mode = SyncMode::Hybrid;
+        assert!(config.validate().is_err());
+
+        config.network.relay_server_url = Some("https://relay.example.com".to_string());
+        assert!(config.validate().is_err());
+
+        config.network.relay_server_url = Some("wss://relay.example.com".to_string());
+        config.validate().unwrap();
+
+        config.network.relay_account = Some("short".to_string());
+        assert!(config.validate().is_err());
+
+        let mut config = AppConfig::default();
+        config.security.sensitive_rules.push(SensitiveRule {
+            name: Some("Ticket".to_string()),
+            detector: Detector::Regex,
+            pattern: Some("TICKET-(".to_string()),
+            action: FilterAction::Block,
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_update_and_reload_notify_listeners() {
+        let dir = std::env::temp_dir().join(format!("clipbridge-config-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("config.toml");
+        let mut store = ConfigStore::load(path.clone()).unwrap();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        store.subscribe(Arc::new(move |config: &AppConfig| {
+            sink.lock().push(config.general.history_size)
+        }));
+
+        let mut config = store.get().clone();
+        config.general.history_size = 20;
+        store.update(config).unwrap();
+
+        // Invalid edits are rejected and the current config kept
+        fs::write(&path, "[general]\nhistorySize = 0\n").unwrap();
+        store.modified = None;
+        assert!(store.reload().is_err());
+        assert_eq!(store.get().general.history_size, 20);
+
+        fs::write(&path, "[general]\nhistorySize = 30\n").unwrap();
+        store.modified = None;
+        store.reload().unwrap();
+
+        assert_eq!(*seen.lock(), vec![20, 30]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_secret_is_written_privately_and_redacted() {
+        let dir = std::env::temp_dir().join(format!("clipbridge-config-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("config.toml");
+        let mut store = ConfigStore::load(path.clone()).unwrap();
+
+        let mut config = store.get().clone();
+        config.network.relay_account = Some("0123456789abcdef0123".to_string());
+        store.update(config.clone()).unwrap();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("0123456789abcdef0123"));
+        assert!(!path.with_extension("toml.tmp").exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let redacted = store.get().redacted();
+        assert_eq!(redacted.network.relay_account, None);
+        assert_eq!(redacted.general, config.general);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
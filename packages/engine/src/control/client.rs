@@ -0,0 +1,71 @@
+use super::{token_path, Request, Response};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+/// An authenticated connection to a running instance's control socket
+pub struct ControlClient {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    /// Events read while waiting for a response
+    events: VecDeque<Value>,
+}
+
+impl ControlClient {
+    /// Connect and authenticate with the token next to the socket
+    pub fn connect(path: &Path) -> io::Result<Self> {
+        let token = fs::read_to_string(token_path(path))?;
+        let stream = UnixStream::connect(path)?;
+        let mut client = Self {
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+            events: VecDeque::new(),
+        };
+
+        match client.request(&Request::Auth {
+            token: token.trim().to_string(),
+        })? {
+            Response::Ok(_) => Ok(client),
+            Response::Error(e) => Err(io::Error::new(io::ErrorKind::PermissionDenied, e)),
+        }
+    }
+
+    /// Send a request and wait for its response
+    pub fn request(&mut self, request: &Request) -> io::Result<Response> {
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+
+        loop {
+            let value = self.read_value()?;
+            if value.get("event").is_some() {
+                self.events.push_back(value);
+            } else {
+                return Ok(serde_json::from_value(value)?);
+            }
+        }
+    }
+
+    /// Wait for the next event after `Request::Subscribe`, as
+    /// `{"event": "<name>", "payload": ...}`
+    pub fn next_event(&mut self) -> io::Result<Value> {
+        match self.events.pop_front() {
+            Some(event) => Ok(event),
+            None => self.read_value(),
+        }
+    }
+
+    fn read_value(&mut self) -> io::Result<Value> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The control socket closed the connection",
+            ));
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+}
@@ -0,0 +1,171 @@
+//! Local control socket for scripting a running app or daemon.
+//!
+//! Clients send one JSON `Request` per line and get one JSON `Response`
+//! line back. The first request must be `Auth` with the token the server
+//! writes next to the socket (see `token_path`). After `Subscribe`, events
+//! are written to the connection as they happen, interleaved with
+//! responses. Only Unix domain sockets are supported.
+
+#[cfg(unix)]
+mod client;
+#[cfg(unix)]
+mod server;
+
+#[cfg(unix)]
+pub use client::ControlClient;
+#[cfg(unix)]
+pub use server::ControlServer;
+
+use crate::network::LinkPath;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+/// Default number of items listed by `History`
+pub const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "camelCase")]
+pub enum Request {
+    /// Prove the client can read the token file
+    Auth {
+        token: String,
+    },
+    StartSync,
+    StopSync,
+    /// This device's id and whether sync is running
+    Status,
+    /// Put text on this device's clipboard; it syncs like any copy
+    Copy {
+        text: String,
+    },
+    /// Send text to peers without touching the local clipboard. `to`
+    /// holds device ids or names; empty sends to every peer.
+    Send {
+        text: String,
+        #[serde(default)]
+        to: Vec<String>,
+    },
+    /// The clipboard text, or the latest item from the device `from`
+    Paste {
+        #[serde(default)]
+        from: Option<String>,
+    },
+    /// Connected devices
+    Devices,
+    /// Items from peers waiting to be applied or discarded
+    Inbox,
+    /// Apply an inbox item to the clipboard, or the newest one
+    Accept {
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Drop an inbox item, or the newest one
+    Discard {
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Recent history, best matches first if `grep` is given
+    History {
+        #[serde(default)]
+        grep: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    /// Receive `Event`s by name, e.g. "sync-state-changed"; empty for all
+    Subscribe {
+        #[serde(default)]
+        events: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Response {
+    Ok(serde_json::Value),
+    Error(String),
+}
+
+/// A connected device as listed by `Devices`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceInfo {
+    pub device_id: String,
+    pub name: Option<String>,
+    pub path: LinkPath,
+    pub online: bool,
+}
+
+/// Where the app and daemon listen unless told otherwise:
+/// `$XDG_RUNTIME_DIR/clipbridge.sock`, or a socket in a private per-user
+/// directory in the temp dir, created if needed
+pub fn default_socket_path() -> io::Result<PathBuf> {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir).join("clipbridge.sock")),
+        _ => Ok(private_dir(&env::temp_dir())?.join("clipbridge.sock")),
+    }
+}
+
+/// `clipbridge-<uid>` in `base`, which other users can write to. Someone
+/// else may have created it first, so it is only used if we own it and
+/// nobody else can get in.
+#[cfg(unix)]
+fn private_dir(base: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    // SAFETY: getuid has no preconditions and cannot fail
+    let uid = unsafe { libc::getuid() };
+    let dir = base.join(format!("clipbridge-{}", uid));
+    if let Err(e) = fs::DirBuilder::new().mode(0o700).create(&dir) {
+        if e.kind() != io::ErrorKind::AlreadyExists {
+            return Err(e);
+        }
+    }
+
+    // Not following links, which could point at another user's directory
+    let metadata = fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not a private directory owned by this user",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(dir)
+}
+
+#[cfg(not(unix))]
+fn private_dir(base: &Path) -> io::Result<PathBuf> {
+    Ok(base.to_path_buf())
+}
+
+/// The file holding the token for the socket at `socket`. Like the socket,
+/// only the user running the instance can read it.
+pub fn token_path(socket: &Path) -> PathBuf {
+    socket.with_extension("token")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_private_dir_is_created_and_checked() {
+        let base = env::temp_dir().join(format!("clipbridge-control-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&base).unwrap();
+
+        let dir = private_dir(&base).unwrap();
+        let mode = fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        assert_eq!(private_dir(&base).unwrap(), dir);
+
+        // A directory others can enter is refused
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(private_dir(&base).is_err());
+
+        fs::remove_dir_all(base).unwrap();
+    }
+}
@@ -0,0 +1,404 @@
+use super::{token_path, Request, Response, DEFAULT_HISTORY_LIMIT};
+use crate::clipboard;
+use crate::events::EventBus;
+use crate::history::SearchQuery;
+use crate::service::Service;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+/// How long an event write may block on a client that is not reading
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Random bytes in the auth token
+const TOKEN_LEN: usize = 32;
+
+/// Serves control requests on a Unix socket until dropped
+pub struct ControlServer {
+    path: PathBuf,
+    is_running: Arc<Mutex<bool>>,
+}
+
+/// Per-connection state
+struct Connection {
+    service: Weak<Service>,
+    token: Arc<String>,
+    writer: Arc<Mutex<UnixStream>>,
+    authenticated: bool,
+    subscription: Option<(Arc<EventBus>, u64)>,
+}
+
+impl ControlServer {
+    /// Listen on `path`, readable by the current user only, and write a new
+    /// token to `token_path(path)`. A socket file left behind by an instance
+    /// that is no longer running is replaced.
+    pub fn start(path: PathBuf, service: Weak<Service>) -> io::Result<Self> {
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("Another instance is listening on {}", path.display()),
+                ));
+            }
+            fs::remove_file(&path)?;
+        }
+
+        let token = Arc::new(Self::write_token(&token_path(&path))?);
+        let listener = UnixListener::bind(&path)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        listener.set_nonblocking(true)?;
+
+        let is_running = Arc::new(Mutex::new(true));
+        let is_running_clone = Arc::clone(&is_running);
+        let display = path.display().to_string();
+
+        thread::spawn(move || {
+            log::info!("Control socket listening on {}", display);
+
+            while *is_running_clone.lock() {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let service = service.clone();
+                        let token = Arc::clone(&token);
+                        thread::spawn(move || {
+                            if let Err(e) = Self::handle_connection(stream, service, token) {
+                                log::debug!("Control connection closed: {}", e);
+                            }
+                        });
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(ACCEPT_INTERVAL);
+                    }
+                    Err(e) => {
+                        log::error!("Error accepting control connection: {}", e);
+                        thread::sleep(ACCEPT_INTERVAL);
+                    }
+                }
+            }
+
+            log::info!("Control socket {} closed", display);
+        });
+
+        Ok(Self { path, is_running })
+    }
+
+    /// Write a random token readable by the current user only
+    fn write_token(path: &Path) -> io::Result<String> {
+        let mut bytes = [0u8; TOKEN_LEN];
+        OsRng.fill_bytes(&mut bytes);
+        let token = hex::encode(bytes);
+
+        let _ = fs::remove_file(path);
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?
+            .write_all(token.as_bytes())?;
+        Ok(token)
+    }
+
+    fn handle_connection(
+        stream: UnixStream,
+        service: Weak<Service>,
+        token: Arc<String>,
+    ) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        // A subscriber that stops reading must not stall the event source
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let mut connection = Connection {
+            service,
+            token,
+            writer: Arc::new(Mutex::new(stream.try_clone()?)),
+            authenticated: false,
+            subscription: None,
+        };
+
+        let result = connection.serve(stream);
+        if let Some((events, id)) = connection.subscription.take() {
+            events.unsubscribe(id);
+        }
+        result
+    }
+}
+
+impl Connection {
+    fn serve(&mut self, stream: UnixStream) -> io::Result<()> {
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => {
+                    let Some(service) = self.service.upgrade() else {
+                        break;
+                    };
+                    match self.handle(&service, request) {
+                        Ok(value) => Response::Ok(value),
+                        Err(e) => Response::Error(e),
+                    }
+                }
+                Err(e) => Response::Error(format!("Invalid request: {}", e)),
+            };
+            write_line(&self.writer, &response)?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, service: &Service, request: Request) -> Result<Value, String> {
+        match request {
+            Request::Auth { token } => {
+                if !tokens_match(&token, &self.token) {
+                    return Err("Invalid token".to_string());
+                }
+                self.authenticated = true;
+                Ok(Value::Null)
+            }
+            _ if !self.authenticated => Err("Not authenticated".to_string()),
+            Request::Subscribe { events } => {
+                if let Some((bus, id)) = self.subscription.take() {
+                    bus.unsubscribe(id);
+                }
+
+                let writer = Arc::clone(&self.writer);
+                let id = service.events.subscribe(move |event| {
+                    if events.is_empty() || events.iter().any(|name| name == event.name()) {
+                        if let Err(e) = write_line(&writer, event) {
+                            log::debug!("Dropping event for control client: {}", e);
+                        }
+                    }
+                });
+                self.subscription = Some((Arc::clone(&service.events), id));
+                Ok(Value::Null)
+            }
+            request => handle(service, request),
+        }
+    }
+}
+
+fn write_line<T: Serialize>(writer: &Mutex<UnixStream>, value: &T) -> io::Result<()> {
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    writer.lock().write_all(line.as_bytes())
+}
+
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        *self.is_running.lock() = false;
+        let _ = fs::remove_file(&self.path);
+        let _ = fs::remove_file(token_path(&self.path));
+    }
+}
+
+/// Requests that need no connection state
+fn handle(service: &Service, request: Request) -> Result<Value, String> {
+    match request {
+        Request::Auth { .. } | Request::Subscribe { .. } => {
+            unreachable!("handled by the connection")
+        }
+        Request::StartSync => service.start_sync().map(|_| Value::Null),
+        Request::StopSync => {
+            service.stop_sync();
+            Ok(Value::Null)
+        }
+        Request::Status => Ok(json!({
+            "deviceId": service.device_id,
+            "syncing": *service.is_syncing.lock(),
+        })),
+        Request::Copy { text } => {
+            clipboard::set_text(&text).map_err(|e| e.to_string())?;
+            Ok(Value::Null)
+        }
+        Request::Send { text, to } => {
+            let sent = service.send_text(text, &to)?;
+            Ok(json!({ "held": !sent }))
+        }
+        Request::Paste { from: None } => clipboard::get_text()
+            .map(Value::String)
+            .map_err(|e| e.to_string()),
+        Request::Paste { from: Some(name) } => service
+            .latest_item_from(Some(&name))?
+            .map(|item| Value::String(item.content))
+            .ok_or_else(|| format!("No items from {}", name)),
+        Request::Devices => to_value(service.devices()),
+        Request::Inbox => to_value(service.inbox()),
+        Request::Accept { id } => resolve_inbox(service, id, true),
+        Request::Discard { id } => resolve_inbox(service, id, false),
+        Request::History { grep, limit } => {
+            let limit = limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+            let history = service.history.lock();
+            let items = match grep {
+                Some(text) => history
+                    .search(&SearchQuery {
+                        text,
+                        limit: Some(limit),
+                        ..SearchQuery::default()
+                    })
+                    .map(|results| results.into_iter().map(|result| result.item).collect()),
+                None => history.list(limit, 0),
+            }
+            .map_err(|e| e.to_string())?;
+            to_value(items)
+        }
+    }
+}
+
+/// Accept or discard the inbox item `id`, or the newest one
+fn resolve_inbox(service: &Service, id: Option<String>, accept: bool) -> Result<Value, String> {
+    let id = match id {
+        Some(id) => id,
+        None => match service.inbox().pop() {
+            Some(item) => item.id,
+            None => return Err("The inbox is empty".to_string()),
+        },
+    };
+    if service.resolve_inbox(&id, accept)? {
+        Ok(Value::Null)
+    } else {
+        Err(format!("No item {} in the inbox", id))
+    }
+}
+
+fn to_value<T: serde::Serialize>(value: T) -> Result<Value, String> {
+    serde_json::to_value(value).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::ControlClient;
+    use crate::events::Event;
+    use crate::history::HistoryItem;
+
+    #[test]
+    fn test_serves_requests_over_the_socket() {
+        let dir = std::env::temp_dir().join(format!("clipbridge-control-{}", uuid::Uuid::new_v4()));
+        let service = Arc::new(Service::open(dir.join("config.toml"), &dir.join("data")).unwrap());
+        service
+            .peers
+            .lock()
+            .update("desktop-1234", |peer| {
+                peer.name = Some("Desktop".to_string())
+            })
+            .unwrap();
+        for (id, device, content) in [
+            ("1", "desktop-1234", "make logs"),
+            ("2", "laptop-5678", "cargo test"),
+        ] {
+            let item = HistoryItem::text(
+                id.to_string(),
+                device.to_string(),
+                false,
+                content.to_string(),
+                id.parse().unwrap(),
+            );
+            service.history.lock().insert(&item).unwrap();
+        }
+
+        let path = dir.join("control.sock");
+        let server = ControlServer::start(path.clone(), Arc::downgrade(&service)).unwrap();
+        // A second instance must not take over the socket
+        assert!(ControlServer::start(path.clone(), Arc::downgrade(&service)).is_err());
+
+        // Requests are refused until the client proves it can read the token
+        let mut raw = UnixStream::connect(&path).unwrap();
+        writeln!(raw, r#"{{"command":"status"}}"#).unwrap();
+        let mut reply = String::new();
+        BufReader::new(&raw).read_line(&mut reply).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Response>(&reply).unwrap(),
+            Response::Error("Not authenticated".to_string())
+        );
+        writeln!(raw, r#"{{"command":"auth","token":"guess"}}"#).unwrap();
+        reply.clear();
+        BufReader::new(&raw).read_line(&mut reply).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Response>(&reply).unwrap(),
+            Response::Error("Invalid token".to_string())
+        );
+
+        let mut client = ControlClient::connect(&path).unwrap();
+        let history = client
+            .request(&Request::History {
+                grep: Some("logs".to_string()),
+                limit: None,
+            })
+            .unwrap();
+        let Response::Ok(Value::Array(items)) = history else {
+            panic!("unexpected response {:?}", history);
+        };
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["id"], "1");
+
+        assert_eq!(
+            client
+                .request(&Request::Paste {
+                    from: Some("desktop".to_string())
+                })
+                .unwrap(),
+            Response::Ok(Value::String("make logs".to_string()))
+        );
+        assert_eq!(
+            client.request(&Request::Devices).unwrap(),
+            Response::Ok(json!([]))
+        );
+        assert_eq!(
+            client
+                .request(&Request::Send {
+                    text: "hi".to_string(),
+                    to: Vec::new()
+                })
+                .unwrap(),
+            Response::Error("Sync is not running".to_string())
+        );
+
+        assert_eq!(
+            client.request(&Request::Accept { id: None }).unwrap(),
+            Response::Error("The inbox is empty".to_string())
+        );
+
+        assert_eq!(
+            client
+                .request(&Request::Subscribe {
+                    events: vec!["sync-state-changed".to_string()]
+                })
+                .unwrap(),
+            Response::Ok(Value::Null)
+        );
+        service.events.emit(Event::RelayDevicesChanged(Vec::new()));
+        service.events.emit(Event::SyncStateChanged {
+            syncing: true,
+            error: None,
+        });
+        assert_eq!(
+            client.next_event().unwrap(),
+            json!({ "event": "sync-state-changed", "payload": { "syncing": true } })
+        );
+
+        drop(server);
+        assert!(!path.exists());
+        assert!(!token_path(&path).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+use crate::config::AppConfig;
+use crate::control::DeviceInfo;
+use crate::network::RelayDevice;
+use crate::sync::{HeldItem, InboxItem};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Characters of an item included in `ClipboardChanged`
+pub const PREVIEW_LEN: usize = 80;
+
+/// Something that happened in a running instance. Serialized as
+/// `{"event": "<name>", "payload": ...}`; the desktop app emits each one as
+/// a Tauri event of the same name.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", content = "payload", rename_all = "kebab-case")]
+pub enum Event {
+    #[serde(rename_all = "camelCase")]
+    SyncStateChanged {
+        syncing: bool,
+        /// Why sync stopped, if it failed to start or restart
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// An item was copied here, or one from a peer was applied
+    #[serde(rename_all = "camelCase")]
+    ClipboardChanged {
+        id: String,
+        source: ChangeSource,
+        origin_device: String,
+        /// The origin device's name, if the user gave it one
+        device_name: Option<String>,
+        preview: String,
+    },
+    /// A device became reachable, or is now reached over another path
+    DeviceConnected(DeviceInfo),
+    DeviceDisconnected(DeviceInfo),
+    /// A device with no settings here made contact
+    #[serde(rename_all = "camelCase")]
+    PairingRequested {
+        device_id: String,
+    },
+    /// History backfill from a peer: items stored so far, and whether the
+    /// last page arrived
+    #[serde(rename_all = "camelCase")]
+    TransferProgress {
+        device_id: String,
+        received: usize,
+        complete: bool,
+    },
+    /// An item from a peer was put in the inbox instead of being applied
+    ItemReceived(InboxItem),
+    /// Items were added to or removed from the inbox
+    InboxChanged(Vec<InboxItem>),
+    /// The new config, without the relay account secret
+    ConfigChanged(AppConfig),
+    SensitiveItemHeld(HeldItem),
+    /// The sensitive filter kept a local item from being stored or sent
+    SensitiveItemBlocked {
+        rules: Vec<String>,
+    },
+    RelayDevicesChanged(Vec<RelayDevice>),
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::SyncStateChanged { .. } => "sync-state-changed",
+            Event::ClipboardChanged { .. } => "clipboard-changed",
+            Event::DeviceConnected(_) => "device-connected",
+            Event::DeviceDisconnected(_) => "device-disconnected",
+            Event::PairingRequested { .. } => "pairing-requested",
+            Event::TransferProgress { .. } => "transfer-progress",
+            Event::ItemReceived(_) => "item-received",
+            Event::InboxChanged(_) => "inbox-changed",
+            Event::ConfigChanged(_) => "config-changed",
+            Event::SensitiveItemHeld(_) => "sensitive-item-held",
+            Event::SensitiveItemBlocked { .. } => "sensitive-item-blocked",
+            Event::RelayDevicesChanged(_) => "relay-devices-changed",
+        }
+    }
+}
+
+/// Where a clipboard change came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeSource {
+    Local,
+    Remote,
+}
+
+/// The first `max_chars` characters of `content` on a single line
+pub fn preview(content: &str, max_chars: usize) -> String {
+    let line: String = content
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(max_chars)
+        .collect();
+    if content.chars().count() > max_chars {
+        format!("{}…", line.trim_end())
+    } else {
+        line.trim_end().to_string()
+    }
+}
+
+pub type EventCallback = Arc<dyn Fn(&Event) + Send + Sync>;
+
+/// Fans events out to subscribers
+#[derive(Default)]
+pub struct EventBus {
+    next_id: AtomicU64,
+    listeners: Mutex<Vec<(u64, EventCallback)>>,
+}
+
+impl EventBus {
+    /// Register a listener. Returns an id for `unsubscribe`.
+    pub fn subscribe<F>(&self, listener: F) -> u64
+    where
+        F: Fn(&Event) + Send + Sync + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.listeners.lock().push((id, Arc::new(listener)));
+        id
+    }
+
+    pub fn unsubscribe(&self, id: u64) {
+        self.listeners
+            .lock()
+            .retain(|(listener_id, _)| *listener_id != id);
+    }
+
+    /// Call every listener. Listeners may subscribe or unsubscribe, but may
+    /// run with the config store locked and must not lock it.
+    pub fn emit(&self, event: Event) {
+        let listeners: Vec<EventCallback> = self
+            .listeners
+            .lock()
+            .iter()
+            .map(|(_, listener)| Arc::clone(listener))
+            .collect();
+        for listener in listeners {
+            listener(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listeners_get_events_until_unsubscribed() {
+        let bus = EventBus::default();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let id = bus.subscribe(move |event| sink.lock().push(event.name()));
+
+        bus.emit(Event::SyncStateChanged {
+            syncing: true,
+            error: None,
+        });
+        bus.unsubscribe(id);
+        bus.emit(Event::SyncStateChanged {
+            syncing: false,
+            error: None,
+        });
+
+        assert_eq!(*seen.lock(), vec!["sync-state-changed"]);
+        assert_eq!(
+            serde_json::to_value(Event::SyncStateChanged {
+                syncing: true,
+                error: None,
+            })
+            .unwrap(),
+            serde_json::json!({ "event": "sync-state-changed", "payload": { "syncing": true } })
+        );
+    }
+
+    #[test]
+    fn test_preview_is_one_short_line() {
+        assert_eq!(
+            preview("cargo test\n  --workspace\n", PREVIEW_LEN),
+            "cargo test   --workspace"
+        );
+        assert_eq!(preview("cargo test", 5), "cargo…");
+    }
+}
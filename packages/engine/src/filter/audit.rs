@@ -0,0 +1,166 @@
+use crate::config::FilterAction;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+/// Entries kept in memory and on disk
+const MAX_ENTRIES: usize = 1000;
+
+/// How the filter (or the user) dealt with an item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditDecision {
+    Blocked,
+    Redacted,
+    /// Held for the user to decide
+    Held,
+    Approved,
+    Rejected,
+    /// Dropped from the held queue without a decision
+    Expired,
+}
+
+/// One filter decision. Never contains the clipboard content.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    pub item_id: String,
+    pub rules: Vec<String>,
+    pub action: FilterAction,
+    pub decision: AuditDecision,
+}
+
+impl AuditEntry {
+    pub fn new(
+        item_id: String,
+        rules: Vec<String>,
+        action: FilterAction,
+        decision: AuditDecision,
+    ) -> Self {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            item_id,
+            rules,
+            action,
+            decision,
+        }
+    }
+}
+
+/// Append-only log of filter decisions, stored as JSON lines
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    entries: VecDeque<AuditEntry>,
+}
+
+impl AuditLog {
+    /// Open the log at `path`, keeping only the newest entries
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let mut entries = VecDeque::new();
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    match serde_json::from_str(&line?) {
+                        Ok(entry) => entries.push_back(entry),
+                        Err(e) => log::warn!("Skipping bad audit log line: {}", e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut log = Self {
+            path: Some(path),
+            entries,
+        };
+        if log.entries.len() > MAX_ENTRIES {
+            log.entries.drain(..log.entries.len() - MAX_ENTRIES);
+            log.rewrite()?;
+        }
+        Ok(log)
+    }
+
+    /// A log that is never written to disk
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, entry: AuditEntry) {
+        log::info!(
+            "Sensitive filter: {:?} item {} ({})",
+            entry.decision,
+            entry.item_id,
+            entry.rules.join(", ")
+        );
+
+        if let Err(e) = self.append(&entry) {
+            log::error!("Failed to write audit log: {}", e);
+        }
+        self.entries.push_back(entry);
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+    }
+
+    /// The newest entries, newest first
+    pub fn recent(&self, limit: usize) -> Vec<AuditEntry> {
+        self.entries.iter().rev().take(limit).cloned().collect()
+    }
+
+    fn append(&self, entry: &AuditEntry) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let line = serde_json::to_string(entry).map_err(io::Error::other)?;
+        writeln!(file, "{}", line)
+    }
+
+    fn rewrite(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut text = String::new();
+        for entry in &self.entries {
+            text.push_str(&serde_json::to_string(entry).map_err(io::Error::other)?);
+            text.push('\n');
+        }
+        fs::write(path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_persist_across_opens() {
+        let path = std::env::temp_dir().join(format!("clipbridge-audit-{}", uuid::Uuid::new_v4()));
+        let mut log = AuditLog::open(path.clone()).unwrap();
+        for i in 0..3 {
+            log.record(AuditEntry {
+                timestamp: i,
+                item_id: i.to_string(),
+                rules: vec!["creditCard".to_string()],
+                action: FilterAction::Redact,
+                decision: AuditDecision::Redacted,
+            });
+        }
+
+        let reopened = AuditLog::open(path.clone()).unwrap();
+        let ids: Vec<_> = reopened.recent(2).into_iter().map(|e| e.item_id).collect();
+        assert_eq!(ids, vec!["2", "1"]);
+        fs::remove_file(path).unwrap();
+    }
+}
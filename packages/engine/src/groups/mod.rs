@@ -0,0 +1,271 @@
+use crate::history::ContentCipher;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Prefix of the invite strings used to join a group on another device
+const INVITE_PREFIX: &str = "clipbridge-group:";
+const KEY_INFO: &[u8] = b"clipbridge sync group v1";
+
+/// A named set of devices that share clipboard items under their own key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncGroup {
+    pub id: String,
+    pub name: String,
+    /// 32 random bytes, hex encoded. Shared only through invites.
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    key: String,
+    /// Device ids that have proven they hold the key
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+impl SyncGroup {
+    pub fn cipher(&self) -> io::Result<ContentCipher> {
+        let key = hex::decode(&self.key).map_err(io::Error::other)?;
+        Ok(ContentCipher::derive_for(&key, KEY_INFO))
+    }
+
+    pub fn is_member(&self, device_id: &str) -> bool {
+        self.members.iter().any(|m| m == device_id)
+    }
+
+    /// The group without its key, for the UI
+    pub fn public(&self) -> Self {
+        Self {
+            key: String::new(),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct GroupFile {
+    /// Group that locally copied items are shared in
+    active: Option<String>,
+    groups: Vec<SyncGroup>,
+}
+
+/// Sync groups persisted as JSON
+pub struct GroupStore {
+    path: Option<PathBuf>,
+    file: GroupFile,
+}
+
+impl GroupStore {
+    /// Load groups from `path`, starting empty if it doesn't exist
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let file = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(io::Error::other)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => GroupFile::default(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            file,
+        })
+    }
+
+    /// A store that is never written to disk
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            file: GroupFile::default(),
+        }
+    }
+
+    pub fn list(&self) -> &[SyncGroup] {
+        &self.file.groups
+    }
+
+    pub fn get(&self, id: &str) -> Option<&SyncGroup> {
+        self.file.groups.iter().find(|g| g.id == id)
+    }
+
+    /// The group locally copied items go to; `None` shares with all peers
+    pub fn active(&self) -> Option<&SyncGroup> {
+        self.file.active.as_deref().and_then(|id| self.get(id))
+    }
+
+    pub fn set_active(&mut self, id: Option<String>) -> io::Result<()> {
+        if let Some(id) = &id {
+            if self.get(id).is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Unknown group {}", id),
+                ));
+            }
+        }
+        self.file.active = id;
+        self.save()
+    }
+
+    /// Create a group with a fresh key and this device as its only member
+    pub fn create(&mut self, name: String, own_device_id: &str) -> io::Result<SyncGroup> {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+
+        let group = SyncGroup {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            key: hex::encode(key),
+            members: vec![own_device_id.to_string()],
+        };
+        self.file.groups.push(group.clone());
+        self.save()?;
+        Ok(group)
+    }
+
+    /// An invite string that lets another device join `id`
+    pub fn invite(&self, id: &str) -> Option<String> {
+        let group = self.get(id)?;
+        let invite = SyncGroup {
+            members: Vec::new(),
+            ..group.clone()
+        };
+        let json = serde_json::to_vec(&invite).ok()?;
+        Some(format!("{}{}", INVITE_PREFIX, hex::encode(json)))
+    }
+
+    /// Join the group described by an invite. Members are learned as they
+    /// prove they hold the key.
+    pub fn join(&mut self, invite: &str, own_device_id: &str) -> io::Result<SyncGroup> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "Invalid group invite");
+        let hex_json = invite
+            .trim()
+            .strip_prefix(INVITE_PREFIX)
+            .ok_or_else(invalid)?;
+        let json = hex::decode(hex_json).map_err(|_| invalid())?;
+        let mut group: SyncGroup = serde_json::from_slice(&json).map_err(|_| invalid())?;
+        if !hex::decode(&group.key).is_ok_and(|key| key.len() == 32) {
+            return Err(invalid());
+        }
+        group.members = vec![own_device_id.to_string()];
+
+        self.file.groups.retain(|g| g.id != group.id);
+        self.file.groups.push(group.clone());
+        self.save()?;
+        Ok(group)
+    }
+
+    pub fn leave(&mut self, id: &str) -> io::Result<()> {
+        self.file.groups.retain(|g| g.id != id);
+        if self.file.active.as_deref() == Some(id) {
+            self.file.active = None;
+        }
+        self.save()
+    }
+
+    /// Record that `device_id` holds the key of group `id`
+    pub fn add_member(&mut self, id: &str, device_id: &str) -> io::Result<()> {
+        let Some(group) = self.file.groups.iter_mut().find(|g| g.id == id) else {
+            return Ok(());
+        };
+        if group.is_member(device_id) {
+            return Ok(());
+        }
+        group.members.push(device_id.to_string());
+        self.save()
+    }
+
+    /// Ids of the groups `device_id` is a member of
+    pub fn groups_of(&self, device_id: &str) -> Vec<String> {
+        self.file
+            .groups
+            .iter()
+            .filter(|g| g.is_member(device_id))
+            .map(|g| g.id.clone())
+            .collect()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let bytes = serde_json::to_vec_pretty(&self.file).map_err(io::Error::other)?;
+
+        // The file holds group keys: create it private to this user and
+        // swap it in whole
+        let tmp = path.with_extension("json.tmp");
+        match fs::remove_file(&tmp) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invite_shares_the_key() {
+        let mut laptop = GroupStore::in_memory();
+        let group = laptop.create("ops".to_string(), "laptop").unwrap();
+        let invite = laptop.invite(&group.id).unwrap();
+
+        let mut desktop = GroupStore::in_memory();
+        let joined = desktop.join(&invite, "desktop").unwrap();
+        assert_eq!(joined.id, group.id);
+        assert_eq!(joined.members, vec!["desktop"]);
+
+        let sealed = group.cipher().unwrap().seal("deploy token");
+        let opened = joined.cipher().unwrap().open(&sealed);
+        assert_eq!(opened.as_deref(), Some("deploy token"));
+
+        assert!(desktop.join("clipbridge-group:zz", "desktop").is_err());
+    }
+
+    #[test]
+    fn test_membership_and_leaving() {
+        let mut store = GroupStore::in_memory();
+        let group = store.create("home".to_string(), "laptop").unwrap();
+        store.set_active(Some(group.id.clone())).unwrap();
+        store.add_member(&group.id, "desktop").unwrap();
+        assert_eq!(store.groups_of("desktop"), vec![group.id.clone()]);
+        assert!(store.groups_of("phone").is_empty());
+
+        store.leave(&group.id).unwrap();
+        assert!(store.active().is_none());
+        assert!(store.set_active(Some(group.id)).is_err());
+    }
+
+    #[test]
+    fn test_keys_are_written_privately_and_reloaded() {
+        let dir = std::env::temp_dir().join(format!("clipbridge-groups-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("groups.json");
+
+        let mut store = GroupStore::load(path.clone()).unwrap();
+        let group = store.create("ops".to_string(), "laptop").unwrap();
+        store.add_member(&group.id, "desktop").unwrap();
+        assert!(!path.with_extension("json.tmp").exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let reloaded = GroupStore::load(path).unwrap();
+        assert_eq!(reloaded.groups_of("desktop"), vec![group.id]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
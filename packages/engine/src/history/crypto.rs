@@ -0,0 +1,79 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+const NONCE_LEN: usize = 12;
+const KEY_INFO: &[u8] = b"clipbridge history content v1";
+
+/// Seals history content with AES-256-GCM. Stored values are the nonce
+/// followed by the ciphertext.
+pub struct ContentCipher {
+    cipher: Aes256Gcm,
+}
+
+impl ContentCipher {
+    /// Derive the history key from a device secret
+    pub fn derive(secret: &[u8]) -> Self {
+        Self::derive_for(secret, KEY_INFO)
+    }
+
+    /// Derive a key for another purpose, named by `info`
+    pub fn derive_for(secret: &[u8], info: &[u8]) -> Self {
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(None, secret)
+            .expand(info, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+        Self {
+            cipher: Aes256Gcm::new(&key.into()),
+        }
+    }
+
+    pub fn seal(&self, plaintext: &str) -> Vec<u8> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .expect("AES-GCM encryption does not fail for in-memory buffers");
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// Decrypt a sealed value. Returns `None` if it was sealed with a
+    /// different key or has been tampered with.
+    pub fn open(&self, sealed: &[u8]) -> Option<String> {
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let cipher = ContentCipher::derive(b"device secret");
+        let sealed = cipher.seal("api_key=hunter2");
+
+        assert!(!sealed.windows(7).any(|w| w == b"hunter2"));
+        assert_eq!(cipher.open(&sealed).as_deref(), Some("api_key=hunter2"));
+    }
+
+    #[test]
+    fn test_open_with_wrong_key_fails() {
+        let sealed = ContentCipher::derive(b"device a").seal("secret");
+        assert!(ContentCipher::derive(b"device b").open(&sealed).is_none());
+    }
+}
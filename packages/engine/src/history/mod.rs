@@ -0,0 +1,760 @@
+mod crypto;
+mod search;
+mod snippets;
+
+use rusqlite::types::{Type, Value, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+pub use crypto::ContentCipher;
+pub use search::{SearchQuery, SearchResult};
+
+/// MIME type of plain text items
+pub const TEXT_PLAIN: &str = "text/plain";
+
+/// Schema migrations, applied in order. `PRAGMA user_version` records how
+/// many have already run.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE IF NOT EXISTS history (
+        id            TEXT PRIMARY KEY,
+        origin_device TEXT NOT NULL,
+        is_local      INTEGER NOT NULL,
+        mime_types    TEXT NOT NULL,
+        content       TEXT NOT NULL,
+        size          INTEGER NOT NULL,
+        created_at    INTEGER NOT NULL,
+        stored_at     INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS history_stored_at ON history (stored_at);
+    ",
+    // Full-text index. Only text items up to 1 MiB have their content
+    // indexed; everything else is searchable by metadata alone.
+    "
+    ALTER TABLE history ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+
+    CREATE VIRTUAL TABLE history_fts USING fts5(text, metadata);
+
+    CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+        INSERT INTO history_fts (rowid, text, metadata) VALUES (
+            new.rowid,
+            CASE WHEN new.mime_types LIKE '%\"text/%' AND new.size <= 1048576
+                THEN new.content ELSE '' END,
+            new.origin_device || ' ' || new.mime_types
+        );
+    END;
+
+    CREATE TRIGGER history_fts_delete AFTER DELETE ON history BEGIN
+        DELETE FROM history_fts WHERE rowid = old.rowid;
+    END;
+
+    INSERT INTO history_fts (rowid, text, metadata)
+        SELECT rowid,
+            CASE WHEN mime_types LIKE '%\"text/%' AND size <= 1048576
+                THEN content ELSE '' END,
+            origin_device || ' ' || mime_types
+        FROM history;
+    ",
+    // Pinned items can be named, ordered and shared with peers
+    "
+    ALTER TABLE history ADD COLUMN name TEXT;
+    ALTER TABLE history ADD COLUMN pin_order INTEGER;
+    ALTER TABLE history ADD COLUMN shared INTEGER NOT NULL DEFAULT 0;
+    ",
+    // Sealed (encrypted) content is stored as a BLOB and never indexed
+    "
+    DROP TRIGGER history_fts_insert;
+
+    CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+        INSERT INTO history_fts (rowid, text, metadata) VALUES (
+            new.rowid,
+            CASE WHEN typeof(new.content) = 'text'
+                    AND new.mime_types LIKE '%\"text/%' AND new.size <= 1048576
+                THEN new.content ELSE '' END,
+            new.origin_device || ' ' || new.mime_types
+        );
+    END;
+    ",
+    // Items synced within a sync group remember it
+    "
+    ALTER TABLE history ADD COLUMN group_id TEXT;
+    ",
+    // How far history from each peer has been backfilled
+    "
+    CREATE TABLE backfill_cursors (
+        peer_id TEXT PRIMARY KEY,
+        since   INTEGER NOT NULL
+    );
+    ",
+];
+
+#[derive(Debug)]
+pub enum HistoryError {
+    Database(rusqlite::Error),
+    Serialization(serde_json::Error),
+    /// Sealed content could not be decrypted with the current key
+    Sealed,
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HistoryError::Database(e) => write!(f, "History database error: {}", e),
+            HistoryError::Serialization(e) => write!(f, "History serialization error: {}", e),
+            HistoryError::Sealed => write!(f, "History content could not be decrypted"),
+        }
+    }
+}
+
+impl Error for HistoryError {}
+
+impl From<rusqlite::Error> for HistoryError {
+    fn from(e: rusqlite::Error) -> Self {
+        HistoryError::Database(e)
+    }
+}
+
+impl From<serde_json::Error> for HistoryError {
+    fn from(e: serde_json::Error) -> Self {
+        HistoryError::Serialization(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, HistoryError>;
+
+/// A single clipboard item recorded in history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryItem {
+    pub id: String,
+    pub origin_device: String,
+    pub is_local: bool,
+    pub mime_types: Vec<String>,
+    pub content: String,
+    pub size: u64,
+    /// When the item was copied on the origin device (ms since epoch)
+    pub created_at: u64,
+    /// When the item was written to this device's history (ms since epoch)
+    pub stored_at: u64,
+    /// Pinned items are exempt from retention
+    pub pinned: bool,
+    /// Optional snippet name for pinned items
+    pub name: Option<String>,
+    /// Position among pinned items, lowest first
+    pub pin_order: Option<i64>,
+    /// Whether this pinned item is synced to peers as a shared snippet
+    pub shared: bool,
+    /// The sync group the item was shared in, if any
+    pub group_id: Option<String>,
+}
+
+impl HistoryItem {
+    /// Create a plain text history item
+    pub fn text(
+        id: String,
+        origin_device: String,
+        is_local: bool,
+        content: String,
+        created_at: u64,
+    ) -> Self {
+        Self {
+            id,
+            origin_device,
+            is_local,
+            mime_types: vec![TEXT_PLAIN.to_string()],
+            size: content.len() as u64,
+            content,
+            created_at,
+            stored_at: now_millis(),
+            pinned: false,
+            name: None,
+            pin_order: None,
+            shared: false,
+            group_id: None,
+        }
+    }
+
+    fn from_row(row: &Row, cipher: Option<&ContentCipher>) -> rusqlite::Result<Self> {
+        let mime_types: String = row.get("mime_types")?;
+        let content = match row.get_ref("content")? {
+            ValueRef::Blob(sealed) => cipher.and_then(|c| c.open(sealed)).ok_or_else(|| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    row.as_ref().column_index("content").unwrap_or_default(),
+                    Type::Blob,
+                    Box::new(HistoryError::Sealed),
+                )
+            })?,
+            _ => row.get("content")?,
+        };
+
+        Ok(Self {
+            id: row.get("id")?,
+            origin_device: row.get("origin_device")?,
+            is_local: row.get("is_local")?,
+            mime_types: serde_json::from_str(&mime_types).unwrap_or_default(),
+            content,
+            size: row.get::<_, i64>("size")? as u64,
+            created_at: row.get::<_, i64>("created_at")? as u64,
+            stored_at: row.get::<_, i64>("stored_at")? as u64,
+            pinned: row.get("pinned")?,
+            name: row.get("name")?,
+            pin_order: row.get("pin_order")?,
+            shared: row.get("shared")?,
+            group_id: row.get("group_id")?,
+        })
+    }
+}
+
+/// Limits applied to history after every insert
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Maximum number of items to keep
+    pub max_items: Option<usize>,
+    /// Maximum age of an item, measured from when it was stored
+    pub max_age: Option<Duration>,
+    /// Maximum combined content size of all items
+    pub max_total_bytes: Option<u64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_items: Some(100),
+            max_age: None,
+            max_total_bytes: None,
+        }
+    }
+}
+
+/// SQLite-backed clipboard history
+pub struct HistoryStore {
+    conn: Connection,
+    retention: RetentionPolicy,
+    /// When set, content is sealed at rest and only metadata is indexed
+    cipher: Option<ContentCipher>,
+}
+
+impl HistoryStore {
+    /// Open (or create) the history database at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    /// Open a history database that lives only in memory
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        // Overwrite deleted content instead of just unlinking the pages
+        conn.execute_batch("PRAGMA secure_delete = ON;")?;
+
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            log::info!("Applying history migration {}", i + 1);
+            conn.execute_batch(&format!(
+                "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                migration,
+                i + 1
+            ))?;
+        }
+
+        Ok(Self {
+            conn,
+            retention: RetentionPolicy::default(),
+            cipher: None,
+        })
+    }
+
+    /// Seal content at rest with `cipher`. Existing plaintext items are
+    /// encrypted and dropped from the full-text index.
+    pub fn seal_with(mut self, cipher: ContentCipher) -> Result<Self> {
+        let plaintext: Vec<(i64, String)> = {
+            let mut stmt = self
+                .conn
+                .prepare("SELECT rowid, content FROM history WHERE typeof(content) = 'text'")?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            rows
+        };
+
+        if !plaintext.is_empty() {
+            log::info!("Sealing {} plaintext history items", plaintext.len());
+
+            let tx = self.conn.transaction()?;
+            for (rowid, content) in &plaintext {
+                tx.execute(
+                    "UPDATE history SET content = ?2 WHERE rowid = ?1",
+                    params![rowid, cipher.seal(content)],
+                )?;
+                tx.execute(
+                    "UPDATE history_fts SET text = '' WHERE rowid = ?1",
+                    params![rowid],
+                )?;
+            }
+            tx.commit()?;
+
+            // Merge the index segments so the old tokens are dropped, then
+            // make sure no plaintext remains in free pages
+            self.conn.execute_batch(
+                "INSERT INTO history_fts (history_fts) VALUES ('optimize');
+                 VACUUM;",
+            )?;
+        }
+
+        self.cipher = Some(cipher);
+        Ok(self)
+    }
+
+    fn item_from_row(&self, row: &Row) -> rusqlite::Result<HistoryItem> {
+        HistoryItem::from_row(row, self.cipher.as_ref())
+    }
+
+    pub fn retention(&self) -> &RetentionPolicy {
+        &self.retention
+    }
+
+    /// Replace the retention policy and prune immediately
+    pub fn set_retention(&mut self, retention: RetentionPolicy) -> Result<usize> {
+        self.retention = retention;
+        self.prune()
+    }
+
+    /// Record an item, replacing any existing item with the same id
+    pub fn insert(&mut self, item: &HistoryItem) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        // Delete explicitly rather than `INSERT OR REPLACE`, which would
+        // skip the delete trigger that keeps the search index in sync
+        tx.execute("DELETE FROM history WHERE id = ?1", params![item.id])?;
+        let content = match &self.cipher {
+            Some(cipher) => Value::Blob(cipher.seal(&item.content)),
+            None => Value::Text(item.content.clone()),
+        };
+        tx.execute(
+            "INSERT INTO history
+                (id, origin_device, is_local, mime_types, content, size, created_at, stored_at,
+                 pinned, name, pin_order, shared, group_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                item.id,
+                item.origin_device,
+                item.is_local,
+                serde_json::to_string(&item.mime_types)?,
+                content,
+                item.size as i64,
+                item.created_at as i64,
+                item.stored_at as i64,
+                item.pinned,
+                item.name,
+                item.pin_order,
+                item.shared,
+                item.group_id,
+            ],
+        )?;
+        tx.commit()?;
+
+        self.prune()?;
+        Ok(())
+    }
+
+    /// List items, newest first
+    pub fn list(&self, limit: usize, offset: usize) -> Result<Vec<HistoryItem>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM history ORDER BY stored_at DESC, rowid DESC LIMIT ?1 OFFSET ?2",
+        )?;
+        let items = stmt
+            .query_map(params![limit as i64, offset as i64], |row| {
+                self.item_from_row(row)
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(items)
+    }
+
+    /// List unpinned items copied after `since`, oldest first. `after` is an
+    /// exclusive `(created_at, id)` position to resume from. Only items
+    /// outside any group or in one of `groups` are returned.
+    pub fn list_since(
+        &self,
+        since: u64,
+        after: Option<(u64, &str)>,
+        groups: &[String],
+        limit: usize,
+    ) -> Result<Vec<HistoryItem>> {
+        let (after_time, after_id) = match after {
+            Some((time, id)) => (Some(time as i64), Some(id)),
+            None => (None, None),
+        };
+
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM history
+             WHERE pinned = 0 AND created_at > ?1
+                AND (?2 IS NULL OR (created_at, id) > (?2, ?3))
+                AND (group_id IS NULL OR group_id IN (SELECT value FROM json_each(?4)))
+             ORDER BY created_at, id
+             LIMIT ?5",
+        )?;
+        let items = stmt
+            .query_map(
+                params![
+                    since as i64,
+                    after_time,
+                    after_id,
+                    serde_json::to_string(groups)?,
+                    limit as i64
+                ],
+                |row| self.item_from_row(row),
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(items)
+    }
+
+    /// Timestamp of the newest item received from `peer_id` by backfill,
+    /// whoever copied it. 0 if nothing was received yet.
+    pub fn backfill_cursor(&self, peer_id: &str) -> Result<u64> {
+        let since: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT since FROM backfill_cursors WHERE peer_id = ?1",
+                params![peer_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(since.unwrap_or(0) as u64)
+    }
+
+    /// Move the backfill cursor for `peer_id` forward to `since`. It never
+    /// moves back.
+    pub fn advance_backfill_cursor(&mut self, peer_id: &str, since: u64) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO backfill_cursors (peer_id, since) VALUES (?1, ?2)
+             ON CONFLICT (peer_id) DO UPDATE SET since = MAX(since, excluded.since)",
+            params![peer_id, since as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Fetch a single item by id
+    pub fn get(&self, id: &str) -> Result<Option<HistoryItem>> {
+        let item = self
+            .conn
+            .query_row("SELECT * FROM history WHERE id = ?1", params![id], |row| {
+                self.item_from_row(row)
+            })
+            .optional()?;
+
+        Ok(item)
+    }
+
+    /// Record an item unless one with the same id already exists. Returns
+    /// whether the item was inserted.
+    pub fn insert_if_absent(&mut self, item: &HistoryItem) -> Result<bool> {
+        if self.get(&item.id)?.is_some() {
+            return Ok(false);
+        }
+
+        self.insert(item)?;
+        Ok(true)
+    }
+
+    /// Delete a single item. Returns whether an item was removed.
+    pub fn delete(&mut self, id: &str) -> Result<bool> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM history WHERE id = ?1", params![id])?;
+        Ok(removed > 0)
+    }
+
+    /// Delete every item except pinned ones
+    pub fn clear(&mut self) -> Result<usize> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM history WHERE pinned = 0", [])?;
+        Ok(removed)
+    }
+
+    /// Securely erase all history, including pinned items: content is
+    /// overwritten, the search index is purged and the file is vacuumed
+    pub fn wipe(&mut self) -> Result<()> {
+        self.conn.execute_batch(
+            "BEGIN;
+             UPDATE history SET content = zeroblob(length(content));
+             DELETE FROM history;
+             DELETE FROM history_fts;
+             COMMIT;
+             INSERT INTO history_fts (history_fts) VALUES ('optimize');
+             VACUUM;",
+        )?;
+
+        log::info!("History wiped");
+        Ok(())
+    }
+
+    /// Number of stored items, pinned ones included
+    pub fn count(&self) -> Result<usize> {
+        let count: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM history", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    /// Apply the retention policy to unpinned items. Returns the number of
+    /// removed items.
+    pub fn prune(&mut self) -> Result<usize> {
+        let mut removed = 0;
+
+        if let Some(max_age) = self.retention.max_age {
+            let cutoff = now_millis().saturating_sub(max_age.as_millis() as u64);
+            removed += self.conn.execute(
+                "DELETE FROM history WHERE pinned = 0 AND stored_at < ?1",
+                params![cutoff as i64],
+            )?;
+        }
+
+        if let Some(max_items) = self.retention.max_items {
+            removed += self.conn.execute(
+                "DELETE FROM history WHERE pinned = 0 AND id NOT IN (
+                    SELECT id FROM history WHERE pinned = 0
+                    ORDER BY stored_at DESC, rowid DESC LIMIT ?1
+                 )",
+                params![max_items as i64],
+            )?;
+        }
+
+        if let Some(max_total_bytes) = self.retention.max_total_bytes {
+            // Keep the newest items whose running total fits within the budget
+            removed += self.conn.execute(
+                "DELETE FROM history WHERE id IN (
+                    SELECT id FROM (
+                        SELECT id, SUM(size) OVER (
+                            ORDER BY stored_at DESC, rowid DESC
+                        ) AS running_total
+                        FROM history WHERE pinned = 0
+                    ) WHERE running_total > ?1
+                 )",
+                params![max_total_bytes as i64],
+            )?;
+        }
+
+        if removed > 0 {
+            log::debug!("Pruned {} history items", removed);
+        }
+
+        Ok(removed)
+    }
+}
+
+fn now_millis() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str, content: &str, stored_at: u64) -> HistoryItem {
+        let mut item = HistoryItem::text(
+            id.to_string(),
+            "device-a".to_string(),
+            true,
+            content.to_string(),
+            stored_at,
+        );
+        item.stored_at = stored_at;
+        item
+    }
+
+    #[test]
+    fn test_insert_get_and_delete() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        store.insert(&item("1", "hello", 1)).unwrap();
+
+        let fetched = store.get("1").unwrap().unwrap();
+        assert_eq!(fetched.content, "hello");
+        assert_eq!(fetched.mime_types, vec!["text/plain".to_string()]);
+        assert_eq!(fetched.size, 5);
+
+        assert!(store.delete("1").unwrap());
+        assert!(store.get("1").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_sealed_content_is_not_stored_in_plaintext() {
+        let mut store = HistoryStore::open_in_memory()
+            .unwrap()
+            .seal_with(ContentCipher::derive(b"secret"))
+            .unwrap();
+        store.insert(&item("1", "hunter2", 1)).unwrap();
+
+        let raw: Vec<u8> = store
+            .conn
+            .query_row("SELECT content FROM history", [], |row| row.get(0))
+            .unwrap();
+        assert!(!raw.windows(7).any(|w| w == b"hunter2"));
+        assert_eq!(store.get("1").unwrap().unwrap().content, "hunter2");
+
+        let other_key = HistoryStore {
+            conn: Connection::open_in_memory().unwrap(),
+            retention: RetentionPolicy::default(),
+            cipher: Some(ContentCipher::derive(b"other")),
+        };
+        let row_result = store.conn.query_row("SELECT * FROM history", [], |row| {
+            other_key.item_from_row(row)
+        });
+        assert!(row_result.is_err());
+    }
+
+    #[test]
+    fn test_sealing_leaves_no_plaintext_in_the_file() {
+        let dir = std::env::temp_dir().join(format!("clipbridge-history-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.db");
+
+        let mut store = HistoryStore::open(&path).unwrap();
+        store.insert(&item("1", "hunter2", 1)).unwrap();
+        let store = store.seal_with(ContentCipher::derive(b"secret")).unwrap();
+        drop(store);
+
+        let raw = std::fs::read(&path).unwrap();
+        assert!(!raw.windows(7).any(|w| w == b"hunter2"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_wipe_removes_pinned_items() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        store.insert(&item("1", "a", 1)).unwrap();
+        store.pin("1", None).unwrap();
+
+        store.wipe().unwrap();
+        assert_eq!(store.count().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_list_is_newest_first() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        store.insert(&item("old", "a", 1)).unwrap();
+        store.insert(&item("new", "b", 2)).unwrap();
+
+        let ids: Vec<_> = store
+            .list(10, 0)
+            .unwrap()
+            .into_iter()
+            .map(|i| i.id)
+            .collect();
+        assert_eq!(ids, vec!["new", "old"]);
+    }
+
+    #[test]
+    fn test_list_since_pages_in_order() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        for (id, created_at) in [("a", 5), ("c", 10), ("b", 10), ("d", 20)] {
+            store.insert(&item(id, "x", created_at)).unwrap();
+        }
+
+        let first: Vec<_> = store.list_since(5, None, &[], 2).unwrap();
+        assert_eq!(
+            first.iter().map(|i| i.id.as_str()).collect::<Vec<_>>(),
+            vec!["b", "c"]
+        );
+
+        let last = first.last().unwrap();
+        let second = store
+            .list_since(5, Some((last.created_at, &last.id)), &[], 2)
+            .unwrap();
+        assert_eq!(
+            second.iter().map(|i| i.id.as_str()).collect::<Vec<_>>(),
+            vec!["d"]
+        );
+    }
+
+    #[test]
+    fn test_list_since_respects_groups() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        for (id, group) in [("a", None), ("b", Some("ops")), ("c", Some("home"))] {
+            let mut item = item(id, "x", 10);
+            item.group_id = group.map(str::to_string);
+            store.insert(&item).unwrap();
+        }
+
+        let visible: Vec<_> = store
+            .list_since(0, None, &["ops".to_string()], 10)
+            .unwrap()
+            .into_iter()
+            .map(|i| i.id)
+            .collect();
+        assert_eq!(visible, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_backfill_cursor_only_moves_forward() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        assert_eq!(store.backfill_cursor("laptop").unwrap(), 0);
+
+        store.advance_backfill_cursor("laptop", 20).unwrap();
+        store.advance_backfill_cursor("laptop", 10).unwrap();
+        assert_eq!(store.backfill_cursor("laptop").unwrap(), 20);
+        assert_eq!(store.backfill_cursor("phone").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_retention_by_count_and_size() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        store
+            .set_retention(RetentionPolicy {
+                max_items: Some(3),
+                max_age: None,
+                max_total_bytes: Some(8),
+            })
+            .unwrap();
+
+        for i in 0..5 {
+            store.insert(&item(&i.to_string(), "abc", i)).unwrap();
+        }
+
+        // Count limit keeps 3, size limit (8 bytes) keeps the newest 2
+        let ids: Vec<_> = store
+            .list(10, 0)
+            .unwrap()
+            .into_iter()
+            .map(|i| i.id)
+            .collect();
+        assert_eq!(ids, vec!["4", "3"]);
+    }
+
+    #[test]
+    fn test_retention_by_age() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        store.insert(&item("ancient", "a", 1)).unwrap();
+        store
+            .insert(&HistoryItem::text(
+                "fresh".to_string(),
+                "device-a".to_string(),
+                true,
+                "b".to_string(),
+                now_millis(),
+            ))
+            .unwrap();
+
+        store
+            .set_retention(RetentionPolicy {
+                max_items: None,
+                max_age: Some(Duration::from_secs(60)),
+                max_total_bytes: None,
+            })
+            .unwrap();
+
+        assert_eq!(store.count().unwrap(), 1);
+        assert!(store.get("fresh").unwrap().is_some());
+    }
+}
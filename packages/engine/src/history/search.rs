@@ -0,0 +1,396 @@
+use super::{HistoryItem, HistoryStore, Result};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+/// Markers wrapped around matched terms in `SearchResult::snippet`
+pub const HIGHLIGHT_START: &str = "<mark>";
+pub const HIGHLIGHT_END: &str = "</mark>";
+
+/// SQL conditions shared by all search queries. Parameters: ?2 origin
+/// device, ?3 MIME pattern, ?4 since, ?5 until, ?6 pinned.
+const FILTERS: &str = "(?2 IS NULL OR h.origin_device = ?2)
+    AND (?3 IS NULL OR h.mime_types LIKE ?3)
+    AND (?4 IS NULL OR h.created_at >= ?4)
+    AND (?5 IS NULL OR h.created_at <= ?5)
+    AND (?6 IS NULL OR h.pinned = ?6)";
+
+/// Filters for `HistoryStore::search`. Unset fields do not filter.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchQuery {
+    /// Free text, matched against content and metadata. Each word is a
+    /// prefix match and all words must match.
+    pub text: String,
+    pub origin_device: Option<String>,
+    pub mime_type: Option<String>,
+    /// Only items copied at or after this time (ms since epoch)
+    pub since: Option<u64>,
+    /// Only items copied at or before this time (ms since epoch)
+    pub until: Option<u64>,
+    pub pinned: Option<bool>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub item: HistoryItem,
+    /// Excerpt around the match, with matched terms wrapped in
+    /// `HIGHLIGHT_START`/`HIGHLIGHT_END`
+    pub snippet: String,
+    /// Relevance score; higher is better
+    pub score: f64,
+}
+
+impl HistoryStore {
+    /// Search history, best matches first. With empty `text`, all items
+    /// matching the filters are returned newest first.
+    ///
+    /// Sealed stores only index metadata, so content is matched by
+    /// decrypting the filtered candidates instead.
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchResult>> {
+        let match_expr = to_match_expression(&query.text);
+        if match_expr.is_some() && self.cipher.is_some() {
+            return self.search_sealed(query);
+        }
+
+        let limit = query.limit.unwrap_or(50) as i64;
+        let offset = query.offset.unwrap_or(0) as i64;
+        let sql = if match_expr.is_some() {
+            format!(
+                "SELECT h.*,
+                    snippet(history_fts, -1, '{}', '{}', '…', 16) AS snippet,
+                    -bm25(history_fts) AS score
+                 FROM history_fts JOIN history h ON h.rowid = history_fts.rowid
+                 WHERE history_fts MATCH ?1 AND {}
+                 ORDER BY bm25(history_fts), h.stored_at DESC
+                 LIMIT ?7 OFFSET ?8",
+                HIGHLIGHT_START, HIGHLIGHT_END, FILTERS
+            )
+        } else {
+            format!(
+                "SELECT h.*, NULL AS snippet, 0.0 AS score
+                 FROM history h
+                 WHERE ?1 IS NULL AND {}
+                 ORDER BY h.stored_at DESC, h.rowid DESC
+                 LIMIT ?7 OFFSET ?8",
+                FILTERS
+            )
+        };
+
+        self.query_results(&sql, query, match_expr, limit, offset)
+    }
+
+    /// Match every term against decrypted content and metadata in memory
+    fn search_sealed(&self, query: &SearchQuery) -> Result<Vec<SearchResult>> {
+        let sql = format!(
+            "SELECT h.*, NULL AS snippet, 0.0 AS score
+             FROM history h
+             WHERE ?1 IS NULL AND {}
+             ORDER BY h.stored_at DESC, h.rowid DESC
+             LIMIT ?7 OFFSET ?8",
+            FILTERS
+        );
+        let candidates = self.query_results(&sql, query, None, -1, 0)?;
+
+        let terms: Vec<String> = query
+            .text
+            .split_whitespace()
+            .map(|term| term.to_ascii_lowercase())
+            .collect();
+
+        let mut results: Vec<SearchResult> = candidates
+            .into_iter()
+            .filter_map(|mut result| {
+                let content = result.item.content.to_ascii_lowercase();
+                let metadata = format!(
+                    "{} {}",
+                    result.item.origin_device,
+                    result.item.mime_types.join(" ")
+                )
+                .to_ascii_lowercase();
+
+                let mut score = 0;
+                for term in &terms {
+                    let hits = content.matches(term.as_str()).count()
+                        + metadata.matches(term.as_str()).count();
+                    if hits == 0 {
+                        return None;
+                    }
+                    score += hits;
+                }
+
+                result.snippet = highlight(&result.item.content, &terms);
+                result.score = score as f64;
+                Some(result)
+            })
+            .collect();
+
+        // Stable sort keeps newest first among equal scores
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        Ok(results
+            .into_iter()
+            .skip(query.offset.unwrap_or(0))
+            .take(query.limit.unwrap_or(50))
+            .collect())
+    }
+
+    fn query_results(
+        &self,
+        sql: &str,
+        query: &SearchQuery,
+        match_expr: Option<String>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SearchResult>> {
+        let mime_pattern = query.mime_type.as_ref().map(|m| format!("%\"{}\"%", m));
+        let since = query.since.map(|t| t as i64);
+        let until = query.until.map(|t| t as i64);
+
+        let mut stmt = self.conn.prepare(sql)?;
+        let results = stmt
+            .query_map(
+                params![
+                    match_expr,
+                    query.origin_device,
+                    mime_pattern,
+                    since,
+                    until,
+                    query.pinned,
+                    limit,
+                    offset
+                ],
+                |row| {
+                    let item = self.item_from_row(row)?;
+                    let snippet: Option<String> = row.get("snippet")?;
+                    Ok(SearchResult {
+                        snippet: snippet.unwrap_or_else(|| preview(&item.content)),
+                        score: row.get("score")?,
+                        item,
+                    })
+                },
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(results)
+    }
+}
+
+/// The first 100 characters of `content`
+fn preview(content: &str) -> String {
+    content.chars().take(100).collect()
+}
+
+/// Build a short excerpt around the first matching term, with all matches
+/// in it wrapped in highlight markers. `terms` must be ASCII-lowercased.
+fn highlight(content: &str, terms: &[String]) -> String {
+    // ASCII lowercasing keeps byte offsets identical to `content`
+    let lower = content.to_ascii_lowercase();
+
+    let first = terms
+        .iter()
+        .filter_map(|term| lower.find(term.as_str()))
+        .min()
+        .unwrap_or(0);
+    let start = floor_char_boundary(content, first.saturating_sub(40));
+    let end = floor_char_boundary(content, (first + 100).min(content.len()));
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+
+    let mut pos = start;
+    while pos < end {
+        let next = terms
+            .iter()
+            .filter(|term| !term.is_empty())
+            .filter_map(|term| {
+                lower[pos..end]
+                    .find(term.as_str())
+                    .map(|i| (pos + i, term.len()))
+            })
+            .min();
+        match next {
+            Some((at, len)) if at + len <= end => {
+                snippet.push_str(&content[pos..at]);
+                snippet.push_str(HIGHLIGHT_START);
+                snippet.push_str(&content[at..at + len]);
+                snippet.push_str(HIGHLIGHT_END);
+                pos = at + len;
+            }
+            _ => {
+                snippet.push_str(&content[pos..end]);
+                pos = end;
+            }
+        }
+    }
+
+    if end < content.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+fn floor_char_boundary(s: &str, mut index: usize) -> usize {
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Turn free text into an FTS5 query: every word becomes a quoted prefix
+/// term, so user input can never be parsed as FTS syntax.
+fn to_match_expression(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_with(items: &[(&str, &str, &str, &str, u64)]) -> HistoryStore {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        for (id, device, mime, content, created_at) in items {
+            let mut item = HistoryItem::text(
+                id.to_string(),
+                device.to_string(),
+                true,
+                content.to_string(),
+                *created_at,
+            );
+            item.mime_types = vec![mime.to_string()];
+            store.insert(&item).unwrap();
+        }
+        store
+    }
+
+    fn ids(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|r| r.item.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_search_matches_prefix_and_highlights() {
+        let store = store_with(&[
+            (
+                "1",
+                "laptop",
+                "text/plain",
+                "curl -X POST https://api.example.com",
+                1,
+            ),
+            ("2", "laptop", "text/plain", "grocery list", 2),
+        ]);
+
+        let results = store
+            .search(&SearchQuery {
+                text: "cur".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(ids(&results), vec!["1"]);
+        assert!(results[0].snippet.contains("<mark>curl</mark>"));
+    }
+
+    #[test]
+    fn test_search_filters() {
+        let store = store_with(&[
+            ("1", "laptop", "text/plain", "deploy script", 10),
+            ("2", "desktop", "text/plain", "deploy notes", 20),
+            ("3", "desktop", "text/html", "<b>deploy</b>", 30),
+        ]);
+
+        let by_device = store
+            .search(&SearchQuery {
+                text: "deploy".to_string(),
+                origin_device: Some("desktop".to_string()),
+                mime_type: Some("text/plain".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(ids(&by_device), vec!["2"]);
+
+        let by_date = store
+            .search(&SearchQuery {
+                since: Some(15),
+                until: Some(25),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(ids(&by_date), vec!["2"]);
+    }
+
+    #[test]
+    fn test_binary_items_are_indexed_by_metadata_only() {
+        let store = store_with(&[("1", "laptop", "image/png", "iVBORw0KGgo", 1)]);
+
+        let by_content = store
+            .search(&SearchQuery {
+                text: "iVBORw0KGgo".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(by_content.is_empty());
+
+        let by_mime = store
+            .search(&SearchQuery {
+                text: "image/png".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(ids(&by_mime), vec!["1"]);
+    }
+
+    #[test]
+    fn test_sealed_search_matches_decrypted_content() {
+        let store = store_with(&[
+            (
+                "1",
+                "laptop",
+                "text/plain",
+                "ssh deploy@prod.example.com",
+                1,
+            ),
+            ("2", "laptop", "text/plain", "lunch order", 2),
+        ])
+        .seal_with(crate::history::ContentCipher::derive(b"secret"))
+        .unwrap();
+
+        let results = store
+            .search(&SearchQuery {
+                text: "DEPLOY".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(ids(&results), vec!["1"]);
+        assert_eq!(
+            results[0].snippet,
+            "ssh <mark>deploy</mark>@prod.example.com"
+        );
+    }
+
+    #[test]
+    fn test_search_ignores_fts_syntax() {
+        let store = store_with(&[("1", "laptop", "text/plain", "a \"quoted\" AND (thing)", 1)]);
+
+        let results = store
+            .search(&SearchQuery {
+                text: "\"quoted\" AND (".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(ids(&results), vec!["1"]);
+    }
+}
@@ -0,0 +1,247 @@
+use super::{HistoryItem, HistoryStore, Result};
+use rusqlite::params;
+
+impl HistoryStore {
+    /// Pin an item, optionally naming it. Newly pinned items go to the end
+    /// of the pinned list. Returns whether the item exists.
+    pub fn pin(&mut self, id: &str, name: Option<String>) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE history SET
+                pinned = 1,
+                name = COALESCE(?2, name),
+                pin_order = COALESCE(
+                    pin_order,
+                    (SELECT COALESCE(MAX(pin_order), 0) + 1 FROM history WHERE pinned = 1)
+                )
+             WHERE id = ?1",
+            params![id, name],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Unpin an item, returning it to normal retention. Unpinned items are
+    /// no longer shared. Returns whether the item exists.
+    pub fn unpin(&mut self, id: &str) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE history SET pinned = 0, pin_order = NULL, shared = 0 WHERE id = ?1",
+            params![id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Set or clear the snippet name of an item
+    pub fn rename(&mut self, id: &str, name: Option<String>) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE history SET name = ?2 WHERE id = ?1",
+            params![id, name],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Mark a pinned item as shared (or private). Returns false if the item
+    /// does not exist or is not pinned.
+    pub fn set_shared(&mut self, id: &str, shared: bool) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE history SET shared = ?2 WHERE id = ?1 AND pinned = 1",
+            params![id, shared],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Reorder pinned items. `ids` lists pinned items in their new order;
+    /// pinned items not listed keep their relative order after them.
+    pub fn reorder_pinned(&mut self, ids: &[String]) -> Result<()> {
+        let mut order: Vec<String> = ids.to_vec();
+        for item in self.list_pinned()? {
+            if !order.contains(&item.id) {
+                order.push(item.id);
+            }
+        }
+
+        let tx = self.conn.transaction()?;
+        for (position, id) in order.iter().enumerate() {
+            tx.execute(
+                "UPDATE history SET pin_order = ?2 WHERE id = ?1 AND pinned = 1",
+                params![id, position as i64 + 1],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// List pinned items in their user-defined order
+    pub fn list_pinned(&self) -> Result<Vec<HistoryItem>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM history WHERE pinned = 1 ORDER BY pin_order, rowid")?;
+        let items = stmt
+            .query_map([], |row| self.item_from_row(row))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(items)
+    }
+
+    /// Store a snippet shared by a peer. A new id is inserted; an existing
+    /// item is only replaced if it came from the same peer, whether it was
+    /// synced earlier or already shared. Returns whether the snippet was stored.
+    pub fn store_shared(&mut self, item: &HistoryItem) -> Result<bool> {
+        match self.get(&item.id)? {
+            Some(existing) if existing.is_local || existing.origin_device != item.origin_device => {
+                Ok(false)
+            }
+            _ => {
+                self.insert(item)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Unpin a snippet `origin_device` shared with us. Returns false if
+    /// there is no such snippet.
+    pub fn unpin_shared(&mut self, id: &str, origin_device: &str) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE history SET pinned = 0, pin_order = NULL, shared = 0
+             WHERE id = ?1 AND shared = 1 AND is_local = 0 AND origin_device = ?2",
+            params![id, origin_device],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// List pinned items that are shared with peers
+    pub fn list_shared(&self) -> Result<Vec<HistoryItem>> {
+        Ok(self
+            .list_pinned()?
+            .into_iter()
+            .filter(|item| item.shared)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::RetentionPolicy;
+
+    fn insert(store: &mut HistoryStore, id: &str, stored_at: u64) {
+        let mut item = HistoryItem::text(
+            id.to_string(),
+            "device-a".to_string(),
+            true,
+            format!("content {}", id),
+            stored_at,
+        );
+        item.stored_at = stored_at;
+        store.insert(&item).unwrap();
+    }
+
+    #[test]
+    fn test_pinned_items_survive_retention_and_clear() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        insert(&mut store, "keep", 1);
+        assert!(store.pin("keep", Some("Address".to_string())).unwrap());
+
+        store
+            .set_retention(RetentionPolicy {
+                max_items: Some(2),
+                max_age: None,
+                max_total_bytes: None,
+            })
+            .unwrap();
+        for i in 2..6 {
+            insert(&mut store, &i.to_string(), i);
+        }
+        store.clear().unwrap();
+
+        let pinned = store.list_pinned().unwrap();
+        assert_eq!(pinned.len(), 1);
+        assert_eq!(pinned[0].name.as_deref(), Some("Address"));
+        assert_eq!(store.count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_reorder_pinned() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        for id in ["a", "b", "c"] {
+            insert(&mut store, id, 1);
+            store.pin(id, None).unwrap();
+        }
+
+        store.reorder_pinned(&["c".to_string()]).unwrap();
+
+        let ids: Vec<_> = store
+            .list_pinned()
+            .unwrap()
+            .into_iter()
+            .map(|i| i.id)
+            .collect();
+        assert_eq!(ids, vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    fn test_only_pinned_items_can_be_shared() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        insert(&mut store, "a", 1);
+        assert!(!store.set_shared("a", true).unwrap());
+
+        store.pin("a", None).unwrap();
+        assert!(store.set_shared("a", true).unwrap());
+        assert_eq!(store.list_shared().unwrap().len(), 1);
+
+        store.unpin("a").unwrap();
+        assert!(store.list_shared().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_peers_only_change_their_own_snippets() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        insert(&mut store, "mine", 1);
+        store.pin("mine", None).unwrap();
+
+        let snippet = |id: &str, from: &str, content: &str| {
+            let mut item = HistoryItem::text(
+                id.to_string(),
+                from.to_string(),
+                false,
+                content.to_string(),
+                1,
+            );
+            item.pinned = true;
+            item.shared = true;
+            item
+        };
+
+        // Neither our items nor other peers' snippets can be overwritten
+        assert!(!store.store_shared(&snippet("mine", "laptop", "x")).unwrap());
+        assert!(store.store_shared(&snippet("s", "laptop", "v1")).unwrap());
+        assert!(!store.store_shared(&snippet("s", "phone", "v2")).unwrap());
+        assert!(store.store_shared(&snippet("s", "laptop", "v3")).unwrap());
+        assert_eq!(store.get("s").unwrap().unwrap().content, "v3");
+
+        assert!(!store.unpin_shared("mine", "laptop").unwrap());
+        assert!(!store.unpin_shared("s", "phone").unwrap());
+        assert!(store.unpin_shared("s", "laptop").unwrap());
+        assert_eq!(store.list_pinned().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_synced_item_can_be_shared_by_its_origin() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        let mut item = HistoryItem::text(
+            "a".to_string(),
+            "laptop".to_string(),
+            false,
+            "x".to_string(),
+            1,
+        );
+        store.insert(&item).unwrap();
+
+        item.pinned = true;
+        item.shared = true;
+        assert!(store.store_shared(&item).unwrap());
+        assert_eq!(store.list_shared().unwrap().len(), 1);
+
+        item.origin_device = "phone".to_string();
+        assert!(!store.store_shared(&item).unwrap());
+    }
+}
@@ -0,0 +1,112 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use ed25519_dalek::SigningKey;
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+const DEVICE_KEY_INFO: &[u8] = b"clipbridge relay device key v1";
+const RELAY_ACCOUNT_INFO: &[u8] = b"clipbridge relay account secret v1";
+
+/// This device's persistent identity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceIdentity {
+    pub device_id: String,
+    /// 32 random bytes, hex encoded. Never leaves this device.
+    secret: String,
+}
+
+impl DeviceIdentity {
+    /// Load the identity from `path`, creating a new one on first run
+    pub fn load_or_create(path: &Path) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(io::Error::other),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let identity = Self::generate();
+                identity.save(path)?;
+                log::info!("Created device identity {}", identity.device_id);
+                Ok(identity)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn generate() -> Self {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+
+        Self {
+            device_id: uuid::Uuid::new_v4().to_string(),
+            secret: hex::encode(secret),
+        }
+    }
+
+    pub fn secret(&self) -> io::Result<Vec<u8>> {
+        hex::decode(&self.secret).map_err(io::Error::other)
+    }
+
+    /// The key this device proves its id with, to the relay and to peers
+    pub fn device_key(&self) -> io::Result<SigningKey> {
+        Ok(SigningKey::from_bytes(&self.derive(DEVICE_KEY_INFO)?))
+    }
+
+    /// Relay account secret for devices that have not joined another
+    /// device's account
+    pub fn relay_account_secret(&self) -> io::Result<String> {
+        Ok(hex::encode(self.derive(RELAY_ACCOUNT_INFO)?))
+    }
+
+    fn derive(&self, info: &[u8]) -> io::Result<[u8; 32]> {
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(None, &self.secret()?)
+            .expand(info, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        Ok(key)
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let bytes = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+
+        // The secret protects history at rest; keep it private to this user
+        // from the moment the file exists
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        file.write_all(&bytes)?;
+        file.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_is_created_private_and_reloaded() {
+        let dir =
+            std::env::temp_dir().join(format!("clipbridge-identity-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("identity.json");
+
+        let identity = DeviceIdentity::load_or_create(&path).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let reloaded = DeviceIdentity::load_or_create(&path).unwrap();
+        assert_eq!(reloaded.device_id, identity.device_id);
+        assert_eq!(reloaded.secret().unwrap(), identity.secret().unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
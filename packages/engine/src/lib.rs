@@ -0,0 +1,20 @@
+//! Clipboard, network and sync engine for ClipBridge.
+//!
+//! Shared by the Tauri desktop app and the headless `clipbridged` daemon so
+//! both speak the same protocol and can pair with each other. `Service`
+//! opens the stores and drives the sync engine for a running instance.
+
+pub mod clipboard;
+pub mod config;
+pub mod control;
+pub mod events;
+pub mod filter;
+pub mod groups;
+pub mod history;
+pub mod identity;
+pub mod network;
+pub mod peers;
+pub mod service;
+pub mod sync;
+
+pub use service::Service;
@@ -1,11 +1,9 @@
 use parking_lot::Mutex;
-use std::net::{SocketAddr, UdpSocket};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
-#[allow(dead_code)]
-const DISCOVERY_PORT: u16 = 7878;
 #[allow(dead_code)]
 const DISCOVERY_MULTICAST_ADDR: &str = "239.255.77.77";
 #[allow(dead_code)]
@@ -15,27 +13,50 @@ const DISCOVERY_MESSAGE: &str = "CLIPBRIDGE_DISCOVERY";
 #[derive(Debug, Clone)]
 pub struct DiscoveredDevice {
     pub device_id: String,
+    /// The device's P2P listener
     pub addr: SocketAddr,
     pub last_seen: u64,
 }
 
+/// Called whenever a device announces itself
+pub type DeviceCallback = Arc<dyn Fn(&DiscoveredDevice) + Send + Sync>;
+
 #[allow(dead_code)]
 pub struct DeviceDiscovery {
     device_id: String,
+    port: u16,
+    /// Our P2P port, announced to other devices
+    p2p_port: Arc<Mutex<u16>>,
     discovered_devices: Arc<Mutex<Vec<DiscoveredDevice>>>,
     is_running: Arc<Mutex<bool>>,
+    on_device: Option<DeviceCallback>,
 }
 
 impl DeviceDiscovery {
     #[allow(dead_code)]
-    pub fn new(device_id: String) -> Self {
+    pub fn new(device_id: String, port: u16, p2p_port: u16) -> Self {
         Self {
             device_id,
+            port,
+            p2p_port: Arc::new(Mutex::new(p2p_port)),
             discovered_devices: Arc::new(Mutex::new(Vec::new())),
             is_running: Arc::new(Mutex::new(false)),
+            on_device: None,
         }
     }
 
+    /// Announce a new P2P port from the next broadcast on
+    pub fn set_p2p_port(&self, p2p_port: u16) {
+        *self.p2p_port.lock() = p2p_port;
+    }
+
+    pub fn set_device_handler<F>(&mut self, callback: F)
+    where
+        F: Fn(&DiscoveredDevice) + Send + Sync + 'static,
+    {
+        self.on_device = Some(Arc::new(callback));
+    }
+
     /// Start broadcasting and listening for devices
     #[allow(dead_code)]
     pub fn start(&self) -> std::io::Result<()> {
@@ -48,20 +69,25 @@ impl DeviceDiscovery {
 
         // Start broadcaster
         let device_id_clone = self.device_id.clone();
+        let p2p_port = Arc::clone(&self.p2p_port);
         let is_running_clone = Arc::clone(&self.is_running);
+        let port = self.port;
         thread::spawn(move || {
-            Self::broadcast_presence(&device_id_clone, &is_running_clone);
+            Self::broadcast_presence(&device_id_clone, &p2p_port, port, &is_running_clone);
         });
 
         // Start listener
         let discovered_devices_clone = Arc::clone(&self.discovered_devices);
         let is_running_clone = Arc::clone(&self.is_running);
         let device_id_clone = self.device_id.clone();
+        let on_device = self.on_device.clone();
         thread::spawn(move || {
             Self::listen_for_devices(
                 &device_id_clone,
+                port,
                 &discovered_devices_clone,
                 &is_running_clone,
+                on_device,
             );
         });
 
@@ -82,7 +108,12 @@ impl DeviceDiscovery {
     }
 
     #[allow(dead_code)]
-    fn broadcast_presence(device_id: &str, is_running: &Arc<Mutex<bool>>) {
+    fn broadcast_presence(
+        device_id: &str,
+        p2p_port: &Arc<Mutex<u16>>,
+        port: u16,
+        is_running: &Arc<Mutex<bool>>,
+    ) {
         let socket = match UdpSocket::bind("0.0.0.0:0") {
             Ok(s) => s,
             Err(e) => {
@@ -96,10 +127,10 @@ impl DeviceDiscovery {
             return;
         }
 
-        let broadcast_addr = format!("{}:{}", DISCOVERY_MULTICAST_ADDR, DISCOVERY_PORT);
-        let message = format!("{}:{}", DISCOVERY_MESSAGE, device_id);
+        let broadcast_addr = format!("{}:{}", DISCOVERY_MULTICAST_ADDR, port);
 
         while *is_running.lock() {
+            let message = format!("{}:{}:{}", DISCOVERY_MESSAGE, device_id, p2p_port.lock());
             if let Err(e) = socket.send_to(message.as_bytes(), &broadcast_addr) {
                 log::warn!("Failed to send discovery broadcast: {}", e);
             } else {
@@ -113,10 +144,12 @@ impl DeviceDiscovery {
     #[allow(dead_code)]
     fn listen_for_devices(
         own_device_id: &str,
+        port: u16,
         discovered_devices: &Arc<Mutex<Vec<DiscoveredDevice>>>,
         is_running: &Arc<Mutex<bool>>,
+        on_device: Option<DeviceCallback>,
     ) {
-        let socket = match UdpSocket::bind(format!("0.0.0.0:{}", DISCOVERY_PORT)) {
+        let socket = match UdpSocket::bind(format!("0.0.0.0:{}", port)) {
             Ok(s) => s,
             Err(e) => {
                 log::error!("Failed to bind UDP socket for listening: {}", e);
@@ -124,6 +157,12 @@ impl DeviceDiscovery {
             }
         };
 
+        // Announcements go to the multicast group, not to our address
+        let group: Ipv4Addr = DISCOVERY_MULTICAST_ADDR.parse().unwrap();
+        if let Err(e) = socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED) {
+            log::warn!("Failed to join the discovery multicast group: {}", e);
+        }
+
         if let Err(e) = socket.set_read_timeout(Some(Duration::from_secs(1))) {
             log::error!("Failed to set read timeout: {}", e);
             return;
@@ -133,41 +172,43 @@ impl DeviceDiscovery {
 
         while *is_running.lock() {
             match socket.recv_from(&mut buf) {
-                Ok((len, addr)) => {
+                Ok((len, from)) => {
                     let message = String::from_utf8_lossy(&buf[..len]);
-                    if let Some(device_id) =
-                        message.strip_prefix(&format!("{}:", DISCOVERY_MESSAGE))
-                    {
-                        let device_id = device_id.to_string();
+                    let Some((device_id, addr)) = parse_announcement(&message, from) else {
+                        continue;
+                    };
 
-                        // Ignore own broadcasts
-                        if device_id == own_device_id {
-                            continue;
-                        }
+                    // Ignore own broadcasts
+                    if device_id == own_device_id {
+                        continue;
+                    }
 
-                        log::debug!("Discovered device: {} at {}", device_id, addr);
+                    log::debug!("Discovered device: {} at {}", device_id, addr);
 
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs();
+                    let device = DiscoveredDevice {
+                        device_id,
+                        addr,
+                        last_seen: now,
+                    };
+
+                    {
                         let mut devices = discovered_devices.lock();
-                        let now = std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs();
-
-                        if let Some(device) = devices.iter_mut().find(|d| d.device_id == device_id)
-                        {
-                            device.last_seen = now;
-                            device.addr = addr;
-                        } else {
-                            devices.push(DiscoveredDevice {
-                                device_id,
-                                addr,
-                                last_seen: now,
-                            });
+                        match devices.iter_mut().find(|d| d.device_id == device.device_id) {
+                            Some(known) => *known = device.clone(),
+                            None => devices.push(device.clone()),
                         }
 
                         // Remove stale devices (not seen for 30 seconds)
                         devices.retain(|d| now - d.last_seen < 30);
                     }
+
+                    if let Some(callback) = &on_device {
+                        callback(&device);
+                    }
                 }
                 Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
                     // Timeout, continue
@@ -180,8 +221,46 @@ impl DeviceDiscovery {
     }
 }
 
+/// The device id and P2P address in an announcement received from `from`
+fn parse_announcement(message: &str, from: SocketAddr) -> Option<(String, SocketAddr)> {
+    let (device_id, p2p_port) = message
+        .strip_prefix(DISCOVERY_MESSAGE)?
+        .strip_prefix(':')?
+        .rsplit_once(':')?;
+    if device_id.is_empty() {
+        return None;
+    }
+    let p2p_port = p2p_port.parse().ok()?;
+    Some((device_id.to_string(), SocketAddr::new(from.ip(), p2p_port)))
+}
+
 impl Drop for DeviceDiscovery {
     fn drop(&mut self) {
         self.stop();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_announcements_carry_the_p2p_port() {
+        let from: SocketAddr = "192.168.1.20:53124".parse().unwrap();
+        assert_eq!(
+            parse_announcement("CLIPBRIDGE_DISCOVERY:laptop-1:7879", from),
+            Some(("laptop-1".to_string(), "192.168.1.20:7879".parse().unwrap()))
+        );
+
+        // Announcements without a port can't be dialed
+        assert_eq!(
+            parse_announcement("CLIPBRIDGE_DISCOVERY:laptop-1", from),
+            None
+        );
+        assert_eq!(parse_announcement("CLIPBRIDGE_DISCOVERY::7879", from), None);
+        assert_eq!(
+            parse_announcement("SOMETHING_ELSE:laptop-1:7879", from),
+            None
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use super::message::{MessageType, NetworkMessage};
+use super::p2p::P2PNetwork;
+use super::relay::RelayClient;
+use super::transport::{PeerLink, Transport};
+use parking_lot::Mutex;
+use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::sync::Arc;
+
+/// Message ids remembered to drop copies that arrive over the other path
+const SEEN_CAPACITY: usize = 1000;
+
+/// Uses direct P2P links for peers on the local network and the relay for
+/// everyone else. Each message goes over the path that is up when it is
+/// sent: a peer is reached directly as soon as a link to it authenticates,
+/// and through the relay again once the link drops. Messages that arrive
+/// over both paths are delivered once.
+pub struct HybridTransport {
+    p2p: Arc<P2PNetwork>,
+    relay: Arc<RelayClient>,
+}
+
+impl HybridTransport {
+    pub fn new<F>(mut p2p: P2PNetwork, relay: RelayClient, on_message: F) -> Self
+    where
+        F: Fn(NetworkMessage) + Send + Sync + 'static,
+    {
+        let seen = Arc::new(Mutex::new(SeenMessages::default()));
+        let on_message = Arc::new(on_message);
+
+        let seen_clone = Arc::clone(&seen);
+        let on_message_clone = Arc::clone(&on_message);
+        p2p.set_message_handler(move |msg| {
+            if seen_clone.lock().insert(&msg) {
+                on_message_clone(msg);
+            }
+        });
+        relay.set_message_handler(move |msg| {
+            if seen.lock().insert(&msg) {
+                on_message(msg);
+            }
+        });
+
+        Self {
+            p2p: Arc::new(p2p),
+            relay: Arc::new(relay),
+        }
+    }
+
+    pub fn p2p(&self) -> Arc<P2PNetwork> {
+        Arc::clone(&self.p2p)
+    }
+
+    pub fn relay(&self) -> Arc<RelayClient> {
+        Arc::clone(&self.relay)
+    }
+}
+
+impl Transport for HybridTransport {
+    fn start(&self) -> io::Result<()> {
+        self.p2p.start()?;
+        Transport::start(&*self.relay)
+    }
+
+    fn stop(&self) {
+        self.p2p.stop();
+        Transport::stop(&*self.relay);
+    }
+
+    fn set_max_item_size(&self, max_item_size: usize) {
+        self.p2p.set_max_item_size(max_item_size);
+        self.relay.set_max_item_size(max_item_size);
+    }
+
+    fn connected_peers(&self) -> Vec<String> {
+        self.peer_links()
+            .into_iter()
+            .map(|link| link.device_id)
+            .collect()
+    }
+
+    fn peer_links(&self) -> Vec<PeerLink> {
+        let mut links = Transport::peer_links(&*self.p2p);
+        for link in self.relay.peer_links() {
+            if !links.iter().any(|l| l.device_id == link.device_id) {
+                links.push(link);
+            }
+        }
+        links
+    }
+
+    /// Send over the direct link if there is one, falling back to the relay
+    fn send_to(&self, peer_id: &str, message: &NetworkMessage) -> io::Result<()> {
+        if self.p2p.connected_peers().iter().any(|p| p == peer_id) {
+            match self.p2p.send_to(peer_id, message) {
+                Ok(()) => return Ok(()),
+                Err(e) => log::info!("Direct link to {} failed, using relay: {}", peer_id, e),
+            }
+        }
+        Transport::send_to(&*self.relay, peer_id, message)
+    }
+
+    fn broadcast(&self, message: &NetworkMessage) -> io::Result<()> {
+        let peers = match &message.to {
+            Some(to) => to.clone(),
+            None => self.connected_peers(),
+        };
+        for peer_id in peers {
+            if let Err(e) = self.send_to(&peer_id, message) {
+                log::warn!("Failed to send data to {}: {}", peer_id, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Recently delivered messages. Clipboard items are keyed by their id, so a
+/// copy resent over the other path is caught too; everything else by nonce.
+#[derive(Default)]
+struct SeenMessages {
+    order: VecDeque<String>,
+    keys: HashSet<String>,
+}
+
+impl SeenMessages {
+    /// Record a message. Returns false if it was already seen.
+    fn insert(&mut self, message: &NetworkMessage) -> bool {
+        let key = match (&message.msg_type, message.payload.get("id")) {
+            (MessageType::ClipboardUpdate, Some(id)) => format!("clipboard:{}", id),
+            _ => format!("nonce:{}", message.nonce),
+        };
+        if !self.keys.insert(key.clone()) {
+            return false;
+        }
+
+        self.order.push_back(key);
+        if self.order.len() > SEEN_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::message::ClipboardData;
+    use crate::network::relay::tests::start_relay;
+    use crate::network::transport::LinkPath;
+    use ed25519_dalek::SigningKey;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn clipboard_message(data: &ClipboardData) -> NetworkMessage {
+        NetworkMessage::new(
+            MessageType::ClipboardUpdate,
+            "laptop".to_string(),
+            serde_json::to_value(data).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_duplicates_are_dropped() {
+        let mut seen = SeenMessages::default();
+        let data = ClipboardData::new("copied once".to_string());
+
+        // The same item resent as a new message over the other path
+        assert!(seen.insert(&clipboard_message(&data)));
+        assert!(!seen.insert(&clipboard_message(&data)));
+
+        let hello = NetworkMessage::new(
+            MessageType::DeviceHello,
+            "laptop".to_string(),
+            serde_json::json!({}),
+        );
+        assert!(seen.insert(&hello));
+        assert!(!seen.insert(&hello.clone()));
+        assert!(seen.insert(&clipboard_message(&ClipboardData::new("new".to_string()))));
+    }
+
+    #[test]
+    fn test_old_entries_are_forgotten() {
+        let mut seen = SeenMessages::default();
+        let first = ClipboardData::new("first".to_string());
+        assert!(seen.insert(&clipboard_message(&first)));
+        for i in 0..SEEN_CAPACITY {
+            seen.insert(&clipboard_message(&ClipboardData::new(i.to_string())));
+        }
+        assert!(seen.insert(&clipboard_message(&first)));
+    }
+
+    /// A transport for `device_id` on the relay at `url`, its P2P port and
+    /// the clipboard contents it receives
+    fn transport(
+        url: &str,
+        device_id: &str,
+        seed: u8,
+    ) -> (HybridTransport, u16, mpsc::Receiver<String>) {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let key = SigningKey::from_bytes(&[seed; 32]);
+        let p2p = P2PNetwork::new(device_id.to_string(), key.clone(), port, 1024);
+        let relay = RelayClient::new(
+            device_id.to_string(),
+            key,
+            url.to_string(),
+            "shared account secret",
+            1024 * 1024,
+        );
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let transport = HybridTransport::new(p2p, relay, move |msg| {
+            if matches!(msg.msg_type, MessageType::ClipboardUpdate) {
+                let data: ClipboardData = serde_json::from_value(msg.payload).unwrap();
+                let _ = tx.lock().send(data.content);
+            }
+        });
+        (transport, port, rx)
+    }
+
+    fn path_to(transport: &HybridTransport, peer: &str) -> Option<LinkPath> {
+        transport
+            .peer_links()
+            .into_iter()
+            .find(|link| link.device_id == peer)
+            .map(|link| link.path)
+    }
+
+    fn wait_for(what: &str, condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_switches_paths_without_losing_items() {
+        let url = start_relay();
+        let (laptop, _, _laptop_rx) = transport(&url, "laptop", 1);
+        let (desktop, desktop_port, desktop_rx) = transport(&url, "desktop", 2);
+
+        laptop.start().unwrap();
+        wait_for("laptop on the relay", || laptop.relay.is_connected());
+        laptop
+            .relay
+            .add_device("desktop", desktop.relay.public_key())
+            .unwrap();
+        desktop.start().unwrap();
+        wait_for("relay path", || {
+            path_to(&laptop, "desktop") == Some(LinkPath::Relay)
+        });
+
+        let send = |content: &str| {
+            let data = ClipboardData::new(content.to_string());
+            laptop.broadcast(&clipboard_message(&data)).unwrap();
+        };
+        let received = || desktop_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+
+        send("over the relay");
+        assert_eq!(received(), "over the relay");
+
+        // A direct link comes up mid-session
+        laptop
+            .p2p
+            .dial("desktop", SocketAddr::from(([127, 0, 0, 1], desktop_port)));
+        wait_for("direct path", || {
+            path_to(&laptop, "desktop") == Some(LinkPath::Direct)
+                && path_to(&desktop, "laptop") == Some(LinkPath::Direct)
+        });
+        send("over the direct link");
+        assert_eq!(received(), "over the direct link");
+
+        // ...and drops again
+        desktop.p2p.stop();
+        wait_for("relay path again", || {
+            path_to(&laptop, "desktop") == Some(LinkPath::Relay)
+        });
+        send("over the relay again");
+        assert_eq!(received(), "over the relay again");
+
+        // Every item arrived exactly once
+        assert!(desktop_rx.recv_timeout(Duration::from_millis(500)).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::clipboard::SourceApp;
+use serde::{Deserialize, Serialize};
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MessageType {
+    ClipboardUpdate,
+    /// Opens a link (payload: `HandshakeData` on direct links)
+    DeviceHello,
+    /// Answers a hello (payload: `HandshakeData` on direct links)
+    DeviceAck,
+    /// Completes the direct link handshake (payload: `DeviceProof`)
+    DeviceAuth,
+    DeviceGoodbye,
+    /// Ask a peer for history items (payload: `HistoryRequestData`)
+    HistoryRequest,
+    /// A page of history items (payload: `HistoryResponseData`)
+    HistoryResponse,
+    Ping,
+    Pong,
+    /// A shared snippet was added or changed (payload: `SnippetData`)
+    SnippetUpdate,
+    /// A shared snippet was unshared or unpinned (payload: `{"id": ...}`)
+    SnippetRemove,
+    /// The sender's full shared snippet collection (payload: `Vec<SnippetData>`)
+    SnippetSync,
+    /// Proof that the sender holds the keys of its sync groups
+    /// (payload: `Vec<GroupProof>`)
+    GroupHello,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkMessage {
+    pub msg_type: MessageType,
+    pub from: String,
+    pub to: Option<Vec<String>>,
+    pub payload: serde_json::Value,
+    pub timestamp: u64,
+    pub nonce: String,
+    /// Set by the transport that delivered the message once it has verified
+    /// that `from` sent it. Never sent over the wire.
+    #[serde(skip)]
+    pub authenticated: bool,
+}
+
+impl NetworkMessage {
+    #[allow(dead_code)]
+    pub fn new(msg_type: MessageType, from: String, payload: serde_json::Value) -> Self {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        Self {
+            msg_type,
+            from,
+            to: None,
+            payload,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            nonce: uuid::Uuid::new_v4().to_string(),
+            authenticated: false,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, serde_json::Error> {
+        serde_json::to_vec(self)
+    }
+
+    #[allow(dead_code)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(bytes)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipboardData {
+    pub id: String,
+    pub content: String,
+    pub timestamp: u64,
+    /// The application the item was copied from, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceApp>,
+    /// Sync group of the item. Group items carry their content sealed with
+    /// the group key, hex encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+impl ClipboardData {
+    #[allow(dead_code)]
+    pub fn new(content: String) -> Self {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            content,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            source: None,
+            group: None,
+        }
+    }
+}
+
+/// A pinned item shared with peers as part of the snippet collection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnippetData {
+    pub id: String,
+    pub name: Option<String>,
+    pub content: String,
+    pub mime_types: Vec<String>,
+    pub order: Option<i64>,
+    pub timestamp: u64,
+}
+
+/// Position in a peer's history, ordered by timestamp then id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryCursor {
+    pub timestamp: u64,
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRequestData {
+    /// Only items copied after this time (ms since epoch)
+    pub since: u64,
+    /// Resume after this item; `None` for the first page
+    pub cursor: Option<HistoryCursor>,
+    /// Maximum number of items in the response
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntryData {
+    pub id: String,
+    pub origin_device: String,
+    /// Sealed with the group key for group items, like `ClipboardData`
+    pub content: String,
+    pub mime_types: Vec<String>,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryResponseData {
+    /// The `since` value of the request this answers
+    pub since: u64,
+    pub entries: Vec<HistoryEntryData>,
+    /// Cursor for the next page, if there are more items
+    pub next: Option<HistoryCursor>,
+    /// Set when the peer is not allowed to read our history
+    pub denied: bool,
+}
+
+/// A group id and the sender's device id sealed with that group's key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupProof {
+    pub group: String,
+    pub proof: String,
+}
+
+/// Hello and ack of a direct link. Each side sends a challenge for the
+/// other to sign with its identity key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandshakeData {
+    pub device_id: String,
+    /// Ed25519 identity key, hex encoded
+    pub public_key: String,
+    /// Random bytes, hex encoded
+    pub challenge: String,
+    /// The ack's signature over the hello's challenge
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// The dialer's signature over the ack's challenge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceProof {
+    pub signature: String,
+}
@@ -1,11 +1,20 @@
 pub mod discovery;
+pub mod hybrid;
 pub mod message;
 pub mod p2p;
+pub mod relay;
+pub mod transport;
 
 // Re-exports for public API
 #[allow(unused_imports)]
 pub use discovery::DeviceDiscovery;
 #[allow(unused_imports)]
+pub use hybrid::HybridTransport;
+#[allow(unused_imports)]
 pub use message::{MessageType, NetworkMessage};
 #[allow(unused_imports)]
 pub use p2p::P2PNetwork;
+#[allow(unused_imports)]
+pub use relay::{RelayClient, RelayDevice};
+#[allow(unused_imports)]
+pub use transport::{LinkPath, PeerLink, Transport};
@@ -0,0 +1,820 @@
+use super::message::{ClipboardData, DeviceProof, HandshakeData, MessageType, NetworkMessage};
+use super::transport::{LinkPath, PeerLink, Transport};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Bytes allowed on top of the item size for the message envelope. Item
+/// content may be hex encoded, so frames may be twice the item size plus this.
+pub(super) const FRAME_OVERHEAD: usize = 64 * 1024;
+
+/// Prefix of the string each side of a direct link signs
+const AUTH_CONTEXT: &str = "clipbridge-p2p-auth";
+/// How long a new connection may take to authenticate
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[allow(dead_code)]
+pub type MessageCallback = Arc<dyn Fn(NetworkMessage) + Send + Sync>;
+
+/// Decides whether a device may use an identity key it has proven it
+/// holds, e.g. by pinning the first key seen for each device
+pub type KeyCheck = Arc<dyn Fn(&str, &VerifyingKey) -> bool + Send + Sync>;
+
+/// Proves our device id to peers and checks theirs
+struct PeerAuth {
+    device_id: String,
+    key: SigningKey,
+    key_check: OnceLock<KeyCheck>,
+}
+
+/// Where a connection is in the handshake
+enum LinkState {
+    /// We dialed and sent a hello with this challenge
+    Dialed { challenge: String },
+    /// We wait for the dialer's hello
+    Accepted,
+    /// We answered `hello` and wait for the dialer to sign `challenge`
+    Answered {
+        hello: Box<NetworkMessage>,
+        public_key: VerifyingKey,
+        challenge: String,
+    },
+    /// `peer` proved its identity; only its messages are accepted
+    Authenticated { peer: String },
+}
+
+#[allow(dead_code)]
+pub struct P2PNetwork {
+    device_id: String,
+    auth: Arc<PeerAuth>,
+    port: Mutex<u16>,
+    max_frame_size: Arc<Mutex<usize>>,
+    connections: Arc<Mutex<HashMap<String, TcpStream>>>,
+    /// Devices we are dialing, and since when
+    dialing: Mutex<HashMap<String, Instant>>,
+    is_running: Arc<Mutex<bool>>,
+    /// Cleared to stop the current listener thread without stopping the network
+    listener_active: Mutex<Arc<Mutex<bool>>>,
+    on_message: Option<MessageCallback>,
+}
+
+impl P2PNetwork {
+    #[allow(dead_code)]
+    /// A network for `device_id`, which proves its identity with `key`
+    pub fn new(device_id: String, key: SigningKey, port: u16, max_item_size: usize) -> Self {
+        Self {
+            auth: Arc::new(PeerAuth {
+                device_id: device_id.clone(),
+                key,
+                key_check: OnceLock::new(),
+            }),
+            device_id,
+            port: Mutex::new(port),
+            max_frame_size: Arc::new(Mutex::new(max_item_size * 2 + FRAME_OVERHEAD)),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            dialing: Mutex::new(HashMap::new()),
+            is_running: Arc::new(Mutex::new(false)),
+            listener_active: Mutex::new(Arc::new(Mutex::new(false))),
+            on_message: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn set_message_handler<F>(&mut self, callback: F)
+    where
+        F: Fn(NetworkMessage) + Send + Sync + 'static,
+    {
+        self.on_message = Some(Arc::new(callback));
+    }
+
+    /// Check the identity key of every peer before its link is used. Without
+    /// a check, any key is accepted.
+    pub fn set_key_check<F>(&self, check: F)
+    where
+        F: Fn(&str, &VerifyingKey) -> bool + Send + Sync + 'static,
+    {
+        let _ = self.auth.key_check.set(Arc::new(check));
+    }
+
+    /// Start the P2P network listener
+    #[allow(dead_code)]
+    pub fn start(&self) -> std::io::Result<()> {
+        let mut is_running = self.is_running.lock();
+        if *is_running {
+            return Ok(());
+        }
+
+        let port = *self.port.lock();
+        let listener = Self::bind(port)?;
+        *is_running = true;
+        drop(is_running);
+
+        self.spawn_listener(listener, port)
+    }
+
+    /// Move the listener to another port. Existing peer connections are
+    /// kept. If binding the new port fails, the old listener keeps running.
+    pub fn rebind(&self, port: u16) -> std::io::Result<()> {
+        let mut current_port = self.port.lock();
+        if *current_port == port {
+            return Ok(());
+        }
+
+        if !*self.is_running.lock() {
+            *current_port = port;
+            return Ok(());
+        }
+
+        let listener = TcpListener::bind(format!("0.0.0.0:{}", port))?;
+        log::info!(
+            "Moving P2P listener from port {} to {}",
+            *current_port,
+            port
+        );
+        *current_port = port;
+        drop(current_port);
+
+        self.spawn_listener(listener, port)
+    }
+
+    /// The port the listener is on
+    pub fn port(&self) -> u16 {
+        *self.port.lock()
+    }
+
+    /// Change the largest accepted item size. Applies to the next frame
+    /// read on every connection.
+    pub fn set_max_item_size(&self, max_item_size: usize) {
+        *self.max_frame_size.lock() = max_item_size * 2 + FRAME_OVERHEAD;
+    }
+
+    /// Bind the listener port. A listener being retired, e.g. by a sync
+    /// restart, releases the port within one accept poll, so retry briefly.
+    fn bind(port: u16) -> std::io::Result<TcpListener> {
+        let mut attempts = 0;
+        loop {
+            match TcpListener::bind(format!("0.0.0.0:{}", port)) {
+                Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && attempts < 10 => {
+                    attempts += 1;
+                    thread::sleep(std::time::Duration::from_millis(100));
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn spawn_listener(&self, listener: TcpListener, port: u16) -> std::io::Result<()> {
+        listener.set_nonblocking(true)?;
+
+        // Retire the previous listener thread, if any
+        let active = Arc::new(Mutex::new(true));
+        let previous = std::mem::replace(&mut *self.listener_active.lock(), Arc::clone(&active));
+        *previous.lock() = false;
+
+        let is_running_clone = Arc::clone(&self.is_running);
+        let connections_clone = Arc::clone(&self.connections);
+        let auth_clone = Arc::clone(&self.auth);
+        let on_message_clone = self.on_message.clone();
+        let max_frame_size_clone = Arc::clone(&self.max_frame_size);
+
+        thread::spawn(move || {
+            log::info!("P2P listener started on port {}", port);
+
+            while *is_running_clone.lock() && *active.lock() {
+                match listener.accept() {
+                    Ok((stream, addr)) => {
+                        log::info!("New P2P connection from {}", addr);
+                        let connections = Arc::clone(&connections_clone);
+                        let auth = Arc::clone(&auth_clone);
+                        let max_frame_size = Arc::clone(&max_frame_size_clone);
+                        let on_message = on_message_clone.clone();
+
+                        thread::spawn(move || {
+                            Self::handle_connection(
+                                stream,
+                                addr,
+                                connections,
+                                auth,
+                                max_frame_size,
+                                on_message,
+                                LinkState::Accepted,
+                            );
+                        });
+                    }
+                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        // No pending connections, sleep briefly
+                        thread::sleep(std::time::Duration::from_millis(100));
+                    }
+                    Err(e) => {
+                        log::error!("Error accepting connection: {}", e);
+                    }
+                }
+            }
+
+            log::info!("P2P listener on port {} stopped", port);
+        });
+
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn stop(&self) {
+        let mut is_running = self.is_running.lock();
+        *is_running = false;
+
+        // Close all connections, so peers see the link drop
+        let mut connections = self.connections.lock();
+        for stream in connections.values() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        connections.clear();
+    }
+
+    /// Connect to `device_id` at `addr` in the background, unless it is
+    /// already linked or a recent attempt is still pending
+    pub fn dial(self: &Arc<Self>, device_id: &str, addr: SocketAddr) {
+        if !*self.is_running.lock() || self.connections.lock().contains_key(device_id) {
+            return;
+        }
+        {
+            let mut dialing = self.dialing.lock();
+            dialing.retain(|_, since| since.elapsed() < HANDSHAKE_TIMEOUT);
+            if dialing.contains_key(device_id) {
+                return;
+            }
+            dialing.insert(device_id.to_string(), Instant::now());
+        }
+
+        let network = Arc::clone(self);
+        thread::spawn(move || {
+            let _ = network.connect_to_peer(addr);
+        });
+    }
+
+    /// Connect to a peer device. The link is used once the peer has proven
+    /// its identity.
+    #[allow(dead_code)]
+    pub fn connect_to_peer(&self, addr: SocketAddr) -> std::io::Result<()> {
+        log::info!("Connecting to peer at {}", addr);
+
+        match TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT) {
+            Ok(stream) => {
+                let challenge = new_challenge();
+                let hello = self
+                    .auth
+                    .handshake(MessageType::DeviceHello, &challenge, None);
+                Self::write_frame(&stream, &hello.to_bytes().map_err(std::io::Error::other)?)?;
+
+                // The peer answers with DeviceAck, which we check before
+                // registering the connection
+                let connections = Arc::clone(&self.connections);
+                let auth = Arc::clone(&self.auth);
+                let on_message = self.on_message.clone();
+                let max_frame_size = Arc::clone(&self.max_frame_size);
+                thread::spawn(move || {
+                    Self::handle_connection(
+                        stream,
+                        addr,
+                        connections,
+                        auth,
+                        max_frame_size,
+                        on_message,
+                        LinkState::Dialed { challenge },
+                    );
+                });
+
+                log::info!("Connected to peer at {}", addr);
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("Failed to connect to peer at {}: {}", addr, e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Broadcast clipboard data to connected peers, or only to `to`
+    #[allow(dead_code)]
+    pub fn broadcast_clipboard(
+        &self,
+        clipboard_data: ClipboardData,
+        to: Option<Vec<String>>,
+    ) -> std::io::Result<()> {
+        let mut message = NetworkMessage::new(
+            MessageType::ClipboardUpdate,
+            self.device_id.clone(),
+            serde_json::to_value(&clipboard_data).unwrap(),
+        );
+        message.to = to;
+
+        self.broadcast(&message)
+    }
+
+    /// Send a message to all connected peers, or only to those listed in
+    /// its `to` field
+    pub fn broadcast(&self, message: &NetworkMessage) -> std::io::Result<()> {
+        let bytes = message.to_bytes().map_err(std::io::Error::other)?;
+
+        let connections = self.connections.lock();
+        for (peer_id, stream) in connections.iter() {
+            if message.to.as_ref().is_some_and(|to| !to.contains(peer_id)) {
+                continue;
+            }
+            if let Err(e) = Self::write_frame(stream, &bytes) {
+                log::warn!("Failed to send data to {}: {}", peer_id, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Device ids of the currently connected peers
+    pub fn connected_peers(&self) -> Vec<String> {
+        self.connections.lock().keys().cloned().collect()
+    }
+
+    /// Send a message to a single connected peer
+    pub fn send_to(&self, peer_id: &str, message: &NetworkMessage) -> std::io::Result<()> {
+        let bytes = message.to_bytes().map_err(std::io::Error::other)?;
+
+        let connections = self.connections.lock();
+        let stream = connections.get(peer_id).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                format!("Peer {} is not connected", peer_id),
+            )
+        })?;
+
+        Self::write_frame(stream, &bytes)
+    }
+
+    /// Write a length-prefixed frame
+    fn write_frame(stream: &TcpStream, bytes: &[u8]) -> std::io::Result<()> {
+        let mut stream = stream.try_clone()?;
+        let len = bytes.len() as u32;
+        stream.write_all(&len.to_be_bytes())?;
+        stream.write_all(bytes)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn handle_connection(
+        mut stream: TcpStream,
+        addr: SocketAddr,
+        connections: Arc<Mutex<HashMap<String, TcpStream>>>,
+        auth: Arc<PeerAuth>,
+        max_frame_size: Arc<Mutex<usize>>,
+        on_message: Option<MessageCallback>,
+        mut state: LinkState,
+    ) {
+        let mut len_buf = [0u8; 4];
+        let mut registered = false;
+
+        // Don't let a silent or slow peer hold the thread before it has
+        // proven who it is
+        if let Err(e) = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)) {
+            log::warn!("Failed to set handshake timeout for {}: {}", addr, e);
+        }
+
+        loop {
+            // Read message length
+            match stream.read_exact(&mut len_buf) {
+                Ok(_) => {
+                    let len = u32::from_be_bytes(len_buf) as usize;
+
+                    if len > *max_frame_size.lock() {
+                        log::warn!("Message too large: {} bytes", len);
+                        break;
+                    }
+
+                    let mut buf = vec![0u8; len];
+                    match stream.read_exact(&mut buf) {
+                        Ok(_) => {
+                            match NetworkMessage::from_bytes(&buf) {
+                                Ok(msg) => {
+                                    log::debug!(
+                                        "Received message from {}: {:?}",
+                                        addr,
+                                        msg.msg_type
+                                    );
+
+                                    let deliver = match auth.receive(&mut state, msg, &stream) {
+                                        Ok(deliver) => deliver,
+                                        Err(e) => {
+                                            log::warn!(
+                                                "Closing P2P connection from {}: {}",
+                                                addr,
+                                                e
+                                            );
+                                            break;
+                                        }
+                                    };
+
+                                    // Register the link once the peer is authenticated
+                                    if let (LinkState::Authenticated { peer }, false) =
+                                        (&state, registered)
+                                    {
+                                        registered = true;
+                                        let _ = stream.set_read_timeout(None);
+                                        if let Ok(stream_clone) = stream.try_clone() {
+                                            connections.lock().insert(peer.clone(), stream_clone);
+                                            log::info!("Added peer connection: {}", peer);
+                                        }
+                                    }
+
+                                    // Call message handler
+                                    if let (Some(callback), Some(mut msg)) = (&on_message, deliver)
+                                    {
+                                        msg.authenticated = true;
+                                        callback(msg);
+                                    }
+                                }
+                                Err(e) => {
+                                    log::error!("Failed to parse message: {}", e);
+                                    break;
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            log::debug!("Connection closed: {}", e);
+                            break;
+                        }
+                    }
+                }
+                Err(e) => {
+                    log::debug!("Connection closed: {}", e);
+                    break;
+                }
+            }
+        }
+
+        // Forget the link unless the peer has already reconnected
+        if let LinkState::Authenticated { peer: peer_id } = state {
+            let mut connections = connections.lock();
+            let current = connections
+                .get(&peer_id)
+                .and_then(|stream| stream.peer_addr().ok());
+            if current.is_none() || current == Some(addr) {
+                connections.remove(&peer_id);
+                log::info!("Removed peer connection: {}", peer_id);
+            }
+        }
+    }
+}
+
+impl PeerAuth {
+    /// Advance the handshake with `msg`. Returns the message to deliver, if
+    /// any, or an error if the connection must be closed.
+    fn receive(
+        &self,
+        state: &mut LinkState,
+        msg: NetworkMessage,
+        stream: &TcpStream,
+    ) -> Result<Option<NetworkMessage>, String> {
+        match (&*state, &msg.msg_type) {
+            (LinkState::Authenticated { peer }, msg_type) => {
+                if msg.from != *peer {
+                    return Err(format!("{} sent a message as {}", peer, msg.from));
+                }
+                if matches!(
+                    msg_type,
+                    MessageType::DeviceHello | MessageType::DeviceAck | MessageType::DeviceAuth
+                ) {
+                    log::debug!("Ignoring repeated {:?} from {}", msg_type, peer);
+                    return Ok(None);
+                }
+                Ok(Some(msg))
+            }
+            (LinkState::Accepted, MessageType::DeviceHello) => {
+                if msg.from == self.device_id {
+                    return Err("Hello from our own device id".to_string());
+                }
+                let hello: HandshakeData =
+                    serde_json::from_value(msg.payload.clone()).map_err(|e| e.to_string())?;
+                let public_key = parse_key(&hello.public_key)?;
+
+                let challenge = new_challenge();
+                let proof = self.sign(&hello.challenge, &msg.from);
+                let ack = self.handshake(MessageType::DeviceAck, &challenge, Some(proof));
+                write_message(stream, &ack)?;
+
+                *state = LinkState::Answered {
+                    hello: Box::new(msg),
+                    public_key,
+                    challenge,
+                };
+                Ok(None)
+            }
+            (LinkState::Dialed { challenge }, MessageType::DeviceAck) => {
+                let ack: HandshakeData =
+                    serde_json::from_value(msg.payload.clone()).map_err(|e| e.to_string())?;
+                let public_key = parse_key(&ack.public_key)?;
+                let signature = ack.signature.as_deref().ok_or("Ack without a signature")?;
+                self.verify(&public_key, challenge, &msg.from, signature)?;
+                self.check_key(&msg.from, &public_key)?;
+
+                let proof = DeviceProof {
+                    signature: self.sign(&ack.challenge, &msg.from),
+                };
+                let auth = NetworkMessage::new(
+                    MessageType::DeviceAuth,
+                    self.device_id.clone(),
+                    serde_json::to_value(proof).map_err(|e| e.to_string())?,
+                );
+                write_message(stream, &auth)?;
+
+                *state = LinkState::Authenticated {
+                    peer: msg.from.clone(),
+                };
+                Ok(Some(msg))
+            }
+            (
+                LinkState::Answered {
+                    hello,
+                    public_key,
+                    challenge,
+                },
+                MessageType::DeviceAuth,
+            ) => {
+                if msg.from != hello.from {
+                    return Err(format!("{} answered the hello of {}", msg.from, hello.from));
+                }
+                let proof: DeviceProof =
+                    serde_json::from_value(msg.payload).map_err(|e| e.to_string())?;
+                self.verify(public_key, challenge, &hello.from, &proof.signature)?;
+                self.check_key(&hello.from, public_key)?;
+
+                // The hello is what the sync engine reacts to
+                let hello = NetworkMessage::clone(hello);
+                *state = LinkState::Authenticated {
+                    peer: hello.from.clone(),
+                };
+                Ok(Some(hello))
+            }
+            (_, msg_type) => Err(format!("Unexpected {:?} before authentication", msg_type)),
+        }
+    }
+
+    /// A hello or ack carrying our key and a challenge for the peer
+    fn handshake(
+        &self,
+        msg_type: MessageType,
+        challenge: &str,
+        signature: Option<String>,
+    ) -> NetworkMessage {
+        let data = HandshakeData {
+            device_id: self.device_id.clone(),
+            public_key: hex::encode(self.key.verifying_key().as_bytes()),
+            challenge: challenge.to_string(),
+            signature,
+        };
+        NetworkMessage::new(
+            msg_type,
+            self.device_id.clone(),
+            serde_json::to_value(data).unwrap(),
+        )
+    }
+
+    /// Sign the challenge `verifier` sent us
+    fn sign(&self, challenge: &str, verifier: &str) -> String {
+        let signed = signed_text(challenge, &self.device_id, verifier);
+        hex::encode(self.key.sign(signed.as_bytes()).to_bytes())
+    }
+
+    /// Check that `signer` signed the challenge we sent it
+    fn verify(
+        &self,
+        public_key: &VerifyingKey,
+        challenge: &str,
+        signer: &str,
+        signature: &str,
+    ) -> Result<(), String> {
+        let signature = hex::decode(signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or("Malformed signature")?;
+        let signed = signed_text(challenge, signer, &self.device_id);
+        public_key
+            .verify(signed.as_bytes(), &signature)
+            .map_err(|_| format!("Bad signature from {}", signer))
+    }
+
+    fn check_key(&self, device_id: &str, public_key: &VerifyingKey) -> Result<(), String> {
+        match self.key_check.get() {
+            Some(check) if !check(device_id, public_key) => {
+                Err(format!("{} used an unknown identity key", device_id))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What `signer` signs to answer the challenge of `verifier`
+fn signed_text(challenge: &str, signer: &str, verifier: &str) -> String {
+    format!("{}:{}:{}:{}", AUTH_CONTEXT, challenge, signer, verifier)
+}
+
+fn new_challenge() -> String {
+    let mut challenge = [0u8; 32];
+    OsRng.fill_bytes(&mut challenge);
+    hex::encode(challenge)
+}
+
+fn parse_key(public_key: &str) -> Result<VerifyingKey, String> {
+    hex::decode(public_key)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| "Malformed public key".to_string())
+}
+
+fn write_message(stream: &TcpStream, message: &NetworkMessage) -> Result<(), String> {
+    let bytes = message.to_bytes().map_err(|e| e.to_string())?;
+    P2PNetwork::write_frame(stream, &bytes).map_err(|e| e.to_string())
+}
+
+impl Transport for P2PNetwork {
+    fn start(&self) -> std::io::Result<()> {
+        P2PNetwork::start(self)
+    }
+
+    fn stop(&self) {
+        P2PNetwork::stop(self)
+    }
+
+    fn set_max_item_size(&self, max_item_size: usize) {
+        P2PNetwork::set_max_item_size(self, max_item_size)
+    }
+
+    fn connected_peers(&self) -> Vec<String> {
+        P2PNetwork::connected_peers(self)
+    }
+
+    fn peer_links(&self) -> Vec<PeerLink> {
+        self.connected_peers()
+            .into_iter()
+            .map(|device_id| PeerLink {
+                device_id,
+                path: LinkPath::Direct,
+                online: true,
+            })
+            .collect()
+    }
+
+    fn send_to(&self, peer_id: &str, message: &NetworkMessage) -> std::io::Result<()> {
+        P2PNetwork::send_to(self, peer_id, message)
+    }
+
+    fn broadcast(&self, message: &NetworkMessage) -> std::io::Result<()> {
+        P2PNetwork::broadcast(self, message)
+    }
+}
+
+impl Drop for P2PNetwork {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    /// A started network for `device_id` and the messages it receives
+    fn network(
+        device_id: &str,
+        key: SigningKey,
+    ) -> (P2PNetwork, u16, mpsc::Receiver<NetworkMessage>) {
+        let port = free_port();
+        let mut network = P2PNetwork::new(device_id.to_string(), key, port, 1024);
+        let (tx, rx) = mpsc::channel();
+        network.set_message_handler(move |msg| {
+            let _ = tx.send(msg);
+        });
+        network.start().unwrap();
+        (network, port, rx)
+    }
+
+    fn wait_for(what: &str, condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn local(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test_peers_authenticate_before_their_link_is_used() {
+        let (desktop, desktop_port, desktop_rx) = network("desktop", signing_key(1));
+        let (laptop, _, laptop_rx) = network("laptop", signing_key(2));
+
+        laptop.connect_to_peer(local(desktop_port)).unwrap();
+        wait_for("both links", || {
+            desktop.connected_peers() == vec!["laptop".to_string()]
+                && laptop.connected_peers() == vec!["desktop".to_string()]
+        });
+
+        let hello = desktop_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(hello.msg_type, MessageType::DeviceHello));
+        assert!(hello.authenticated);
+        let ack = laptop_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(ack.msg_type, MessageType::DeviceAck));
+        assert_eq!(ack.from, "desktop");
+
+        laptop
+            .broadcast_clipboard(ClipboardData::new("over the LAN".to_string()), None)
+            .unwrap();
+        let item = desktop_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(item.msg_type, MessageType::ClipboardUpdate));
+        assert!(item.authenticated);
+        assert_eq!(item.from, "laptop");
+    }
+
+    #[test]
+    fn test_impostors_are_refused() {
+        let (desktop, desktop_port, desktop_rx) = network("desktop", signing_key(1));
+        let pinned = signing_key(2).verifying_key();
+        desktop.set_key_check(move |device_id, public_key| {
+            device_id != "laptop" || *public_key == pinned
+        });
+
+        // Another key claiming the laptop's device id
+        let (impostor, _, _impostor_rx) = network("laptop", signing_key(3));
+        impostor.connect_to_peer(local(desktop_port)).unwrap();
+
+        // A client that skips the handshake
+        let mut raw = TcpStream::connect(local(desktop_port)).unwrap();
+        let update = NetworkMessage::new(
+            MessageType::ClipboardUpdate,
+            "laptop".to_string(),
+            serde_json::to_value(ClipboardData::new("spoofed".to_string())).unwrap(),
+        );
+        P2PNetwork::write_frame(&raw, &update.to_bytes().unwrap()).unwrap();
+        let mut buf = [0u8; 4];
+        raw.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        assert_eq!(raw.read(&mut buf).unwrap(), 0, "connection not closed");
+
+        // The real laptop still gets through
+        let (laptop, _, _laptop_rx) = network("laptop", signing_key(2));
+        laptop.connect_to_peer(local(desktop_port)).unwrap();
+        wait_for("the laptop's link", || {
+            desktop.connected_peers() == vec!["laptop".to_string()]
+        });
+        let hello = desktop_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(hello.msg_type, MessageType::DeviceHello));
+        assert!(desktop_rx.try_recv().is_err());
+        assert!(impostor.connected_peers().is_empty());
+    }
+
+    #[test]
+    fn test_frames_must_come_from_the_authenticated_peer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let auth = PeerAuth {
+            device_id: "desktop".to_string(),
+            key: signing_key(1),
+            key_check: OnceLock::new(),
+        };
+        let mut state = LinkState::Authenticated {
+            peer: "laptop".to_string(),
+        };
+
+        let own = NetworkMessage::new(
+            MessageType::Ping,
+            "laptop".to_string(),
+            serde_json::json!({}),
+        );
+        assert!(auth.receive(&mut state, own, &stream).unwrap().is_some());
+
+        let spoofed = NetworkMessage::new(
+            MessageType::Ping,
+            "phone".to_string(),
+            serde_json::json!({}),
+        );
+        assert!(auth.receive(&mut state, spoofed, &stream).is_err());
+    }
+}
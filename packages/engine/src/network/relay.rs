@@ -0,0 +1,641 @@
+use super::message::{MessageType, NetworkMessage};
+use super::p2p::{MessageCallback, FRAME_OVERHEAD};
+use super::transport::{LinkPath, PeerLink, Transport};
+use crate::history::ContentCipher;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use ed25519_dalek::{Signer, SigningKey};
+use hkdf::Hkdf;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::VecDeque;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::Duration;
+use tungstenite::client::IntoClientRequest;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+/// Prefix of the string signed to answer the relay's challenge
+const AUTH_CONTEXT: &str = "clipbridge-relay-auth";
+const ACCOUNT_INFO: &[u8] = b"clipbridge relay account v1";
+const PAYLOAD_INFO: &[u8] = b"clipbridge relay payload v1";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a read waits before pending frames are written
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Frames kept while disconnected; older ones are dropped
+const MAX_OUTBOX: usize = 200;
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// Called with the account's devices whenever the relay reports a change
+pub type DevicesCallback = Arc<dyn Fn(&[RelayDevice]) + Send + Sync>;
+
+/// A device registered on our relay account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayDevice {
+    pub device_id: String,
+    pub public_key: String,
+    pub online: bool,
+}
+
+/// Mirrors `ClientFrame` in packages/relay-server
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum ClientFrame<'a> {
+    #[serde(rename_all = "camelCase")]
+    Hello {
+        account: &'a str,
+        device_id: &'a str,
+        public_key: &'a str,
+    },
+    Auth {
+        signature: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    AddDevice {
+        device_id: &'a str,
+        public_key: &'a str,
+    },
+    #[serde(rename_all = "camelCase")]
+    RemoveDevice {
+        device_id: &'a str,
+    },
+    Frame {
+        message: &'a NetworkMessage,
+    },
+}
+
+/// Mirrors `ServerFrame` in packages/relay-server
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum ServerFrame {
+    Challenge { nonce: String },
+    Welcome { devices: Vec<RelayDevice> },
+    Devices { devices: Vec<RelayDevice> },
+    Frame { message: NetworkMessage },
+    Error { message: String },
+}
+
+/// Connects to a ClipBridge relay server over WebSocket. Reconnects with
+/// jittered backoff, and keeps outgoing frames while disconnected.
+///
+/// Devices of one account share a secret. The relay only sees an account
+/// id derived from it; message payloads are sealed with a key derived from
+/// it, so the relay never sees clipboard content.
+pub struct RelayClient {
+    inner: Arc<RelayInner>,
+}
+
+struct RelayInner {
+    device_id: String,
+    url: String,
+    account: String,
+    key: SigningKey,
+    public_key: String,
+    cipher: ContentCipher,
+    max_frame_size: Mutex<usize>,
+    is_running: Mutex<bool>,
+    connected: Mutex<bool>,
+    devices: Mutex<Vec<RelayDevice>>,
+    /// Serialized frames waiting to be written
+    outbox: Mutex<VecDeque<String>>,
+    on_message: OnceLock<MessageCallback>,
+    on_devices: OnceLock<DevicesCallback>,
+}
+
+impl RelayClient {
+    pub fn new(
+        device_id: String,
+        key: SigningKey,
+        url: String,
+        account_secret: &str,
+        max_item_size: usize,
+    ) -> Self {
+        let mut account = [0u8; 16];
+        Hkdf::<Sha256>::new(None, account_secret.as_bytes())
+            .expand(ACCOUNT_INFO, &mut account)
+            .expect("16 bytes is a valid HKDF-SHA256 output length");
+
+        Self {
+            inner: Arc::new(RelayInner {
+                device_id,
+                url,
+                account: hex::encode(account),
+                public_key: hex::encode(key.verifying_key().as_bytes()),
+                key,
+                cipher: ContentCipher::derive_for(account_secret.as_bytes(), PAYLOAD_INFO),
+                max_frame_size: Mutex::new(frame_size_for(max_item_size)),
+                is_running: Mutex::new(false),
+                connected: Mutex::new(false),
+                devices: Mutex::new(Vec::new()),
+                outbox: Mutex::new(VecDeque::new()),
+                on_message: OnceLock::new(),
+                on_devices: OnceLock::new(),
+            }),
+        }
+    }
+
+    pub fn set_message_handler<F>(&self, callback: F)
+    where
+        F: Fn(NetworkMessage) + Send + Sync + 'static,
+    {
+        let _ = self.inner.on_message.set(Arc::new(callback));
+    }
+
+    pub fn set_devices_handler<F>(&self, callback: F)
+    where
+        F: Fn(&[RelayDevice]) + Send + Sync + 'static,
+    {
+        let _ = self.inner.on_devices.set(Arc::new(callback));
+    }
+
+    /// Our hex encoded public key, which another device of the account
+    /// registers with `add_device`
+    pub fn public_key(&self) -> &str {
+        &self.inner.public_key
+    }
+
+    pub fn is_connected(&self) -> bool {
+        *self.inner.connected.lock()
+    }
+
+    /// Devices registered on the account, as last reported by the relay
+    pub fn devices(&self) -> Vec<RelayDevice> {
+        self.inner.devices.lock().clone()
+    }
+
+    /// Register another device on the account
+    pub fn add_device(&self, device_id: &str, public_key: &str) -> io::Result<()> {
+        self.inner.enqueue(&ClientFrame::AddDevice {
+            device_id,
+            public_key,
+        })
+    }
+
+    pub fn remove_device(&self, device_id: &str) -> io::Result<()> {
+        self.inner.enqueue(&ClientFrame::RemoveDevice { device_id })
+    }
+}
+
+impl Transport for RelayClient {
+    fn start(&self) -> io::Result<()> {
+        let mut is_running = self.inner.is_running.lock();
+        if *is_running {
+            return Ok(());
+        }
+        *is_running = true;
+
+        let inner = Arc::clone(&self.inner);
+        thread::spawn(move || inner.run());
+        Ok(())
+    }
+
+    fn stop(&self) {
+        *self.inner.is_running.lock() = false;
+    }
+
+    /// Applies from the next connection
+    fn set_max_item_size(&self, max_item_size: usize) {
+        *self.inner.max_frame_size.lock() = frame_size_for(max_item_size);
+    }
+
+    /// Every other device on the account. The relay queues messages for
+    /// devices that are offline.
+    fn connected_peers(&self) -> Vec<String> {
+        self.inner
+            .devices
+            .lock()
+            .iter()
+            .filter(|device| device.device_id != self.inner.device_id)
+            .map(|device| device.device_id.clone())
+            .collect()
+    }
+
+    fn peer_links(&self) -> Vec<PeerLink> {
+        self.inner
+            .devices
+            .lock()
+            .iter()
+            .filter(|device| device.device_id != self.inner.device_id)
+            .map(|device| PeerLink {
+                device_id: device.device_id.clone(),
+                path: LinkPath::Relay,
+                online: device.online,
+            })
+            .collect()
+    }
+
+    fn send_to(&self, peer_id: &str, message: &NetworkMessage) -> io::Result<()> {
+        let mut message = message.clone();
+        message.to = Some(vec![peer_id.to_string()]);
+        self.inner.send(message)
+    }
+
+    fn broadcast(&self, message: &NetworkMessage) -> io::Result<()> {
+        self.inner.send(message.clone())
+    }
+}
+
+impl Drop for RelayClient {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl RelayInner {
+    fn is_running(&self) -> bool {
+        *self.is_running.lock()
+    }
+
+    /// Connect, serve the connection until it drops and reconnect, until
+    /// stopped
+    fn run(&self) {
+        let mut attempt = 0;
+
+        while self.is_running() {
+            match self.connect() {
+                Ok(socket) => {
+                    attempt = 0;
+                    log::info!("Connected to relay {}", self.url);
+                    *self.connected.lock() = true;
+                    if let Err(e) = self.serve(socket) {
+                        log::warn!("Relay connection lost: {}", e);
+                    }
+                    *self.connected.lock() = false;
+                    // Keep the devices so messages for them are queued
+                    let mut devices = self.devices.lock().clone();
+                    for device in devices.iter_mut() {
+                        device.online = false;
+                    }
+                    self.set_devices(devices);
+                }
+                Err(e) => log::warn!("Failed to connect to relay {}: {}", self.url, e),
+            }
+
+            let delay = backoff(attempt);
+            attempt = attempt.saturating_add(1);
+            let mut waited = Duration::ZERO;
+            while waited < delay && self.is_running() {
+                thread::sleep(POLL_INTERVAL);
+                waited += POLL_INTERVAL;
+            }
+        }
+
+        log::info!("Relay client stopped");
+    }
+
+    fn connect(&self) -> io::Result<Socket> {
+        let request = self
+            .url
+            .as_str()
+            .into_client_request()
+            .map_err(io::Error::other)?;
+        let uri = request.uri();
+        let host = uri
+            .host()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Relay URL has no host"))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let default_port = if uri.scheme_str() == Some("wss") {
+            443
+        } else {
+            80
+        };
+        let addr = (host.as_str(), uri.port_u16().unwrap_or(default_port))
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Relay host not found"))?;
+
+        let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+
+        let max_frame_size = *self.max_frame_size.lock();
+        let config = WebSocketConfig {
+            max_message_size: Some(max_frame_size),
+            max_frame_size: Some(max_frame_size),
+            ..Default::default()
+        };
+        let (mut socket, _) =
+            tungstenite::client_tls_with_config(request, stream, Some(config), None)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+
+        self.authenticate(&mut socket)?;
+        set_read_timeout(&socket, POLL_INTERVAL)?;
+        Ok(socket)
+    }
+
+    fn authenticate(&self, socket: &mut Socket) -> io::Result<()> {
+        write_frame(
+            socket,
+            &ClientFrame::Hello {
+                account: &self.account,
+                device_id: &self.device_id,
+                public_key: &self.public_key,
+            },
+        )?;
+
+        let nonce = match read_frame(socket)? {
+            ServerFrame::Challenge { nonce } => nonce,
+            ServerFrame::Error { message } => return Err(rejected(message)),
+            _ => return Err(io::Error::other("Expected a challenge from the relay")),
+        };
+        let signed = format!(
+            "{}:{}:{}:{}",
+            AUTH_CONTEXT, nonce, self.account, self.device_id
+        );
+        let signature = hex::encode(self.key.sign(signed.as_bytes()).to_bytes());
+        write_frame(socket, &ClientFrame::Auth { signature })?;
+
+        match read_frame(socket)? {
+            ServerFrame::Welcome { devices } => self.set_devices(devices),
+            ServerFrame::Error { message } => return Err(rejected(message)),
+            _ => return Err(io::Error::other("Expected a welcome from the relay")),
+        }
+
+        // Let online devices know we are here, like a P2P hello
+        let hello = NetworkMessage::new(
+            MessageType::DeviceHello,
+            self.device_id.clone(),
+            serde_json::json!({ "device_id": self.device_id }),
+        );
+        self.send(hello)
+    }
+
+    /// Write queued frames and dispatch incoming ones until the connection
+    /// drops or the client is stopped
+    fn serve(&self, mut socket: Socket) -> io::Result<()> {
+        loop {
+            if !self.is_running() {
+                let _ = socket.close(None);
+                let _ = socket.flush();
+                return Ok(());
+            }
+
+            loop {
+                let Some(frame) = self.outbox.lock().pop_front() else {
+                    break;
+                };
+                if let Err(e) = socket.send(Message::Text(frame.clone())) {
+                    self.outbox.lock().push_front(frame);
+                    return Err(io::Error::other(e));
+                }
+            }
+
+            match socket.read() {
+                Ok(Message::Text(text)) => self.handle_frame(&text),
+                Ok(Message::Close(_)) => return Ok(()),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) => {}
+                Err(e) => return Err(io::Error::other(e)),
+            }
+        }
+    }
+
+    fn handle_frame(&self, text: &str) {
+        let frame = match serde_json::from_str::<ServerFrame>(text) {
+            Ok(frame) => frame,
+            Err(e) => {
+                log::warn!("Invalid frame from relay: {}", e);
+                return;
+            }
+        };
+
+        match frame {
+            ServerFrame::Frame { message } => self.receive(message),
+            ServerFrame::Welcome { devices } | ServerFrame::Devices { devices } => {
+                self.set_devices(devices)
+            }
+            ServerFrame::Error { message } => log::warn!("Relay error: {}", message),
+            ServerFrame::Challenge { .. } => {}
+        }
+    }
+
+    fn receive(&self, mut message: NetworkMessage) {
+        let opened = message
+            .payload
+            .as_str()
+            .and_then(|sealed| hex::decode(sealed).ok())
+            .and_then(|sealed| self.cipher.open(&sealed))
+            .and_then(|json| serde_json::from_str(&json).ok());
+        let Some(payload) = opened else {
+            log::warn!(
+                "Dropping relayed message from {} we cannot open",
+                message.from
+            );
+            return;
+        };
+        message.payload = payload;
+        // The relay checks `from` against the device it authenticated, and
+        // only account members can seal payloads
+        message.authenticated = true;
+
+        if matches!(message.msg_type, MessageType::DeviceHello) {
+            let mut ack = NetworkMessage::new(
+                MessageType::DeviceAck,
+                self.device_id.clone(),
+                serde_json::json!({ "device_id": self.device_id }),
+            );
+            ack.to = Some(vec![message.from.clone()]);
+            if let Err(e) = self.send(ack) {
+                log::warn!("Failed to answer hello from {}: {}", message.from, e);
+            }
+        }
+
+        if let Some(callback) = self.on_message.get() {
+            callback(message);
+        }
+    }
+
+    fn set_devices(&self, devices: Vec<RelayDevice>) {
+        *self.devices.lock() = devices.clone();
+        if let Some(callback) = self.on_devices.get() {
+            callback(&devices);
+        }
+    }
+
+    /// Seal the payload and queue the message for the relay
+    fn send(&self, mut message: NetworkMessage) -> io::Result<()> {
+        let payload = serde_json::to_string(&message.payload).map_err(io::Error::other)?;
+        message.payload = serde_json::Value::String(hex::encode(self.cipher.seal(&payload)));
+        self.enqueue(&ClientFrame::Frame { message: &message })
+    }
+
+    fn enqueue(&self, frame: &ClientFrame) -> io::Result<()> {
+        let text = serde_json::to_string(frame).map_err(io::Error::other)?;
+        let mut outbox = self.outbox.lock();
+        outbox.push_back(text);
+        if outbox.len() > MAX_OUTBOX {
+            outbox.pop_front();
+            log::warn!("Relay outbox full, dropped the oldest frame");
+        }
+        Ok(())
+    }
+}
+
+/// Payloads are sealed and hex encoded on top of the P2P frame size
+fn frame_size_for(max_item_size: usize) -> usize {
+    (max_item_size * 2 + FRAME_OVERHEAD) * 2 + FRAME_OVERHEAD
+}
+
+/// Exponential backoff with jitter: between half and all of
+/// `MIN_BACKOFF * 2^attempt`, capped at `MAX_BACKOFF`
+fn backoff(attempt: u32) -> Duration {
+    let ceiling = MIN_BACKOFF
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_BACKOFF);
+    let half = ceiling / 2;
+    let jitter = OsRng.next_u64() % (half.as_millis() as u64 + 1);
+    half + Duration::from_millis(jitter)
+}
+
+fn rejected(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, message)
+}
+
+fn set_read_timeout(socket: &Socket, timeout: Duration) -> io::Result<()> {
+    match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(timeout)),
+        MaybeTlsStream::Rustls(stream) => stream.get_ref().set_read_timeout(Some(timeout)),
+        _ => Ok(()),
+    }
+}
+
+fn write_frame(socket: &mut Socket, frame: &ClientFrame) -> io::Result<()> {
+    let text = serde_json::to_string(frame).map_err(io::Error::other)?;
+    socket.send(Message::Text(text)).map_err(io::Error::other)
+}
+
+fn read_frame(socket: &mut Socket) -> io::Result<ServerFrame> {
+    loop {
+        match socket.read().map_err(io::Error::other)? {
+            Message::Text(text) => return serde_json::from_str(&text).map_err(io::Error::other),
+            Message::Close(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "Relay closed the connection",
+                ))
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use clipbridge_relay::{RelayServer, ServerConfig};
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    /// Start a relay server on a free local port
+    pub(crate) fn start_relay() -> String {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async {
+                let config = ServerConfig {
+                    listen: "127.0.0.1:0".parse().unwrap(),
+                    ..Default::default()
+                };
+                let server = RelayServer::bind(config).await.unwrap();
+                tx.send(server.local_addr().unwrap()).unwrap();
+                server.run_until(std::future::pending()).await.unwrap();
+            });
+        });
+        format!("ws://{}", rx.recv().unwrap())
+    }
+
+    fn client(url: &str, device_id: &str) -> (RelayClient, mpsc::Receiver<NetworkMessage>) {
+        let key = SigningKey::from_bytes(&rand_key());
+        let client = RelayClient::new(
+            device_id.to_string(),
+            key,
+            url.to_string(),
+            "shared account secret",
+            1024 * 1024,
+        );
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        client.set_message_handler(move |msg| {
+            let _ = tx.lock().send(msg);
+        });
+        (client, rx)
+    }
+
+    fn rand_key() -> [u8; 32] {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        key
+    }
+
+    fn wait_for(what: &str, condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Next message of `msg_type`, skipping hellos and acks
+    fn recv_clipboard(rx: &mpsc::Receiver<NetworkMessage>) -> NetworkMessage {
+        loop {
+            let msg = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+            if matches!(msg.msg_type, MessageType::ClipboardUpdate) {
+                return msg;
+            }
+        }
+    }
+
+    #[test]
+    fn test_relays_sealed_messages_between_devices() {
+        let url = start_relay();
+        let (laptop, _laptop_rx) = client(&url, "laptop");
+        let (phone, phone_rx) = client(&url, "phone");
+
+        laptop.start().unwrap();
+        wait_for("laptop to connect", || laptop.is_connected());
+        laptop.add_device("phone", phone.public_key()).unwrap();
+        wait_for("phone to be registered", || {
+            laptop.connected_peers() == vec!["phone".to_string()]
+        });
+
+        // Queued by the relay until the phone connects
+        let message = NetworkMessage::new(
+            MessageType::ClipboardUpdate,
+            "laptop".to_string(),
+            serde_json::json!({ "content": "hello from the laptop" }),
+        );
+        laptop.broadcast(&message).unwrap();
+
+        phone.start().unwrap();
+        let received = recv_clipboard(&phone_rx);
+        assert_eq!(received.from, "laptop");
+        assert_eq!(received.payload, message.payload);
+        wait_for("both devices online", || {
+            laptop.devices().iter().all(|device| device.online)
+        });
+    }
+
+    #[test]
+    fn test_backoff_is_jittered_and_capped() {
+        for attempt in 0..20 {
+            let ceiling = (MIN_BACKOFF * 2u32.pow(attempt.min(16))).min(MAX_BACKOFF);
+            let delay = backoff(attempt);
+            assert!(delay >= ceiling / 2 && delay <= ceiling);
+        }
+    }
+}
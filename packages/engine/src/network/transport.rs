@@ -0,0 +1,46 @@
+use super::message::NetworkMessage;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// How messages reach a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkPath {
+    /// A P2P connection on the local network
+    Direct,
+    Relay,
+}
+
+/// The path currently used for a peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerLink {
+    pub device_id: String,
+    pub path: LinkPath,
+    /// False while the relay is queueing messages for the peer
+    pub online: bool,
+}
+
+/// A way of exchanging `NetworkMessage`s with peers. The sync engine only
+/// talks to peers through this trait, so it does not care whether an item
+/// arrived over a direct link or the relay.
+pub trait Transport: Send + Sync {
+    fn start(&self) -> io::Result<()>;
+
+    fn stop(&self);
+
+    /// Change the largest accepted item size
+    fn set_max_item_size(&self, max_item_size: usize);
+
+    /// Device ids messages can currently be sent to
+    fn connected_peers(&self) -> Vec<String>;
+
+    /// The path to each peer in `connected_peers`
+    fn peer_links(&self) -> Vec<PeerLink>;
+
+    /// Send a message to a single peer
+    fn send_to(&self, peer_id: &str, message: &NetworkMessage) -> io::Result<()>;
+
+    /// Send a message to all peers, or only to those listed in its `to` field
+    fn broadcast(&self, message: &NetworkMessage) -> io::Result<()>;
+}
@@ -0,0 +1,268 @@
+use crate::config::ConfirmConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Which way clipboard items flow between this device and a peer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncDirection {
+    #[default]
+    Both,
+    /// We send our items to the peer but ignore theirs
+    SendOnly,
+    /// We apply the peer's items but never send ours
+    ReceiveOnly,
+    Muted,
+}
+
+/// Whether items from a peer wait in the inbox before they replace the
+/// clipboard
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmPolicy {
+    /// Follow `security.confirmIncoming`
+    #[default]
+    Default,
+    /// Stage every item from the peer
+    Always,
+    /// A trusted peer: apply its items right away
+    Trusted,
+}
+
+/// Per-peer settings, keyed by device id
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PeerSettings {
+    pub device_id: String,
+    pub name: Option<String>,
+    /// Whether this peer may request our clipboard history
+    pub allow_history: bool,
+    pub direction: SyncDirection,
+    /// MIME types synced with this peer, e.g. `text/plain` or `image/*`.
+    /// Empty means all types.
+    pub mime_types: Vec<String>,
+    pub confirm: ConfirmPolicy,
+    /// Identity key the peer first proved on a direct link, hex encoded.
+    /// Later links must prove the same key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+impl PeerSettings {
+    pub fn new(device_id: String) -> Self {
+        Self {
+            device_id,
+            ..Default::default()
+        }
+    }
+
+    fn allows_mime(&self, mime_type: &str) -> bool {
+        matches_mime(&self.mime_types, mime_type)
+    }
+}
+
+/// Whether `mime_type` matches one of `patterns`, e.g. `text/plain` or
+/// `image/*`. No patterns match every type.
+fn matches_mime(patterns: &[String], mime_type: &str) -> bool {
+    patterns.is_empty()
+        || patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix("/*") {
+                Some(prefix) => mime_type
+                    .split_once('/')
+                    .is_some_and(|(kind, _)| kind.eq_ignore_ascii_case(prefix)),
+                None => pattern.eq_ignore_ascii_case(mime_type),
+            })
+}
+
+/// Peer settings persisted as JSON
+pub struct PeerStore {
+    path: Option<PathBuf>,
+    peers: HashMap<String, PeerSettings>,
+}
+
+impl PeerStore {
+    /// Load peer settings from `path`, starting empty if it doesn't exist
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let peers = match fs::read(&path) {
+            Ok(bytes) => {
+                let list: Vec<PeerSettings> =
+                    serde_json::from_slice(&bytes).map_err(io::Error::other)?;
+                list.into_iter().map(|p| (p.device_id.clone(), p)).collect()
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            peers,
+        })
+    }
+
+    /// A store that is never written to disk
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            peers: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, device_id: &str) -> Option<&PeerSettings> {
+        self.peers.get(device_id)
+    }
+
+    pub fn list(&self) -> Vec<PeerSettings> {
+        let mut peers: Vec<_> = self.peers.values().cloned().collect();
+        peers.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        peers
+    }
+
+    /// Modify a peer's settings, creating them if needed, and save
+    pub fn update<F>(&mut self, device_id: &str, f: F) -> io::Result<PeerSettings>
+    where
+        F: FnOnce(&mut PeerSettings),
+    {
+        let peer = self
+            .peers
+            .entry(device_id.to_string())
+            .or_insert_with(|| PeerSettings::new(device_id.to_string()));
+        f(peer);
+        let peer = peer.clone();
+
+        self.save()?;
+        Ok(peer)
+    }
+
+    /// Whether `public_key` (hex) is the identity key of `device_id`. The
+    /// first key a device proves is pinned; later ones must match it.
+    pub fn check_key(&mut self, device_id: &str, public_key: &str) -> io::Result<bool> {
+        match self.get(device_id).and_then(|p| p.public_key.as_deref()) {
+            Some(pinned) => Ok(pinned == public_key),
+            None => {
+                self.update(device_id, |peer| {
+                    peer.public_key = Some(public_key.to_string())
+                })?;
+                Ok(true)
+            }
+        }
+    }
+
+    pub fn allows_history(&self, device_id: &str) -> bool {
+        self.get(device_id).is_some_and(|p| p.allow_history)
+    }
+
+    /// Whether our clipboard items of `mime_type` go to this peer. Peers
+    /// without settings sync both ways.
+    pub fn sends_to(&self, device_id: &str, mime_type: &str) -> bool {
+        match self.get(device_id) {
+            Some(p) => {
+                matches!(p.direction, SyncDirection::Both | SyncDirection::SendOnly)
+                    && p.allows_mime(mime_type)
+            }
+            None => true,
+        }
+    }
+
+    /// Whether clipboard items of `mime_type` from this peer are applied
+    pub fn receives_from(&self, device_id: &str, mime_type: &str) -> bool {
+        match self.get(device_id) {
+            Some(p) => {
+                matches!(
+                    p.direction,
+                    SyncDirection::Both | SyncDirection::ReceiveOnly
+                ) && p.allows_mime(mime_type)
+            }
+            None => true,
+        }
+    }
+
+    /// Whether items of `mime_type` from this peer are staged for the user
+    /// to apply, given the default policy in `config`
+    pub fn needs_confirmation(
+        &self,
+        device_id: &str,
+        mime_type: &str,
+        config: &ConfirmConfig,
+    ) -> bool {
+        match self.get(device_id).map(|p| p.confirm).unwrap_or_default() {
+            ConfirmPolicy::Always => true,
+            ConfirmPolicy::Trusted => false,
+            ConfirmPolicy::Default => config.enabled && matches_mime(&config.mime_types, mime_type),
+        }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let bytes = serde_json::to_vec_pretty(&self.list()).map_err(io::Error::other)?;
+        fs::write(path, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_direction_and_mime_types() {
+        let mut store = PeerStore::in_memory();
+        assert!(store.sends_to("unknown", "text/plain"));
+        assert!(store.receives_from("unknown", "text/plain"));
+
+        store
+            .update("phone", |peer| {
+                peer.direction = SyncDirection::SendOnly;
+                peer.mime_types = vec!["text/*".to_string()];
+            })
+            .unwrap();
+        assert!(store.sends_to("phone", "text/plain"));
+        assert!(!store.sends_to("phone", "image/png"));
+        assert!(!store.receives_from("phone", "text/plain"));
+
+        store
+            .update("phone", |peer| peer.direction = SyncDirection::Muted)
+            .unwrap();
+        assert!(!store.sends_to("phone", "text/plain"));
+    }
+
+    #[test]
+    fn test_confirmation_policy() {
+        let mut store = PeerStore::in_memory();
+        let mut config = ConfirmConfig::default();
+        assert!(!store.needs_confirmation("laptop", "text/plain", &config));
+
+        config.enabled = true;
+        config.mime_types = vec!["text/*".to_string()];
+        assert!(store.needs_confirmation("laptop", "text/plain", &config));
+        assert!(!store.needs_confirmation("laptop", "image/png", &config));
+
+        store
+            .update("laptop", |peer| peer.confirm = ConfirmPolicy::Trusted)
+            .unwrap();
+        assert!(!store.needs_confirmation("laptop", "text/plain", &config));
+
+        config.enabled = false;
+        store
+            .update("phone", |peer| peer.confirm = ConfirmPolicy::Always)
+            .unwrap();
+        assert!(store.needs_confirmation("phone", "image/png", &config));
+    }
+
+    #[test]
+    fn test_first_key_is_pinned() {
+        let mut store = PeerStore::in_memory();
+        assert!(store.check_key("laptop", "aa").unwrap());
+        assert!(store.check_key("laptop", "aa").unwrap());
+        assert!(!store.check_key("laptop", "bb").unwrap());
+        assert_eq!(
+            store.get("laptop").unwrap().public_key.as_deref(),
+            Some("aa")
+        );
+    }
+}
@@ -0,0 +1,444 @@
+use crate::clipboard;
+use crate::config::{AppConfig, ConfigStore};
+use crate::control::DeviceInfo;
+use crate::events::{Event, EventBus};
+use crate::filter::AuditLog;
+use crate::groups::GroupStore;
+use crate::history::{ContentCipher, HistoryItem, HistoryStore, RetentionPolicy, SearchQuery};
+use crate::identity::DeviceIdentity;
+use crate::peers::PeerStore;
+use crate::sync::{InboxItem, SyncEngine};
+use parking_lot::Mutex;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The stores and sync engine of a running instance. The desktop app and
+/// the daemon both drive sync through this.
+pub struct Service {
+    pub device_id: String,
+    pub identity: DeviceIdentity,
+    pub config: Arc<Mutex<ConfigStore>>,
+    pub sync_engine: Arc<Mutex<Option<SyncEngine>>>,
+    pub history: Arc<Mutex<HistoryStore>>,
+    pub peers: Arc<Mutex<PeerStore>>,
+    pub groups: Arc<Mutex<GroupStore>>,
+    pub audit: Arc<Mutex<AuditLog>>,
+    pub is_syncing: Arc<Mutex<bool>>,
+    /// Why sync last failed to start, until it starts or is stopped
+    pub sync_error: Arc<Mutex<Option<String>>>,
+    pub events: Arc<EventBus>,
+}
+
+impl Service {
+    /// Load the config from `config_path` and open the stores in `data_dir`,
+    /// creating both if needed
+    pub fn open(config_path: PathBuf, data_dir: &Path) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(data_dir)?;
+        let config = ConfigStore::load(config_path)?;
+        let identity = DeviceIdentity::load_or_create(&data_dir.join("identity.json"))?;
+        let mut history = HistoryStore::open(data_dir.join("history.db"))?
+            .seal_with(ContentCipher::derive(&identity.secret()?))?;
+        history.set_retention(retention_for(config.get()))?;
+        let peers = PeerStore::load(data_dir.join("peers.json"))?;
+        let groups = GroupStore::load(data_dir.join("groups.json"))?;
+        let audit = AuditLog::open(data_dir.join("audit.log"))?;
+
+        Ok(Self {
+            device_id: identity.device_id.clone(),
+            identity,
+            config: Arc::new(Mutex::new(config)),
+            sync_engine: Arc::new(Mutex::new(None)),
+            history: Arc::new(Mutex::new(history)),
+            peers: Arc::new(Mutex::new(peers)),
+            groups: Arc::new(Mutex::new(groups)),
+            audit: Arc::new(Mutex::new(audit)),
+            is_syncing: Arc::new(Mutex::new(false)),
+            sync_error: Arc::new(Mutex::new(None)),
+            events: Arc::new(EventBus::default()),
+        })
+    }
+
+    /// Apply config changes, including edits to the config file, for as
+    /// long as the service is alive
+    pub fn watch_config(self: &Arc<Self>) {
+        let service = Arc::downgrade(self);
+        self.config
+            .lock()
+            .subscribe(Arc::new(move |config: &AppConfig| {
+                if let Some(service) = service.upgrade() {
+                    service.apply_config(config);
+                    service.events.emit(Event::ConfigChanged(config.redacted()));
+                }
+            }));
+        ConfigStore::watch_file(Arc::downgrade(&self.config));
+    }
+
+    pub fn start_sync(&self) -> Result<(), String> {
+        // Read before locking `is_syncing`: config listeners may lock it
+        let config = self.config.lock().get().clone();
+        let mut is_syncing = self.is_syncing.lock();
+        if *is_syncing {
+            return Ok(());
+        }
+
+        log::info!("Starting clipboard sync");
+
+        let engine = match self.create_engine(config) {
+            Ok(engine) => engine,
+            Err(e) => {
+                drop(is_syncing);
+                self.sync_failed(&e);
+                return Err(e);
+            }
+        };
+        *self.sync_engine.lock() = Some(engine);
+        *is_syncing = true;
+        drop(is_syncing);
+
+        *self.sync_error.lock() = None;
+        self.events.emit(Event::SyncStateChanged {
+            syncing: true,
+            error: None,
+        });
+        Ok(())
+    }
+
+    pub fn stop_sync(&self) {
+        let mut is_syncing = self.is_syncing.lock();
+        if !*is_syncing {
+            return;
+        }
+
+        log::info!("Stopping clipboard sync");
+        if let Some(engine) = self.sync_engine.lock().take() {
+            engine.stop();
+        }
+        *is_syncing = false;
+        drop(is_syncing);
+
+        *self.sync_error.lock() = None;
+        self.events.emit(Event::SyncStateChanged {
+            syncing: false,
+            error: None,
+        });
+    }
+
+    fn sync_failed(&self, error: &str) {
+        *self.sync_error.lock() = Some(error.to_string());
+        self.events.emit(Event::SyncStateChanged {
+            syncing: false,
+            error: Some(error.to_string()),
+        });
+    }
+
+    /// Push a changed config to the history store and the sync engine
+    pub fn apply_config(&self, config: &AppConfig) {
+        if let Err(e) = self.history.lock().set_retention(retention_for(config)) {
+            log::error!("Failed to apply history retention: {}", e);
+        }
+
+        let restarted = {
+            let mut engine = self.sync_engine.lock();
+            if engine
+                .as_ref()
+                .is_some_and(|running| running.needs_restart(config))
+            {
+                log::info!("Restarting sync for the new network settings");
+                if let Some(old) = engine.take() {
+                    old.stop();
+                }
+                self.create_engine(config.clone())
+                    .map(|new_engine| *engine = Some(new_engine))
+            } else {
+                if let Some(running) = engine.as_ref() {
+                    if let Err(e) = running.apply_config(config) {
+                        log::error!("Failed to apply config to sync: {}", e);
+                    }
+                }
+                Ok(())
+            }
+        };
+        if let Err(e) = restarted {
+            log::error!("Failed to restart sync: {}", e);
+            *self.is_syncing.lock() = false;
+            self.sync_failed(&e);
+        }
+    }
+
+    /// How each reachable device is connected; empty unless syncing
+    pub fn devices(&self) -> Vec<DeviceInfo> {
+        let links = self
+            .sync_engine
+            .lock()
+            .as_ref()
+            .map(|engine| engine.peer_links())
+            .unwrap_or_default();
+        let peers = self.peers.lock();
+
+        links
+            .into_iter()
+            .map(|link| DeviceInfo {
+                name: peers
+                    .get(&link.device_id)
+                    .and_then(|peer| peer.name.clone()),
+                device_id: link.device_id,
+                path: link.path,
+                online: link.online,
+            })
+            .collect()
+    }
+
+    /// Find a connected or known device by id, name (ignoring case) or a
+    /// unique id prefix
+    pub fn resolve_device(&self, name: &str) -> Result<String, String> {
+        let mut known: Vec<(String, Option<String>)> = self
+            .devices()
+            .into_iter()
+            .map(|device| (device.device_id, device.name))
+            .collect();
+        for peer in self.peers.lock().list() {
+            if !known.iter().any(|(id, _)| *id == peer.device_id) {
+                known.push((peer.device_id, peer.name));
+            }
+        }
+
+        let exact = known.iter().find(|(id, peer_name)| {
+            id == name
+                || peer_name
+                    .as_deref()
+                    .is_some_and(|peer_name| peer_name.eq_ignore_ascii_case(name))
+        });
+        if let Some((id, _)) = exact {
+            return Ok(id.clone());
+        }
+
+        let matches: Vec<&String> = known
+            .iter()
+            .map(|(id, _)| id)
+            .filter(|id| id.starts_with(name))
+            .collect();
+        match matches.as_slice() {
+            [id] => Ok((*id).clone()),
+            [] => Err(format!("Unknown device {}", name)),
+            _ => Err(format!("{} matches more than one device", name)),
+        }
+    }
+
+    /// Send `text` to every device, or to the devices named in `to`,
+    /// without touching the clipboard. Returns false if the sensitive
+    /// filter held it for the user to decide.
+    pub fn send_text(&self, text: String, to: &[String]) -> Result<bool, String> {
+        let to = if to.is_empty() {
+            None
+        } else {
+            let ids = to
+                .iter()
+                .map(|name| self.resolve_device(name))
+                .collect::<Result<Vec<_>, _>>()?;
+            Some(ids)
+        };
+
+        let engine = self.sync_engine.lock();
+        let engine = engine.as_ref().ok_or("Sync is not running")?;
+        engine.send_text(text, to)
+    }
+
+    /// Put `text` on the clipboard without sending it to peers
+    pub fn apply_text(&self, text: &str) -> Result<(), String> {
+        match self.sync_engine.lock().as_ref() {
+            Some(engine) => engine.apply_text(text),
+            None => clipboard::set_text(text).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Items from peers waiting to be applied or discarded, oldest first;
+    /// empty unless syncing
+    pub fn inbox(&self) -> Vec<InboxItem> {
+        self.sync_engine
+            .lock()
+            .as_ref()
+            .map(|engine| engine.inbox())
+            .unwrap_or_default()
+    }
+
+    /// Apply (`accept`) or discard an inbox item. Returns false if no item
+    /// with that id is in the inbox.
+    pub fn resolve_inbox(&self, id: &str, accept: bool) -> Result<bool, String> {
+        match self.sync_engine.lock().as_ref() {
+            Some(engine) => engine.resolve_inbox(id, accept),
+            None => Ok(false),
+        }
+    }
+
+    /// The newest item copied on the device named `from`, or on any other
+    /// device. Items waiting in the inbox come before applied ones.
+    pub fn latest_item_from(&self, from: Option<&str>) -> Result<Option<HistoryItem>, String> {
+        // Resolve before locking history: the engine locks it while locked
+        let origin_device = from.map(|name| self.resolve_device(name)).transpose()?;
+        let pending = self
+            .inbox()
+            .into_iter()
+            .rev()
+            .find(|item| match &origin_device {
+                Some(id) => *id == item.origin_device,
+                None => true,
+            });
+        if let Some(item) = pending {
+            return Ok(Some(item.to_history_item()));
+        }
+
+        let history_size = self.config.lock().get().general.history_size;
+
+        let history = self.history.lock();
+        if origin_device.is_some() {
+            let query = SearchQuery {
+                origin_device,
+                limit: Some(1),
+                ..SearchQuery::default()
+            };
+            let results = history.search(&query).map_err(|e| e.to_string())?;
+            return Ok(results.into_iter().next().map(|result| result.item));
+        }
+        let items = history.list(history_size, 0).map_err(|e| e.to_string())?;
+        Ok(items.into_iter().find(|item| !item.is_local))
+    }
+
+    /// Create and start a sync engine that reports to `events`
+    fn create_engine(&self, config: AppConfig) -> Result<SyncEngine, String> {
+        let engine = SyncEngine::new(
+            &self.identity,
+            config,
+            Arc::clone(&self.history),
+            Arc::clone(&self.peers),
+            Arc::clone(&self.groups),
+            Arc::clone(&self.audit),
+        )?;
+        let events = Arc::clone(&self.events);
+        engine.set_held_handler(move |item| events.emit(Event::SensitiveItemHeld(item.clone())));
+        let events = Arc::clone(&self.events);
+        engine.set_event_handler(move |event| events.emit(event.clone()));
+        let events = Arc::clone(&self.events);
+        engine.set_relay_devices_handler(move |devices| {
+            events.emit(Event::RelayDevicesChanged(devices.to_vec()))
+        });
+
+        engine.start()?;
+        Ok(engine)
+    }
+}
+
+fn retention_for(config: &AppConfig) -> RetentionPolicy {
+    RetentionPolicy {
+        max_items: Some(config.general.history_size),
+        max_age: config
+            .general
+            .max_age_days
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+        max_total_bytes: config.general.max_total_bytes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::message::ClipboardData;
+    use crate::network::{MessageType, NetworkMessage};
+
+    #[test]
+    fn test_open_creates_and_reuses_stores() {
+        let dir = std::env::temp_dir().join(format!("clipbridge-service-{}", uuid::Uuid::new_v4()));
+        let config_path = dir.join("config.toml");
+        let data_dir = dir.join("data");
+
+        let service = Service::open(config_path.clone(), &data_dir).unwrap();
+        assert!(config_path.exists());
+        assert!(!*service.is_syncing.lock());
+
+        let mut config = service.config.lock().get().clone();
+        config.general.history_size = 25;
+        service.apply_config(&config);
+        assert_eq!(service.history.lock().retention().max_items, Some(25));
+
+        config.general.max_age_days = Some(7);
+        config.general.max_total_bytes = Some(1024 * 1024);
+        service.apply_config(&config);
+        let retention = service.history.lock().retention().clone();
+        assert_eq!(
+            retention.max_age,
+            Some(Duration::from_secs(7 * 24 * 60 * 60))
+        );
+        assert_eq!(retention.max_total_bytes, Some(1024 * 1024));
+
+        let device_id = service.device_id.clone();
+        drop(service);
+        let reopened = Service::open(config_path, &data_dir).unwrap();
+        assert_eq!(reopened.device_id, device_id);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pull_takes_items_waiting_in_the_inbox() {
+        let dir = std::env::temp_dir().join(format!("clipbridge-service-{}", uuid::Uuid::new_v4()));
+        let service = Service::open(dir.join("config.toml"), &dir.join("data")).unwrap();
+        let mut config = service.config.lock().get().clone();
+        config.sync.auto_sync = false;
+        service
+            .peers
+            .lock()
+            .update("laptop-5678", |peer| peer.name = Some("Laptop".to_string()))
+            .unwrap();
+        let applied = HistoryItem::text(
+            "old".to_string(),
+            "laptop-5678".to_string(),
+            false,
+            "applied earlier".to_string(),
+            1,
+        );
+        service.history.lock().insert(&applied).unwrap();
+
+        // Not started: the message is handed to it directly
+        let engine = SyncEngine::new(
+            &service.identity,
+            config,
+            Arc::clone(&service.history),
+            Arc::clone(&service.peers),
+            Arc::clone(&service.groups),
+            Arc::clone(&service.audit),
+        )
+        .unwrap();
+        let mut msg = NetworkMessage::new(
+            MessageType::ClipboardUpdate,
+            "laptop-5678".to_string(),
+            serde_json::to_value(ClipboardData::new("waiting".to_string())).unwrap(),
+        );
+        msg.authenticated = true;
+        engine.receive(msg);
+        *service.sync_engine.lock() = Some(engine);
+
+        let pulled = service.latest_item_from(Some("laptop")).unwrap().unwrap();
+        assert_eq!(pulled.content, "waiting");
+        assert_eq!(service.inbox()[0].id, pulled.id);
+        assert_eq!(
+            service.latest_item_from(None).unwrap().unwrap().content,
+            "waiting"
+        );
+
+        // Once the inbox is empty, pulls fall back to history
+        assert!(service.resolve_inbox(&pulled.id, false).unwrap());
+        assert_eq!(
+            service
+                .latest_item_from(Some("laptop"))
+                .unwrap()
+                .unwrap()
+                .content,
+            "applied earlier"
+        );
+
+        drop(service);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
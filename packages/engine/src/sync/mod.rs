@@ -0,0 +1,1854 @@
+use crate::clipboard::{self, ClipboardItem, ClipboardMonitor, SourceApp};
+use crate::config::{AppConfig, FilterAction, SyncMode};
+use crate::control::DeviceInfo;
+use crate::events::{self, ChangeSource, Event, EventCallback};
+use crate::filter::{AuditDecision, AuditEntry, AuditLog, SensitiveFilter};
+use crate::groups::GroupStore;
+use crate::history::{HistoryItem, HistoryStore, TEXT_PLAIN};
+use crate::identity::DeviceIdentity;
+use crate::network::message::{
+    ClipboardData, GroupProof, HistoryCursor, HistoryEntryData, HistoryRequestData,
+    HistoryResponseData, SnippetData,
+};
+use crate::network::{
+    DeviceDiscovery, HybridTransport, MessageType, NetworkMessage, P2PNetwork, PeerLink,
+    RelayClient, RelayDevice, Transport,
+};
+use crate::peers::PeerStore;
+use ed25519_dalek::VerifyingKey;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of history items per HistoryResponse page
+const HISTORY_PAGE_SIZE: usize = 50;
+/// Items held by `Ask` rules beyond this are dropped, oldest first
+const MAX_HELD_ITEMS: usize = 20;
+/// Items waiting in the inbox beyond this are dropped, oldest first
+const MAX_INBOX_ITEMS: usize = 20;
+/// How often peer links are checked for `DeviceConnected`/`DeviceDisconnected`
+const LINK_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// How often the inbox is checked for items to apply automatically
+const INBOX_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Audit rule name for items their owner marked as secret
+const CONCEALED_RULE: &str = "concealedHint";
+/// Audit rule name for items copied from an `excludedApps` entry
+const EXCLUDED_APP_RULE: &str = "excludedApp";
+
+/// Called when the sensitive filter holds an item for the user to decide
+pub type HeldCallback = Arc<dyn Fn(&HeldItem) + Send + Sync>;
+
+/// A local item waiting for the user to allow or reject syncing it
+#[derive(Debug, Clone, Serialize)]
+pub struct HeldItem {
+    #[serde(flatten)]
+    pub data: ClipboardData,
+    pub rules: Vec<String>,
+    /// Recipients, if it was sent to specific devices
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<Vec<String>>,
+}
+
+/// An item from a peer waiting to be applied, received while
+/// `sync.autoSync` is off or staged for confirmation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxItem {
+    pub id: String,
+    pub origin_device: String,
+    /// The origin device's name, if the user gave it one
+    pub device_name: Option<String>,
+    pub content: String,
+    pub timestamp: u64,
+    /// When the item is applied unless resolved first, in milliseconds
+    /// since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apply_at: Option<u64>,
+    /// The group the item was sealed for, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+}
+
+impl InboxItem {
+    /// How the item is recorded in history once applied
+    pub fn to_history_item(&self) -> HistoryItem {
+        let mut item = HistoryItem::text(
+            self.id.clone(),
+            self.origin_device.clone(),
+            false,
+            self.content.clone(),
+            self.timestamp,
+        );
+        item.group_id = self.group_id.clone();
+        item
+    }
+}
+
+/// Ties the clipboard monitor, the network transport and history together
+pub struct SyncEngine {
+    ctx: Arc<SyncContext>,
+    /// The relay in cloud mode, direct P2P links in P2P mode and both in
+    /// hybrid mode
+    network: Arc<dyn Transport>,
+    /// Set when `network` uses direct P2P links
+    p2p: Option<Arc<P2PNetwork>>,
+    /// Set when `network` uses the relay
+    relay: Option<Arc<RelayClient>>,
+    discovery: Mutex<Option<DeviceDiscovery>>,
+    /// Cleared to stop the peer link and inbox watchers
+    watching: Arc<Mutex<bool>>,
+}
+
+/// A history request sent to a peer and not answered yet
+struct Backfill {
+    since: u64,
+    /// Items stored from earlier pages of the same backfill
+    received: usize,
+}
+
+/// State shared between the engine and the monitor/network callbacks
+struct SyncContext {
+    device_id: String,
+    /// Read on every event, so config changes apply to the next one
+    config: RwLock<AppConfig>,
+    filter: RwLock<SensitiveFilter>,
+    monitor: ClipboardMonitor,
+    history: Arc<Mutex<HistoryStore>>,
+    peers: Arc<Mutex<PeerStore>>,
+    groups: Arc<Mutex<GroupStore>>,
+    audit: Arc<Mutex<AuditLog>>,
+    held: Mutex<Vec<HeldItem>>,
+    inbox: Mutex<Vec<InboxItem>>,
+    /// The last local copy recorded but not sent while `sync.autoSync` is
+    /// off. Sending the same content reuses its history entry.
+    unsent: Mutex<Option<ClipboardData>>,
+    on_held: OnceLock<HeldCallback>,
+    on_event: OnceLock<EventCallback>,
+    /// The outstanding history request to each peer. Responses that don't
+    /// answer one are dropped.
+    backfills: Mutex<HashMap<String, Backfill>>,
+    /// Set once the network exists; weak so callbacks don't keep it alive
+    network: OnceLock<Weak<dyn Transport>>,
+}
+
+impl SyncEngine {
+    pub fn new(
+        identity: &DeviceIdentity,
+        config: AppConfig,
+        history: Arc<Mutex<HistoryStore>>,
+        peers: Arc<Mutex<PeerStore>>,
+        groups: Arc<Mutex<GroupStore>>,
+        audit: Arc<Mutex<AuditLog>>,
+    ) -> Result<Self, String> {
+        let device_id = identity.device_id.clone();
+        let ctx = Arc::new(SyncContext::new(
+            device_id.clone(),
+            config.clone(),
+            history,
+            peers,
+            groups,
+            audit,
+        ));
+
+        let (network, p2p, relay): (Arc<dyn Transport>, _, _) = match config.sync.mode {
+            SyncMode::Cloud => {
+                let relay = Self::relay_client(identity, &config)?;
+                let ctx_clone = Arc::clone(&ctx);
+                relay.set_message_handler(move |msg| ctx_clone.handle_message(msg));
+
+                let relay = Arc::new(relay);
+                (relay.clone(), None, Some(relay))
+            }
+            SyncMode::P2p => {
+                let mut p2p = Self::p2p_network(identity, &config, &ctx)?;
+                let ctx_clone = Arc::clone(&ctx);
+                p2p.set_message_handler(move |msg| ctx_clone.handle_message(msg));
+
+                let p2p = Arc::new(p2p);
+                (p2p.clone(), Some(p2p), None)
+            }
+            SyncMode::Hybrid => {
+                let ctx_clone = Arc::clone(&ctx);
+                let hybrid = HybridTransport::new(
+                    Self::p2p_network(identity, &config, &ctx)?,
+                    Self::relay_client(identity, &config)?,
+                    move |msg| ctx_clone.handle_message(msg),
+                );
+                let (p2p, relay) = (hybrid.p2p(), hybrid.relay());
+                (Arc::new(hybrid), Some(p2p), Some(relay))
+            }
+        };
+        let _ = ctx.network.set(Arc::downgrade(&network));
+
+        let discovery = p2p
+            .as_ref()
+            .filter(|_| config.network.discovery_enabled)
+            .map(|p2p| Self::discovery(&device_id, config.network.discovery_port, p2p));
+
+        Ok(Self {
+            ctx,
+            network,
+            p2p,
+            relay,
+            discovery: Mutex::new(discovery),
+            watching: Arc::new(Mutex::new(false)),
+        })
+    }
+
+    fn p2p_network(
+        identity: &DeviceIdentity,
+        config: &AppConfig,
+        ctx: &Arc<SyncContext>,
+    ) -> Result<P2PNetwork, String> {
+        let p2p = P2PNetwork::new(
+            identity.device_id.clone(),
+            identity.device_key().map_err(|e| e.to_string())?,
+            config.network.p2p_port,
+            config.sync.max_item_size as usize,
+        );
+        let ctx = Arc::clone(ctx);
+        p2p.set_key_check(move |device_id, public_key| ctx.check_peer_key(device_id, public_key));
+        Ok(p2p)
+    }
+
+    /// Discovery that announces `p2p` and dials the devices it finds
+    fn discovery(device_id: &str, port: u16, p2p: &Arc<P2PNetwork>) -> DeviceDiscovery {
+        let mut discovery = DeviceDiscovery::new(device_id.to_string(), port, p2p.port());
+        let own_id = device_id.to_string();
+        let p2p = Arc::downgrade(p2p);
+        discovery.set_device_handler(move |device| {
+            // Only the smaller id dials, so two devices that find each
+            // other open a single link
+            if own_id < device.device_id {
+                if let Some(p2p) = p2p.upgrade() {
+                    p2p.dial(&device.device_id, device.addr);
+                }
+            }
+        });
+        discovery
+    }
+
+    fn relay_client(identity: &DeviceIdentity, config: &AppConfig) -> Result<RelayClient, String> {
+        let url = config
+            .network
+            .relay_server_url
+            .clone()
+            .ok_or("Cloud and hybrid modes need network.relayServerUrl")?;
+        let account = match &config.network.relay_account {
+            Some(secret) => secret.clone(),
+            None => identity.relay_account_secret().map_err(|e| e.to_string())?,
+        };
+        Ok(RelayClient::new(
+            identity.device_id.clone(),
+            identity.device_key().map_err(|e| e.to_string())?,
+            url,
+            &account,
+            config.sync.max_item_size as usize,
+        ))
+    }
+
+    /// Start the network and watch the local clipboard
+    pub fn start(&self) -> Result<(), String> {
+        self.network.start().map_err(|e| e.to_string())?;
+        if let Some(discovery) = self.discovery.lock().as_ref() {
+            discovery.start().map_err(|e| e.to_string())?;
+        }
+
+        let ctx = Arc::clone(&self.ctx);
+        self.ctx
+            .monitor
+            .start(move |item| ctx.handle_local_change(item))
+            .map_err(|e| e.to_string())?;
+
+        let mut watching = self.watching.lock();
+        if !*watching {
+            *watching = true;
+            let ctx = Arc::clone(&self.ctx);
+            let flag = Arc::clone(&self.watching);
+            thread::spawn(move || ctx.watch_links(&flag));
+            let ctx = Arc::clone(&self.ctx);
+            let flag = Arc::clone(&self.watching);
+            thread::spawn(move || ctx.watch_inbox(&flag));
+        }
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        *self.watching.lock() = false;
+        self.ctx.monitor.stop();
+        self.network.stop();
+        if let Some(discovery) = self.discovery.lock().as_ref() {
+            discovery.stop();
+        }
+    }
+
+    /// Whether `config` needs a new engine rather than `apply_config`:
+    /// switching transports, or relay settings in cloud and hybrid modes
+    pub fn needs_restart(&self, config: &AppConfig) -> bool {
+        let current = self.ctx.config.read();
+        current.sync.mode != config.sync.mode
+            || (self.relay.is_some()
+                && current.network.relay_server_url != config.network.relay_server_url)
+            || (self.relay.is_some()
+                && current.network.relay_account != config.network.relay_account)
+    }
+
+    /// Apply a changed config while running: rebinds the P2P listener on a
+    /// port change and starts or stops discovery. Everything else is read
+    /// from the config on the next event.
+    pub fn apply_config(&self, config: &AppConfig) -> Result<(), String> {
+        let old = std::mem::replace(&mut *self.ctx.config.write(), config.clone());
+
+        if old.security != config.security {
+            *self.ctx.filter.write() = SensitiveFilter::from_config(config);
+        }
+
+        if old.sync.max_item_size != config.sync.max_item_size {
+            self.network
+                .set_max_item_size(config.sync.max_item_size as usize);
+        }
+
+        let Some(p2p) = &self.p2p else {
+            return Ok(());
+        };
+
+        let rebound = p2p.rebind(config.network.p2p_port).map_err(|e| {
+            format!(
+                "Failed to listen on port {}: {}",
+                config.network.p2p_port, e
+            )
+        });
+
+        let mut discovery = self.discovery.lock();
+        if let Some(discovery) = discovery.as_ref() {
+            discovery.set_p2p_port(p2p.port());
+        }
+        if old.network.discovery_enabled != config.network.discovery_enabled
+            || old.network.discovery_port != config.network.discovery_port
+        {
+            // Dropping the old instance stops it
+            *discovery = None;
+            if config.network.discovery_enabled {
+                let new_discovery =
+                    Self::discovery(&self.ctx.device_id, config.network.discovery_port, p2p);
+                new_discovery.start().map_err(|e| e.to_string())?;
+                *discovery = Some(new_discovery);
+            }
+        }
+
+        rebound
+    }
+
+    /// Set the callback for items held by the sensitive filter
+    pub fn set_held_handler<F>(&self, callback: F)
+    where
+        F: Fn(&HeldItem) + Send + Sync + 'static,
+    {
+        let _ = self.ctx.on_held.set(Arc::new(callback));
+    }
+
+    /// Set the callback for clipboard, device and transfer events
+    pub fn set_event_handler<F>(&self, callback: F)
+    where
+        F: Fn(&Event) + Send + Sync + 'static,
+    {
+        let _ = self.ctx.on_event.set(Arc::new(callback));
+    }
+
+    /// Items held by the sensitive filter, oldest first
+    pub fn held_items(&self) -> Vec<HeldItem> {
+        self.ctx.held.lock().clone()
+    }
+
+    /// Sync (`allow`) or discard a held item. Returns false if no item
+    /// with that id is held.
+    pub fn resolve_held(&self, id: &str, allow: bool) -> bool {
+        let item = {
+            let mut held = self.ctx.held.lock();
+            match held.iter().position(|item| item.data.id == id) {
+                Some(index) => held.remove(index),
+                None => return false,
+            }
+        };
+
+        let decision = if allow {
+            AuditDecision::Approved
+        } else {
+            AuditDecision::Rejected
+        };
+        self.ctx.audit(
+            &item.data.id,
+            item.rules.clone(),
+            FilterAction::Ask,
+            decision,
+        );
+        if allow {
+            if let Err(e) = self.ctx.publish(item.data, item.to.as_deref()) {
+                log::warn!("Failed to send held item: {}", e);
+            }
+        }
+        true
+    }
+
+    /// Items waiting to be applied, oldest first
+    pub fn inbox(&self) -> Vec<InboxItem> {
+        self.ctx.inbox.lock().clone()
+    }
+
+    /// Handle a message as if the transport had delivered it
+    #[cfg(test)]
+    pub(crate) fn receive(&self, msg: NetworkMessage) {
+        self.ctx.handle_message(msg);
+    }
+
+    /// Apply (`accept`) or discard an inbox item. Returns false if no item
+    /// with that id is in the inbox.
+    pub fn resolve_inbox(&self, id: &str, accept: bool) -> Result<bool, String> {
+        let item = {
+            let mut inbox = self.ctx.inbox.lock();
+            match inbox.iter().position(|item| item.id == id) {
+                Some(index) => inbox.remove(index),
+                None => return Ok(false),
+            }
+        };
+        self.ctx.inbox_changed();
+
+        if accept {
+            self.ctx.apply_remote(item)?;
+        }
+        Ok(true)
+    }
+
+    /// Send a shared snippet (or an update to one) to all peers
+    pub fn share_snippet(&self, item: &HistoryItem) -> Result<(), String> {
+        let message = NetworkMessage::new(
+            MessageType::SnippetUpdate,
+            self.ctx.device_id.clone(),
+            serde_json::to_value(SyncContext::to_snippet(item)).map_err(|e| e.to_string())?,
+        );
+        self.network.broadcast(&message).map_err(|e| e.to_string())
+    }
+
+    /// Tell peers a snippet is no longer shared
+    pub fn unshare_snippet(&self, id: &str) -> Result<(), String> {
+        let message = NetworkMessage::new(
+            MessageType::SnippetRemove,
+            self.ctx.device_id.clone(),
+            serde_json::json!({ "id": id }),
+        );
+        self.network.broadcast(&message).map_err(|e| e.to_string())
+    }
+
+    /// Prove our group membership to all connected peers, e.g. after
+    /// joining a group
+    pub fn announce_groups(&self) {
+        for peer_id in self.network.connected_peers() {
+            self.ctx.send_group_proofs(&peer_id);
+        }
+    }
+
+    /// Ask a peer for history items copied after `since`. Further pages are
+    /// requested automatically as responses arrive.
+    pub fn request_history(&self, peer_id: &str, since: u64) -> Result<(), String> {
+        self.ctx.request_history(peer_id, since, None, 0)
+    }
+
+    /// How each reachable peer is currently connected
+    pub fn peer_links(&self) -> Vec<PeerLink> {
+        self.network.peer_links()
+    }
+
+    /// Send `text` to peers now, or only to the device ids in `to`, as if
+    /// it had been copied here. Returns false if the sensitive filter held
+    /// it for the user to decide, and an error if none of `to` may receive
+    /// it.
+    pub fn send_text(&self, text: String, to: Option<Vec<String>>) -> Result<bool, String> {
+        if let Some(to) = &to {
+            let connected = self.network.connected_peers();
+            if let Some(missing) = to.iter().find(|id| !connected.contains(id)) {
+                return Err(format!("{} is not connected", missing));
+            }
+        }
+        self.ctx.share(text, None, to)
+    }
+
+    /// Put `text` on the local clipboard without sending it to peers
+    pub fn apply_text(&self, text: &str) -> Result<(), String> {
+        // Mark as seen first so the monitor does not send it
+        self.ctx.monitor.set_last_content(text.to_string());
+        clipboard::set_text(text).map_err(|e| e.to_string())
+    }
+
+    /// Set the callback for changes to the relay account's devices
+    pub fn set_relay_devices_handler<F>(&self, callback: F)
+    where
+        F: Fn(&[RelayDevice]) + Send + Sync + 'static,
+    {
+        if let Some(relay) = &self.relay {
+            relay.set_devices_handler(callback);
+        }
+    }
+
+    /// Devices on the relay account; empty unless the relay is used
+    pub fn relay_devices(&self) -> Vec<RelayDevice> {
+        self.relay
+            .as_ref()
+            .map(|relay| relay.devices())
+            .unwrap_or_default()
+    }
+
+    fn relay(&self) -> Result<&RelayClient, String> {
+        self.relay
+            .as_deref()
+            .ok_or_else(|| "The relay is only used in cloud and hybrid modes".to_string())
+    }
+
+    /// Let another device sign in to our relay account
+    pub fn add_relay_device(&self, device_id: &str, public_key: &str) -> Result<(), String> {
+        self.relay()?
+            .add_device(device_id, public_key)
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn remove_relay_device(&self, device_id: &str) -> Result<(), String> {
+        self.relay()?
+            .remove_device(device_id)
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl SyncContext {
+    fn network(&self) -> Option<Arc<dyn Transport>> {
+        self.network.get().and_then(Weak::upgrade)
+    }
+
+    fn new(
+        device_id: String,
+        config: AppConfig,
+        history: Arc<Mutex<HistoryStore>>,
+        peers: Arc<Mutex<PeerStore>>,
+        groups: Arc<Mutex<GroupStore>>,
+        audit: Arc<Mutex<AuditLog>>,
+    ) -> Self {
+        Self {
+            device_id,
+            filter: RwLock::new(SensitiveFilter::from_config(&config)),
+            config: RwLock::new(config),
+            monitor: ClipboardMonitor::new(),
+            history,
+            peers,
+            groups,
+            audit,
+            held: Mutex::new(Vec::new()),
+            inbox: Mutex::new(Vec::new()),
+            unsent: Mutex::new(None),
+            on_held: OnceLock::new(),
+            on_event: OnceLock::new(),
+            backfills: Mutex::new(HashMap::new()),
+            network: OnceLock::new(),
+        }
+    }
+
+    fn emit(&self, event: Event) {
+        if let Some(callback) = self.on_event.get() {
+            callback(&event);
+        }
+    }
+
+    fn device_name(&self, device_id: &str) -> Option<String> {
+        self.peers
+            .lock()
+            .get(device_id)
+            .and_then(|peer| peer.name.clone())
+    }
+
+    /// Report peers that come and go until `watching` is cleared, then
+    /// report the remaining ones as disconnected
+    fn watch_links(&self, watching: &Mutex<bool>) {
+        let mut connected: HashMap<String, DeviceInfo> = HashMap::new();
+
+        while *watching.lock() {
+            let Some(network) = self.network() else {
+                break;
+            };
+            let mut current: HashMap<String, DeviceInfo> = HashMap::new();
+            // Direct links come first; keep them over relay links
+            for link in network.peer_links() {
+                if link.online && !current.contains_key(&link.device_id) {
+                    let device = DeviceInfo {
+                        name: self.device_name(&link.device_id),
+                        device_id: link.device_id.clone(),
+                        path: link.path,
+                        online: true,
+                    };
+                    current.insert(link.device_id, device);
+                }
+            }
+            drop(network);
+
+            for (device_id, device) in &connected {
+                if !current.contains_key(device_id) {
+                    self.emit(Event::DeviceDisconnected(DeviceInfo {
+                        online: false,
+                        ..device.clone()
+                    }));
+                }
+            }
+            for (device_id, device) in &current {
+                if connected.get(device_id).map(|known| known.path) != Some(device.path) {
+                    self.emit(Event::DeviceConnected(device.clone()));
+                }
+            }
+            connected = current;
+
+            thread::sleep(LINK_CHECK_INTERVAL);
+        }
+
+        for device in connected.into_values() {
+            self.emit(Event::DeviceDisconnected(DeviceInfo {
+                online: false,
+                ..device
+            }));
+        }
+    }
+
+    fn handle_local_change(&self, item: ClipboardItem) {
+        if item.concealed {
+            self.block(
+                &uuid::Uuid::new_v4().to_string(),
+                vec![CONCEALED_RULE.to_string()],
+            );
+            return;
+        }
+
+        let excluded = item.source.as_ref().and_then(|source| {
+            self.config
+                .read()
+                .security
+                .excluded_apps
+                .iter()
+                .find(|name| source.matches(name))
+                .cloned()
+        });
+        if let Some(name) = excluded {
+            self.block(
+                &uuid::Uuid::new_v4().to_string(),
+                vec![format!("{}:{}", EXCLUDED_APP_RULE, name)],
+            );
+            return;
+        }
+
+        if !self.config.read().sync.auto_sync {
+            self.record_unsent(item.text, item.source);
+            return;
+        }
+        if let Err(e) = self.share(item.text, item.source, None) {
+            log::info!("Not syncing clipboard item: {}", e);
+        }
+    }
+
+    /// Record a local copy in history without sending it. The filter
+    /// applies as it would when sending; `Ask` rules are checked if the
+    /// item is sent.
+    fn record_unsent(&self, content: String, source: Option<SourceApp>) {
+        let verdict = self.filter.read().check(&content);
+        let mut data = ClipboardData::new(verdict.content);
+        data.source = source;
+        match verdict.action {
+            Some(FilterAction::Block) => {
+                self.block(&data.id, verdict.rules);
+                return;
+            }
+            Some(action @ FilterAction::Redact) => {
+                self.audit(&data.id, verdict.rules, action, AuditDecision::Redacted);
+            }
+            Some(FilterAction::Ask) | None => {}
+        }
+
+        let group_id = self.groups.lock().active().map(|group| group.id.clone());
+        self.record_local(&data, group_id);
+        *self.unsent.lock() = Some(data);
+    }
+
+    /// The unsent item holding `content`, if it is the last one recorded
+    fn take_unsent(&self, content: &str) -> Option<ClipboardData> {
+        let mut unsent = self.unsent.lock();
+        if unsent.as_ref().is_some_and(|data| data.content == content) {
+            unsent.take()
+        } else {
+            None
+        }
+    }
+
+    /// Run `content` through the sensitive filter and send it to peers, or
+    /// only to `to` if given. Returns false if it was held for the user to
+    /// decide.
+    fn share(
+        &self,
+        content: String,
+        source: Option<SourceApp>,
+        to: Option<Vec<String>>,
+    ) -> Result<bool, String> {
+        let max_item_size = self.config.read().sync.max_item_size;
+        if content.len() as u64 > max_item_size {
+            return Err(format!(
+                "{} bytes is over the {} byte limit",
+                content.len(),
+                max_item_size
+            ));
+        }
+
+        let verdict = self.filter.read().check(&content);
+        let mut data = match self.take_unsent(&verdict.content) {
+            Some(data) => data,
+            None => ClipboardData::new(verdict.content),
+        };
+        if source.is_some() {
+            data.source = source;
+        }
+        let Some(action) = verdict.action else {
+            self.publish(data, to.as_deref())?;
+            return Ok(true);
+        };
+
+        match action {
+            FilterAction::Redact => {
+                self.audit(&data.id, verdict.rules, action, AuditDecision::Redacted);
+                self.publish(data, to.as_deref())?;
+                Ok(true)
+            }
+            FilterAction::Ask => {
+                // Redactions from other rules still apply if it is approved
+                self.audit(&data.id, verdict.rules.clone(), action, AuditDecision::Held);
+                self.hold(HeldItem {
+                    data,
+                    rules: verdict.rules,
+                    to,
+                });
+                Ok(false)
+            }
+            FilterAction::Block => {
+                let reason = format!("blocked by {}", verdict.rules.join(", "));
+                self.block(&data.id, verdict.rules);
+                Err(reason)
+            }
+        }
+    }
+
+    /// Record a local item in history and send it to peers, or only to
+    /// those in `to`. Fails without recording it if none of `to` may
+    /// receive it.
+    fn publish(&self, mut data: ClipboardData, to: Option<&[String]>) -> Result<(), String> {
+        let active = self
+            .groups
+            .lock()
+            .active()
+            .map(|group| (group.id.clone(), group.members.clone()));
+
+        let network = self.network();
+        let recipients: Vec<String> = match &network {
+            Some(network) => {
+                let peers = self.peers.lock();
+                network
+                    .connected_peers()
+                    .into_iter()
+                    .filter(|peer_id| peers.sends_to(peer_id, TEXT_PLAIN))
+                    .filter(|peer_id| match to {
+                        Some(to) => to.contains(peer_id),
+                        None => true,
+                    })
+                    .filter(|peer_id| match &active {
+                        Some((_, members)) => members.contains(peer_id),
+                        None => true,
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        if let Some(to) = to.filter(|_| recipients.is_empty()) {
+            return Err(format!(
+                "{} cannot receive it under the sync policy or active group",
+                to.join(", ")
+            ));
+        }
+
+        self.record_local(&data, active.as_ref().map(|(id, _)| id.clone()));
+        let Some(network) = network else {
+            return Ok(());
+        };
+        if recipients.is_empty() {
+            return Ok(());
+        }
+
+        if let Some((group_id, _)) = active {
+            let Some(sealed) = self.seal_for_group(&group_id, &data.content) else {
+                return Err(format!(
+                    "Failed to seal clipboard item for group {}",
+                    group_id
+                ));
+            };
+            data.content = sealed;
+            data.group = Some(group_id);
+        }
+
+        let mut message = NetworkMessage::new(
+            MessageType::ClipboardUpdate,
+            self.device_id.clone(),
+            serde_json::to_value(&data).unwrap(),
+        );
+        message.to = Some(recipients);
+        network
+            .broadcast(&message)
+            .map_err(|e| format!("Failed to broadcast clipboard: {}", e))
+    }
+
+    fn record_local(&self, data: &ClipboardData, group_id: Option<String>) {
+        let mut item = HistoryItem::text(
+            data.id.clone(),
+            self.device_id.clone(),
+            true,
+            data.content.clone(),
+            data.timestamp,
+        );
+        item.group_id = group_id;
+        if let Err(e) = self.history.lock().insert(&item) {
+            log::warn!("Failed to record local clipboard item: {}", e);
+        }
+        self.emit(Event::ClipboardChanged {
+            id: data.id.clone(),
+            source: ChangeSource::Local,
+            origin_device: self.device_id.clone(),
+            device_name: None,
+            preview: events::preview(&data.content, events::PREVIEW_LEN),
+        });
+    }
+
+    fn hold(&self, item: HeldItem) {
+        if let Some(callback) = self.on_held.get() {
+            callback(&item);
+        }
+
+        let mut held = self.held.lock();
+        held.push(item);
+        if held.len() > MAX_HELD_ITEMS {
+            let expired = held.remove(0);
+            drop(held);
+            self.audit(
+                &expired.data.id,
+                expired.rules,
+                FilterAction::Ask,
+                AuditDecision::Expired,
+            );
+        }
+    }
+
+    /// Record a blocked item: one the sensitive filter caught, a concealed
+    /// copy or one from an excluded app
+    fn block(&self, item_id: &str, rules: Vec<String>) {
+        self.audit(
+            item_id,
+            rules.clone(),
+            FilterAction::Block,
+            AuditDecision::Blocked,
+        );
+        self.emit(Event::SensitiveItemBlocked { rules });
+    }
+
+    fn audit(
+        &self,
+        item_id: &str,
+        rules: Vec<String>,
+        action: FilterAction,
+        decision: AuditDecision,
+    ) {
+        self.audit.lock().record(AuditEntry::new(
+            item_id.to_string(),
+            rules,
+            action,
+            decision,
+        ));
+    }
+
+    /// Pin the identity key of a peer on its first direct link, and refuse
+    /// links that prove another key later
+    fn check_peer_key(&self, device_id: &str, public_key: &VerifyingKey) -> bool {
+        let public_key = hex::encode(public_key.as_bytes());
+        let (known, result) = {
+            let mut peers = self.peers.lock();
+            let known = peers.get(device_id).is_some();
+            (known, peers.check_key(device_id, &public_key))
+        };
+
+        match result {
+            Ok(true) => {
+                // Pinning adds the peer, so the hello won't find it unknown
+                if !known {
+                    self.emit(Event::PairingRequested {
+                        device_id: device_id.to_string(),
+                    });
+                }
+                true
+            }
+            Ok(false) => {
+                log::warn!(
+                    "{} proved a different identity key than before, refusing the link",
+                    device_id
+                );
+                false
+            }
+            Err(e) => {
+                log::error!("Failed to pin the identity key of {}: {}", device_id, e);
+                false
+            }
+        }
+    }
+
+    fn handle_message(&self, msg: NetworkMessage) {
+        if msg
+            .to
+            .as_ref()
+            .is_some_and(|to| !to.contains(&self.device_id))
+        {
+            log::debug!("Dropping message from {} addressed to others", msg.from);
+            return;
+        }
+
+        match msg.msg_type {
+            MessageType::ClipboardUpdate => self.handle_clipboard_update(msg),
+            MessageType::DeviceHello
+            | MessageType::DeviceAck
+            | MessageType::HistoryRequest
+            | MessageType::HistoryResponse
+            | MessageType::SnippetUpdate
+            | MessageType::SnippetSync
+            | MessageType::SnippetRemove
+            | MessageType::GroupHello
+                if !msg.authenticated =>
+            {
+                log::warn!(
+                    "Ignoring {:?} from unauthenticated sender {}",
+                    msg.msg_type,
+                    msg.from
+                );
+            }
+            MessageType::DeviceHello | MessageType::DeviceAck => {
+                if self.peers.lock().get(&msg.from).is_none() {
+                    self.emit(Event::PairingRequested {
+                        device_id: msg.from.clone(),
+                    });
+                }
+                // Proofs first, so the peer can serve us group history
+                self.send_group_proofs(&msg.from);
+                self.send_snippet_collection(&msg.from);
+                self.backfill_history(&msg.from);
+            }
+            MessageType::HistoryRequest => {
+                match serde_json::from_value::<HistoryRequestData>(msg.payload) {
+                    Ok(request) => self.serve_history(&msg.from, request),
+                    Err(e) => log::warn!("Invalid history request from {}: {}", msg.from, e),
+                }
+            }
+            MessageType::HistoryResponse => {
+                match serde_json::from_value::<HistoryResponseData>(msg.payload) {
+                    Ok(response) => self.store_history(&msg.from, response),
+                    Err(e) => log::warn!("Invalid history response from {}: {}", msg.from, e),
+                }
+            }
+            MessageType::SnippetUpdate => {
+                match serde_json::from_value::<SnippetData>(msg.payload) {
+                    Ok(snippet) => self.store_snippet(snippet, &msg.from),
+                    Err(e) => log::warn!("Invalid snippet payload from {}: {}", msg.from, e),
+                }
+            }
+            MessageType::SnippetSync => {
+                match serde_json::from_value::<Vec<SnippetData>>(msg.payload) {
+                    Ok(snippets) => {
+                        for snippet in snippets {
+                            self.store_snippet(snippet, &msg.from);
+                        }
+                    }
+                    Err(e) => log::warn!("Invalid snippet payload from {}: {}", msg.from, e),
+                }
+            }
+            MessageType::GroupHello => {
+                match serde_json::from_value::<Vec<GroupProof>>(msg.payload) {
+                    Ok(proofs) => self.check_group_proofs(&msg.from, proofs),
+                    Err(e) => log::warn!("Invalid group proofs from {}: {}", msg.from, e),
+                }
+            }
+            MessageType::SnippetRemove => {
+                if !self.peers.lock().receives_from(&msg.from, TEXT_PLAIN) {
+                    log::debug!("Ignoring snippet removal from {} (sync policy)", msg.from);
+                    return;
+                }
+                if let Some(id) = msg.payload.get("id").and_then(|id| id.as_str()) {
+                    if let Err(e) = self.history.lock().unpin_shared(id, &msg.from) {
+                        log::warn!("Failed to remove shared snippet {}: {}", id, e);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_clipboard_update(&self, msg: NetworkMessage) {
+        if !self.peers.lock().receives_from(&msg.from, TEXT_PLAIN) {
+            log::debug!("Ignoring clipboard item from {} (sync policy)", msg.from);
+            return;
+        }
+
+        let mut data: ClipboardData = match serde_json::from_value(msg.payload) {
+            Ok(data) => data,
+            Err(e) => {
+                log::warn!("Invalid clipboard payload from {}: {}", msg.from, e);
+                return;
+            }
+        };
+        if let Some(group_id) = &data.group {
+            let Some(content) = self.open_from_group(group_id, &data.content) else {
+                log::warn!(
+                    "Dropping clipboard item from {} for group {} we cannot read",
+                    msg.from,
+                    group_id
+                );
+                return;
+            };
+            data.content = content;
+            // Only key holders can seal group items, but a captured item
+            // can be replayed under any sender id
+            if msg.authenticated {
+                self.add_group_member(group_id, &msg.from);
+            }
+        }
+        if data.content.len() as u64 > self.config.read().sync.max_item_size {
+            log::warn!(
+                "Dropping clipboard item of {} bytes from {}",
+                data.content.len(),
+                msg.from
+            );
+            return;
+        }
+
+        let (auto_sync, confirm) = {
+            let config = self.config.read();
+            (
+                config.sync.auto_sync,
+                config.security.confirm_incoming.clone(),
+            )
+        };
+        // Per-peer trust only counts for a sender the transport verified
+        let confirming = !msg.authenticated
+            || self
+                .peers
+                .lock()
+                .needs_confirmation(&msg.from, TEXT_PLAIN, &confirm);
+        let mut item = InboxItem {
+            id: data.id,
+            device_name: self.device_name(&msg.from),
+            origin_device: msg.from.clone(),
+            content: data.content,
+            timestamp: data.timestamp,
+            apply_at: None,
+            group_id: data.group,
+        };
+        if !auto_sync || confirming {
+            // Manual mode waits for the user whatever the policy says
+            item.apply_at = (auto_sync && msg.authenticated && confirm.auto_apply_secs > 0)
+                .then(|| now_millis() + confirm.auto_apply_secs * 1000);
+            self.stage(item);
+            return;
+        }
+        if let Err(e) = self.apply_remote(item) {
+            log::warn!("Failed to apply clipboard from {}: {}", msg.from, e);
+        }
+    }
+
+    /// Put an item from a peer on the clipboard and record it in history.
+    /// Items that are never applied stay out of history.
+    fn apply_remote(&self, item: InboxItem) -> Result<(), String> {
+        // Mark as seen first so the monitor does not echo it back to peers
+        self.monitor.set_last_content(item.content.clone());
+        clipboard::set_text(&item.content).map_err(|e| e.to_string())?;
+
+        if let Err(e) = self.history.lock().insert(&item.to_history_item()) {
+            log::warn!("Failed to record remote clipboard item: {}", e);
+        }
+
+        self.emit(Event::ClipboardChanged {
+            id: item.id,
+            source: ChangeSource::Remote,
+            device_name: item.device_name,
+            origin_device: item.origin_device,
+            preview: events::preview(&item.content, events::PREVIEW_LEN),
+        });
+        Ok(())
+    }
+
+    /// Keep an item from a peer in the inbox until it is accepted
+    fn stage(&self, item: InboxItem) {
+        {
+            let mut inbox = self.inbox.lock();
+            inbox.push(item.clone());
+            if inbox.len() > MAX_INBOX_ITEMS {
+                let dropped = inbox.remove(0);
+                log::info!(
+                    "Inbox full, dropped {} from {}",
+                    dropped.id,
+                    dropped.origin_device
+                );
+            }
+        }
+        self.emit(Event::ItemReceived(item));
+        self.inbox_changed();
+    }
+
+    /// Apply inbox items whose time has come until `watching` is cleared
+    fn watch_inbox(&self, watching: &Mutex<bool>) {
+        while *watching.lock() {
+            let now = now_millis();
+            let due: Vec<InboxItem> = {
+                let mut inbox = self.inbox.lock();
+                let (due, waiting) = inbox
+                    .drain(..)
+                    .partition(|item| item.apply_at.is_some_and(|at| at <= now));
+                *inbox = waiting;
+                due
+            };
+
+            if !due.is_empty() {
+                self.inbox_changed();
+            }
+            for item in due {
+                log::info!(
+                    "Applying {} from {} after the timeout",
+                    item.id,
+                    item.origin_device
+                );
+                if let Err(e) = self.apply_remote(item) {
+                    log::warn!("Failed to apply staged clipboard item: {}", e);
+                }
+            }
+
+            thread::sleep(INBOX_CHECK_INTERVAL);
+        }
+    }
+
+    fn inbox_changed(&self) {
+        let items = self.inbox.lock().clone();
+        self.emit(Event::InboxChanged(items));
+    }
+
+    /// Seal `content` with the key of `group_id`, hex encoded
+    fn seal_for_group(&self, group_id: &str, content: &str) -> Option<String> {
+        let cipher = self.groups.lock().get(group_id)?.cipher().ok()?;
+        Some(hex::encode(cipher.seal(content)))
+    }
+
+    /// Open content sealed by `seal_for_group`. `None` if we are not in the
+    /// group or the content was not sealed with its key.
+    fn open_from_group(&self, group_id: &str, sealed: &str) -> Option<String> {
+        let cipher = self.groups.lock().get(group_id)?.cipher().ok()?;
+        cipher.open(&hex::decode(sealed).ok()?)
+    }
+
+    fn add_group_member(&self, group_id: &str, device_id: &str) {
+        if let Err(e) = self.groups.lock().add_member(group_id, device_id) {
+            log::warn!("Failed to save group membership: {}", e);
+        }
+    }
+
+    /// Prove to a peer which groups we are in by sealing our device id
+    fn send_group_proofs(&self, peer_id: &str) {
+        let proofs: Vec<GroupProof> = {
+            let groups = self.groups.lock();
+            groups
+                .list()
+                .iter()
+                .filter_map(|group| {
+                    let cipher = group.cipher().ok()?;
+                    Some(GroupProof {
+                        group: group.id.clone(),
+                        proof: hex::encode(cipher.seal(&self.device_id)),
+                    })
+                })
+                .collect()
+        };
+        if proofs.is_empty() {
+            return;
+        }
+
+        let Some(network) = self.network() else {
+            return;
+        };
+        let message = NetworkMessage::new(
+            MessageType::GroupHello,
+            self.device_id.clone(),
+            serde_json::to_value(&proofs).unwrap(),
+        );
+        if let Err(e) = network.send_to(peer_id, &message) {
+            log::warn!("Failed to send group proofs to {}: {}", peer_id, e);
+        }
+    }
+
+    fn check_group_proofs(&self, peer_id: &str, proofs: Vec<GroupProof>) {
+        for proof in proofs {
+            if self.open_from_group(&proof.group, &proof.proof).as_deref() == Some(peer_id) {
+                self.add_group_member(&proof.group, peer_id);
+            }
+        }
+    }
+
+    /// Send our shared snippets to a newly connected peer
+    fn send_snippet_collection(&self, peer_id: &str) {
+        let snippets: Vec<SnippetData> = match self.history.lock().list_shared() {
+            Ok(items) => items.iter().map(Self::to_snippet).collect(),
+            Err(e) => {
+                log::warn!("Failed to load shared snippets: {}", e);
+                return;
+            }
+        };
+        if snippets.is_empty() {
+            return;
+        }
+
+        let Some(network) = self.network() else {
+            return;
+        };
+        let message = NetworkMessage::new(
+            MessageType::SnippetSync,
+            self.device_id.clone(),
+            serde_json::to_value(&snippets).unwrap(),
+        );
+        if let Err(e) = network.send_to(peer_id, &message) {
+            log::warn!("Failed to send snippets to {}: {}", peer_id, e);
+        }
+    }
+
+    /// Catch up on items copied since our newest one
+    fn backfill_history(&self, peer_id: &str) {
+        // Resume after the newest item the peer served us, whoever copied it
+        let since = match self.history.lock().backfill_cursor(peer_id) {
+            Ok(since) => since,
+            Err(e) => {
+                log::warn!("Failed to read history: {}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = self.request_history(peer_id, since, None, 0) {
+            log::warn!("Failed to request history from {}: {}", peer_id, e);
+        }
+    }
+
+    fn request_history(
+        &self,
+        peer_id: &str,
+        since: u64,
+        cursor: Option<HistoryCursor>,
+        received: usize,
+    ) -> Result<(), String> {
+        let network = self.network().ok_or("Network is not running")?;
+        let request = HistoryRequestData {
+            since,
+            cursor,
+            limit: HISTORY_PAGE_SIZE,
+        };
+        let message = NetworkMessage::new(
+            MessageType::HistoryRequest,
+            self.device_id.clone(),
+            serde_json::to_value(&request).map_err(|e| e.to_string())?,
+        );
+
+        // Expect the response before it can arrive
+        self.backfills
+            .lock()
+            .insert(peer_id.to_string(), Backfill { since, received });
+        network.send_to(peer_id, &message).map_err(|e| {
+            self.backfills.lock().remove(peer_id);
+            e.to_string()
+        })
+    }
+
+    fn serve_history(&self, peer_id: &str, request: HistoryRequestData) {
+        let mut response = HistoryResponseData {
+            since: request.since,
+            entries: Vec::new(),
+            next: None,
+            denied: false,
+        };
+
+        let allowed = {
+            let peers = self.peers.lock();
+            peers.allows_history(peer_id) && peers.sends_to(peer_id, TEXT_PLAIN)
+        };
+        if allowed {
+            let limit = request.limit.clamp(1, HISTORY_PAGE_SIZE);
+            let after = request
+                .cursor
+                .as_ref()
+                .map(|c| (c.timestamp, c.id.as_str()));
+            // Group items are only served to members of the group
+            let groups = self.groups.lock().groups_of(peer_id);
+            // Fetch one extra item to learn whether another page exists
+            let items =
+                match self
+                    .history
+                    .lock()
+                    .list_since(request.since, after, &groups, limit + 1)
+                {
+                    Ok(items) => items,
+                    Err(e) => {
+                        log::warn!("Failed to read history for {}: {}", peer_id, e);
+                        return;
+                    }
+                };
+
+            response.entries = items
+                .iter()
+                .take(limit)
+                .filter(|item| {
+                    let mime_type = item.mime_types.first().map_or(TEXT_PLAIN, String::as_str);
+                    self.peers.lock().sends_to(peer_id, mime_type)
+                })
+                .filter_map(|item| {
+                    let content = match &item.group_id {
+                        Some(group_id) => self.seal_for_group(group_id, &item.content)?,
+                        None => item.content.clone(),
+                    };
+                    Some(HistoryEntryData {
+                        id: item.id.clone(),
+                        origin_device: item.origin_device.clone(),
+                        content,
+                        mime_types: item.mime_types.clone(),
+                        timestamp: item.created_at,
+                        group: item.group_id.clone(),
+                    })
+                })
+                .collect();
+            if items.len() > limit {
+                response.next = items.get(limit - 1).map(|item| HistoryCursor {
+                    timestamp: item.created_at,
+                    id: item.id.clone(),
+                });
+            }
+        } else {
+            log::info!("Denied history request from {}", peer_id);
+            response.denied = true;
+        }
+
+        let Some(network) = self.network() else {
+            return;
+        };
+        let message = NetworkMessage::new(
+            MessageType::HistoryResponse,
+            self.device_id.clone(),
+            serde_json::to_value(&response).unwrap(),
+        );
+        if let Err(e) = network.send_to(peer_id, &message) {
+            log::warn!("Failed to send history to {}: {}", peer_id, e);
+        }
+    }
+
+    fn store_history(&self, peer_id: &str, response: HistoryResponseData) {
+        let backfill = {
+            let mut backfills = self.backfills.lock();
+            match backfills.get(peer_id) {
+                Some(backfill) if backfill.since == response.since => backfills.remove(peer_id),
+                _ => None,
+            }
+        };
+        let Some(backfill) = backfill else {
+            log::warn!("Ignoring history from {} we did not ask for", peer_id);
+            return;
+        };
+        if response.denied {
+            log::info!("{} did not allow us to read its history", peer_id);
+            return;
+        }
+
+        let max_item_size = self.config.read().sync.max_item_size;
+        // Pages come oldest first; entries we drop below count as received
+        let newest = response.entries.iter().map(|entry| entry.timestamp).max();
+        let entries: Vec<HistoryEntryData> = {
+            let peers = self.peers.lock();
+            response
+                .entries
+                .into_iter()
+                .filter(|entry| {
+                    let mime_type = entry.mime_types.first().map_or(TEXT_PLAIN, String::as_str);
+                    peers.receives_from(peer_id, mime_type)
+                        && peers.receives_from(&entry.origin_device, mime_type)
+                })
+                .collect()
+        };
+
+        let mut stored = 0;
+        {
+            let mut history = self.history.lock();
+            for entry in entries {
+                let content = match &entry.group {
+                    Some(group_id) => match self.open_from_group(group_id, &entry.content) {
+                        Some(content) => content,
+                        None => continue,
+                    },
+                    None => entry.content,
+                };
+                if content.len() as u64 > max_item_size {
+                    log::warn!(
+                        "Dropping history item of {} bytes from {}",
+                        content.len(),
+                        peer_id
+                    );
+                    continue;
+                }
+                let mut item = HistoryItem::text(
+                    entry.id,
+                    entry.origin_device,
+                    false,
+                    content,
+                    entry.timestamp,
+                );
+                item.mime_types = entry.mime_types;
+                item.group_id = entry.group;
+
+                match history.insert_if_absent(&item) {
+                    Ok(true) => stored += 1,
+                    Ok(false) => {}
+                    Err(e) => log::warn!("Failed to store history from {}: {}", peer_id, e),
+                }
+            }
+            if let Some(newest) = newest {
+                if let Err(e) = history.advance_backfill_cursor(peer_id, newest) {
+                    log::warn!("Failed to save backfill position for {}: {}", peer_id, e);
+                }
+            }
+        }
+        log::info!("Backfilled {} history items from {}", stored, peer_id);
+
+        let received = backfill.received + stored;
+        self.emit(Event::TransferProgress {
+            device_id: peer_id.to_string(),
+            received,
+            complete: response.next.is_none(),
+        });
+
+        if let Some(next) = response.next {
+            if let Err(e) = self.request_history(peer_id, response.since, Some(next), received) {
+                log::warn!("Failed to request more history from {}: {}", peer_id, e);
+            }
+        }
+    }
+
+    fn store_snippet(&self, snippet: SnippetData, from: &str) {
+        let mime_type = snippet
+            .mime_types
+            .first()
+            .map(String::as_str)
+            .unwrap_or(TEXT_PLAIN);
+        if !self.peers.lock().receives_from(from, mime_type) {
+            log::debug!("Ignoring shared snippet from {} (sync policy)", from);
+            return;
+        }
+
+        let mut item = HistoryItem::text(
+            snippet.id,
+            from.to_string(),
+            false,
+            snippet.content,
+            snippet.timestamp,
+        );
+        item.mime_types = snippet.mime_types;
+        item.pinned = true;
+        item.name = snippet.name;
+        item.pin_order = snippet.order;
+        item.shared = true;
+
+        match self.history.lock().store_shared(&item) {
+            Ok(true) => {}
+            Ok(false) => log::warn!(
+                "Ignoring snippet {} from {}: the id belongs to another item",
+                item.id,
+                from
+            ),
+            Err(e) => log::warn!("Failed to store shared snippet from {}: {}", from, e),
+        }
+    }
+
+    fn to_snippet(item: &HistoryItem) -> SnippetData {
+        SnippetData {
+            id: item.id.clone(),
+            name: item.name.clone(),
+            content: item.content.clone(),
+            mime_types: item.mime_types.clone(),
+            order: item.pin_order,
+            timestamp: item.created_at,
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+impl Drop for SyncEngine {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peers::{ConfirmPolicy, SyncDirection};
+
+    fn context(config: AppConfig) -> SyncContext {
+        SyncContext::new(
+            "desktop".to_string(),
+            config,
+            Arc::new(Mutex::new(HistoryStore::open_in_memory().unwrap())),
+            Arc::new(Mutex::new(PeerStore::in_memory())),
+            Arc::new(Mutex::new(GroupStore::in_memory())),
+            Arc::new(Mutex::new(AuditLog::in_memory())),
+        )
+    }
+
+    fn message(msg_type: MessageType, from: &str, payload: serde_json::Value) -> NetworkMessage {
+        let mut msg = NetworkMessage::new(msg_type, from.to_string(), payload);
+        msg.authenticated = true;
+        msg
+    }
+
+    fn clipboard(from: &str, content: &str) -> NetworkMessage {
+        let data = ClipboardData::new(content.to_string());
+        message(
+            MessageType::ClipboardUpdate,
+            from,
+            serde_json::to_value(data).unwrap(),
+        )
+    }
+
+    /// Record a history request to `peer_id` as if it had been sent
+    fn expect_history(ctx: &SyncContext, peer_id: &str, since: u64) {
+        ctx.backfills
+            .lock()
+            .insert(peer_id.to_string(), Backfill { since, received: 0 });
+    }
+
+    #[test]
+    fn test_trust_needs_an_authenticated_sender() {
+        let mut config = AppConfig::default();
+        config.security.confirm_incoming.enabled = true;
+        config.security.confirm_incoming.auto_apply_secs = 30;
+        let ctx = context(config);
+        ctx.peers
+            .lock()
+            .update("laptop", |peer| peer.confirm = ConfirmPolicy::Trusted)
+            .unwrap();
+
+        // Claims to be the trusted laptop, but nothing verified it
+        let mut spoofed = clipboard("laptop", "from somewhere");
+        spoofed.authenticated = false;
+        ctx.handle_message(spoofed);
+        let inbox = ctx.inbox.lock().clone();
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(inbox[0].apply_at, None);
+
+        // A verified peer without trust follows confirmIncoming
+        ctx.handle_message(clipboard("phone", "from the phone"));
+        let inbox = ctx.inbox.lock().clone();
+        assert_eq!(inbox.len(), 2);
+        assert!(inbox[1].apply_at.is_some());
+    }
+
+    #[test]
+    fn test_unapplied_items_stay_out_of_history() {
+        let mut config = AppConfig::default();
+        config.security.confirm_incoming.enabled = true;
+        let ctx = context(config);
+
+        let mut spoofed = clipboard("laptop", "from somewhere");
+        spoofed.authenticated = false;
+        ctx.handle_message(spoofed);
+        ctx.handle_message(clipboard("phone", "waiting for the user"));
+
+        // Staged items are only recorded once accepted, so discarding
+        // them leaves nothing behind
+        assert_eq!(ctx.inbox.lock().len(), 2);
+        assert_eq!(ctx.history.lock().count().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_concealed_and_excluded_copies_are_reported_blocked() {
+        let mut config = AppConfig::default();
+        config.security.excluded_apps = vec!["keepassxc".to_string()];
+        let ctx = context(config);
+        let blocked = Arc::new(Mutex::new(Vec::new()));
+        let sink = blocked.clone();
+        let _ = ctx.on_event.set(Arc::new(move |event: &Event| {
+            if let Event::SensitiveItemBlocked { rules } = event {
+                sink.lock().extend(rules.clone());
+            }
+        }));
+
+        ctx.handle_local_change(ClipboardItem {
+            text: "hunter2".to_string(),
+            concealed: true,
+            source: None,
+        });
+        ctx.handle_local_change(ClipboardItem {
+            text: "hunter2".to_string(),
+            concealed: false,
+            source: Some(SourceApp {
+                class: Some("KeePassXC".to_string()),
+                instance: Some("keepassxc".to_string()),
+                pid: None,
+                executable: None,
+            }),
+        });
+
+        assert_eq!(
+            *blocked.lock(),
+            vec![
+                CONCEALED_RULE.to_string(),
+                format!("{}:keepassxc", EXCLUDED_APP_RULE)
+            ]
+        );
+        assert_eq!(ctx.history.lock().count().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_sending_to_filtered_devices_fails() {
+        let ctx = context(AppConfig::default());
+        ctx.peers
+            .lock()
+            .update("laptop", |peer| peer.direction = SyncDirection::ReceiveOnly)
+            .unwrap();
+
+        let to = Some(vec!["laptop".to_string()]);
+        assert!(ctx.share("notes".to_string(), None, to).is_err());
+        assert_eq!(ctx.history.lock().count().unwrap(), 0);
+
+        // Copies for every peer are still recorded with nobody to send to
+        assert!(ctx.share("notes".to_string(), None, None).unwrap());
+        assert_eq!(ctx.history.lock().count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_group_membership_needs_an_authenticated_sender() {
+        let ctx = context(AppConfig::default());
+        let group = ctx
+            .groups
+            .lock()
+            .create("ops".to_string(), "desktop")
+            .unwrap();
+        let group_item = |from: &str| {
+            let mut data =
+                ClipboardData::new(ctx.seal_for_group(&group.id, "deploy token").unwrap());
+            data.group = Some(group.id.clone());
+            message(
+                MessageType::ClipboardUpdate,
+                from,
+                serde_json::to_value(data).unwrap(),
+            )
+        };
+
+        // A replayed item proves nothing about the claimed sender
+        let mut replayed = group_item("phone");
+        replayed.authenticated = false;
+        ctx.handle_message(replayed);
+        assert!(ctx.groups.lock().groups_of("phone").is_empty());
+
+        ctx.handle_message(group_item("laptop"));
+        assert_eq!(
+            ctx.groups.lock().groups_of("laptop"),
+            vec![group.id.clone()]
+        );
+    }
+
+    #[test]
+    fn test_snippets_follow_sync_policy_and_ownership() {
+        let ctx = context(AppConfig::default());
+        let snippet = |id: &str, content: &str| SnippetData {
+            id: id.to_string(),
+            name: None,
+            content: content.to_string(),
+            mime_types: vec![TEXT_PLAIN.to_string()],
+            order: None,
+            timestamp: 1,
+        };
+        let update = |from: &str, id: &str, content: &str| {
+            message(
+                MessageType::SnippetUpdate,
+                from,
+                serde_json::to_value(snippet(id, content)).unwrap(),
+            )
+        };
+
+        ctx.handle_message(update("laptop", "s", "address"));
+        // Another peer can neither replace nor remove it
+        ctx.handle_message(update("phone", "s", "phishing"));
+        ctx.handle_message(message(
+            MessageType::SnippetRemove,
+            "phone",
+            serde_json::json!({ "id": "s" }),
+        ));
+        let stored = ctx.history.lock().get("s").unwrap().unwrap();
+        assert_eq!(stored.content, "address");
+        assert!(stored.pinned);
+
+        let mut spoofed = update("laptop", "s", "spoofed");
+        spoofed.authenticated = false;
+        ctx.handle_message(spoofed);
+        ctx.peers
+            .lock()
+            .update("laptop", |peer| peer.direction = SyncDirection::SendOnly)
+            .unwrap();
+        ctx.handle_message(update("laptop", "s", "muted"));
+        assert_eq!(
+            ctx.history.lock().get("s").unwrap().unwrap().content,
+            "address"
+        );
+    }
+
+    #[test]
+    fn test_backfilled_history_follows_sync_policy() {
+        let mut config = AppConfig::default();
+        config.sync.max_item_size = 16;
+        let ctx = context(config);
+        ctx.peers
+            .lock()
+            .update("phone", |peer| peer.direction = SyncDirection::Muted)
+            .unwrap();
+        expect_history(&ctx, "laptop", 0);
+
+        let entry =
+            |id: &str, origin_device: &str, content: &str, timestamp: u64| HistoryEntryData {
+                id: id.to_string(),
+                origin_device: origin_device.to_string(),
+                content: content.to_string(),
+                mime_types: vec![TEXT_PLAIN.to_string()],
+                timestamp,
+                group: None,
+            };
+        let response = HistoryResponseData {
+            since: 0,
+            entries: vec![
+                entry("a", "laptop", "kept", 10),
+                entry("b", "laptop", "far too large for the limit", 20),
+                entry("c", "phone", "from a muted device", 30),
+                entry("d", "tablet", "relayed", 40),
+            ],
+            next: None,
+            denied: false,
+        };
+        ctx.handle_message(message(
+            MessageType::HistoryResponse,
+            "laptop",
+            serde_json::to_value(&response).unwrap(),
+        ));
+
+        let history = ctx.history.lock();
+        assert!(history.get("a").unwrap().is_some());
+        assert!(history.get("b").unwrap().is_none());
+        assert!(history.get("c").unwrap().is_none());
+        assert!(history.get("d").unwrap().is_some());
+        // The next backfill from the laptop resumes after everything it sent
+        assert_eq!(history.backfill_cursor("laptop").unwrap(), 40);
+        assert_eq!(history.backfill_cursor("phone").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_backfill_resumes_after_items_the_peer_served() {
+        let ctx = context(AppConfig::default());
+        let response = |entries: Vec<HistoryEntryData>| {
+            message(
+                MessageType::HistoryResponse,
+                "laptop",
+                serde_json::to_value(HistoryResponseData {
+                    since: 0,
+                    entries,
+                    next: None,
+                    denied: false,
+                })
+                .unwrap(),
+            )
+        };
+        let entry = |id: &str, origin_device: &str, timestamp: u64| HistoryEntryData {
+            id: id.to_string(),
+            origin_device: origin_device.to_string(),
+            content: id.to_string(),
+            mime_types: vec![TEXT_PLAIN.to_string()],
+            timestamp,
+            group: None,
+        };
+
+        // A live-synced copy from the laptop is newer than a tablet item the
+        // laptop holds and we missed
+        let copy = HistoryItem::text(
+            "copy".to_string(),
+            "laptop".to_string(),
+            false,
+            "latest copy".to_string(),
+            100,
+        );
+        ctx.history.lock().insert(&copy).unwrap();
+        assert_eq!(ctx.history.lock().backfill_cursor("laptop").unwrap(), 0);
+        expect_history(&ctx, "laptop", 0);
+
+        ctx.handle_message(response(vec![
+            entry("missed", "tablet", 50),
+            entry("copy", "laptop", 100),
+        ]));
+        let history = ctx.history.lock();
+        assert!(history.get("missed").unwrap().is_some());
+        assert_eq!(history.backfill_cursor("laptop").unwrap(), 100);
+        // Origins claimed by the laptop do not move other peers' cursors
+        assert_eq!(history.backfill_cursor("tablet").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_only_requested_history_is_stored() {
+        let ctx = context(AppConfig::default());
+        let response = |since: u64, id: &str| {
+            message(
+                MessageType::HistoryResponse,
+                "laptop",
+                serde_json::to_value(HistoryResponseData {
+                    since,
+                    entries: vec![HistoryEntryData {
+                        id: id.to_string(),
+                        origin_device: "tablet".to_string(),
+                        content: id.to_string(),
+                        mime_types: vec![TEXT_PLAIN.to_string()],
+                        timestamp: 50,
+                        group: None,
+                    }],
+                    next: None,
+                    denied: false,
+                })
+                .unwrap(),
+            )
+        };
+
+        ctx.handle_message(response(0, "unsolicited"));
+        expect_history(&ctx, "laptop", 10);
+        ctx.handle_message(response(0, "other request"));
+        ctx.handle_message(response(10, "answer"));
+        // The request is answered once
+        ctx.handle_message(response(10, "replayed"));
+
+        let history = ctx.history.lock();
+        assert!(history.get("unsolicited").unwrap().is_none());
+        assert!(history.get("other request").unwrap().is_none());
+        assert!(history.get("answer").unwrap().is_some());
+        assert!(history.get("replayed").unwrap().is_none());
+        assert_eq!(history.backfill_cursor("laptop").unwrap(), 50);
+    }
+}
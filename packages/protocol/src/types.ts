@@ -116,9 +116,14 @@ export interface AppConfig {
     autoStart: boolean;
     showNotifications: boolean;
     historySize: number;
+    /** Delete items older than this many days */
+    maxAgeDays?: number;
+    /** Delete the oldest items once their content exceeds this many bytes */
+    maxTotalBytes?: number;
   };
   sync: {
     mode: SyncMode;
+    /** Send every copy; when false, only items sent explicitly, and incoming items wait in an inbox */
     autoSync: boolean;
     syncImages: boolean;
     syncFiles: boolean;
@@ -129,12 +134,30 @@ export interface AppConfig {
     requireDeviceApproval: boolean;
     enableSensitiveFilter: boolean;
     excludedApps: string[];
+    /** Stage items from peers until they are applied or discarded */
+    confirmIncoming: {
+      enabled: boolean;
+      /** e.g. `text/plain` or `text/*`; empty means all types */
+      mimeTypes: string[];
+      /** Apply a staged item after this many seconds; 0 waits for the user */
+      autoApplySecs: number;
+    };
   };
   network: {
     relayServerUrl?: string;
     p2pPort: number;
     discoveryEnabled: boolean;
   };
+  /** Global shortcuts; an empty string disables one */
+  hotkeys: {
+    enabled: boolean;
+    push: string;
+    pull: string;
+    toggleSync: string;
+    historyPicker: string;
+    /** Device to push to and pull from; every device when unset */
+    device?: string;
+  };
 }
 
 /**
@@ -158,9 +181,21 @@ export const DEFAULT_CONFIG: AppConfig = {
     requireDeviceApproval: true,
     enableSensitiveFilter: true,
     excludedApps: [],
+    confirmIncoming: {
+      enabled: false,
+      mimeTypes: [],
+      autoApplySecs: 0,
+    },
   },
   network: {
     p2pPort: 7878,
     discoveryEnabled: true,
   },
+  hotkeys: {
+    enabled: true,
+    push: 'CmdOrCtrl+Alt+S',
+    pull: 'CmdOrCtrl+Alt+G',
+    toggleSync: 'CmdOrCtrl+Alt+P',
+    historyPicker: 'CmdOrCtrl+Alt+H',
+  },
 };
@@ -86,6 +86,10 @@ export function validateConfig(config: any): config is AppConfig {
   if (typeof config.general.showNotifications !== 'boolean') return false;
   if (typeof config.general.historySize !== 'number' || config.general.historySize < 0)
     return false;
+  for (const key of ['maxAgeDays', 'maxTotalBytes']) {
+    const value = config.general[key];
+    if (value !== undefined && (typeof value !== 'number' || value <= 0)) return false;
+  }
 
   // Validate sync settings
   if (!config.sync || typeof config.sync !== 'object') return false;
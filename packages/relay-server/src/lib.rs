@@ -0,0 +1,85 @@
+//! Relay server for ClipBridge cloud sync mode.
+//!
+//! Devices connect over WebSocket, authenticate with their Ed25519 device
+//! key and exchange `NetworkMessage` frames through the relay. Frames are
+//! routed by their `from`/`to` fields only; clipboard payloads are end-to-end
+//! encrypted by the devices and never inspected.
+
+pub mod protocol;
+pub mod queue;
+pub mod registry;
+mod server;
+
+pub use server::RelayServer;
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum RelayError {
+    Io(std::io::Error),
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    Protocol(String),
+    Auth(String),
+}
+
+impl fmt::Display for RelayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelayError::Io(e) => write!(f, "IO error: {}", e),
+            RelayError::WebSocket(e) => write!(f, "WebSocket error: {}", e),
+            RelayError::Protocol(e) => write!(f, "Protocol error: {}", e),
+            RelayError::Auth(e) => write!(f, "Authentication failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RelayError {}
+
+impl From<std::io::Error> for RelayError {
+    fn from(e: std::io::Error) -> Self {
+        RelayError::Io(e)
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for RelayError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        RelayError::WebSocket(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for RelayError {
+    fn from(e: serde_json::Error) -> Self {
+        RelayError::Protocol(e.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, RelayError>;
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub listen: SocketAddr,
+    /// Where device registries are stored. `None` keeps them in memory.
+    pub registry_path: Option<PathBuf>,
+    /// How long frames are kept for offline devices
+    pub queue_ttl: Duration,
+    /// Most frames kept per offline device
+    pub max_queued: usize,
+    /// Largest accepted WebSocket message in bytes
+    pub max_message_size: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([0, 0, 0, 0], 8787)),
+            registry_path: None,
+            queue_ttl: Duration::from_secs(24 * 60 * 60),
+            max_queued: 100,
+            // Fits a sealed frame for the desktop's default 10MB item size
+            max_message_size: 48 * 1024 * 1024,
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use clipbridge_relay::{RelayServer, ServerConfig};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: clipbridge-relay [--listen ADDR] [--registry FILE] [--queue-ttl SECS]";
+
+fn parse_args() -> Result<ServerConfig, String> {
+    let mut config = ServerConfig::default();
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--listen" => {
+                config.listen = value()?
+                    .parse()
+                    .map_err(|e| format!("Invalid --listen: {}", e))?
+            }
+            "--registry" => config.registry_path = Some(PathBuf::from(value()?)),
+            "--queue-ttl" => {
+                let secs = value()?
+                    .parse()
+                    .map_err(|e| format!("Invalid --queue-ttl: {}", e))?;
+                config.queue_ttl = Duration::from_secs(secs);
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ => return Err(format!("Unknown argument {}\n{}", arg, USAGE)),
+        }
+    }
+
+    Ok(config)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match RelayServer::bind(config).await {
+        Ok(server) => {
+            server
+                .run_until(async {
+                    let _ = tokio::signal::ctrl_c().await;
+                })
+                .await
+        }
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+/// Prefix of the string a device signs to prove it holds its key
+const AUTH_CONTEXT: &str = "clipbridge-relay-auth";
+
+/// Frames sent by devices. Every frame is a JSON text message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ClientFrame {
+    /// First frame on a connection. `public_key` is a hex encoded Ed25519
+    /// key; the first device of a new account registers it.
+    #[serde(rename_all = "camelCase")]
+    Hello {
+        account: String,
+        device_id: String,
+        public_key: String,
+    },
+    /// Answer to `Challenge`: a hex encoded signature of `auth_message`
+    Auth { signature: String },
+    /// Register another device on our account
+    #[serde(rename_all = "camelCase")]
+    AddDevice {
+        device_id: String,
+        public_key: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    RemoveDevice { device_id: String },
+    /// A `NetworkMessage` to route. Only `from` and `to` are read; the
+    /// payload is end-to-end encrypted by the devices.
+    Frame { message: serde_json::Value },
+}
+
+/// Frames sent by the relay
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ServerFrame {
+    Challenge {
+        nonce: String,
+    },
+    /// Authentication succeeded. Queued frames follow.
+    Welcome {
+        devices: Vec<DeviceStatus>,
+    },
+    /// The account's device list changed
+    Devices {
+        devices: Vec<DeviceStatus>,
+    },
+    Frame {
+        message: serde_json::Value,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceStatus {
+    pub device_id: String,
+    pub public_key: String,
+    pub online: bool,
+}
+
+/// The bytes a device signs to answer a challenge
+pub fn auth_message(nonce: &str, account: &str, device_id: &str) -> String {
+    format!("{}:{}:{}:{}", AUTH_CONTEXT, nonce, account, device_id)
+}
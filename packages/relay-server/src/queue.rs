@@ -0,0 +1,92 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Frames waiting for offline devices. Frames expire after the TTL, and
+/// each device keeps at most `max_per_device`, dropping the oldest.
+pub struct OfflineQueue {
+    ttl: Duration,
+    max_per_device: usize,
+    /// Keyed by (account, device id)
+    queues: HashMap<(String, String), VecDeque<(Instant, serde_json::Value)>>,
+}
+
+impl OfflineQueue {
+    pub fn new(ttl: Duration, max_per_device: usize) -> Self {
+        Self {
+            ttl,
+            max_per_device,
+            queues: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, account: &str, device_id: &str, message: serde_json::Value) {
+        let queue = self
+            .queues
+            .entry((account.to_string(), device_id.to_string()))
+            .or_default();
+        queue.push_back((Instant::now() + self.ttl, message));
+        while queue.len() > self.max_per_device {
+            queue.pop_front();
+        }
+    }
+
+    /// Remove and return the unexpired frames for a device, oldest first
+    pub fn take(&mut self, account: &str, device_id: &str) -> Vec<serde_json::Value> {
+        let now = Instant::now();
+        self.queues
+            .remove(&(account.to_string(), device_id.to_string()))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(expires, _)| *expires > now)
+            .map(|(_, message)| message)
+            .collect()
+    }
+
+    /// Drop expired frames for every device
+    pub fn purge(&mut self) {
+        let now = Instant::now();
+        self.queues.retain(|_, queue| {
+            queue.retain(|(expires, _)| *expires > now);
+            !queue.is_empty()
+        });
+    }
+
+    /// Drop everything queued for a device that was removed
+    pub fn forget(&mut self, account: &str, device_id: &str) {
+        self.queues
+            .remove(&(account.to_string(), device_id.to_string()));
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_take_returns_frames_in_order() {
+        let mut queue = OfflineQueue::new(Duration::from_secs(60), 2);
+        for i in 0..3 {
+            queue.push("acct", "phone", json!(i));
+        }
+
+        assert_eq!(queue.take("acct", "phone"), vec![json!(1), json!(2)]);
+        assert!(queue.take("acct", "phone").is_empty());
+    }
+
+    #[test]
+    fn test_expired_frames_are_dropped() {
+        let mut queue = OfflineQueue::new(Duration::ZERO, 10);
+        queue.push("acct", "phone", json!("stale"));
+        queue.purge();
+        assert!(queue.is_empty());
+    }
+}
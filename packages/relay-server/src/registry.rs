@@ -0,0 +1,164 @@
+use crate::{RelayError, Result};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Devices registered on one account, keyed by device id. Values are hex
+/// encoded Ed25519 public keys.
+pub type Account = BTreeMap<String, String>;
+
+/// Per-account device registries, persisted as JSON
+pub struct Registry {
+    path: Option<PathBuf>,
+    accounts: BTreeMap<String, Account>,
+}
+
+impl Registry {
+    /// Load the registry from `path`, starting empty if it doesn't exist
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let accounts = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(io::Error::other)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            accounts,
+        })
+    }
+
+    /// A registry that is never written to disk
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            accounts: BTreeMap::new(),
+        }
+    }
+
+    pub fn account(&self, account: &str) -> Option<&Account> {
+        self.accounts.get(account)
+    }
+
+    /// Check that `public_key` may sign in as `device_id`: it must be the
+    /// registered key, or the account must not exist yet.
+    pub fn check_key(&self, account: &str, device_id: &str, public_key: &str) -> Result<()> {
+        parse_key(public_key)?;
+        match self.accounts.get(account) {
+            None => Ok(()),
+            Some(devices) => match devices.get(device_id) {
+                Some(key) if key.eq_ignore_ascii_case(public_key) => Ok(()),
+                Some(_) => Err(RelayError::Auth("Key does not match device".to_string())),
+                None => Err(RelayError::Auth(
+                    "Device is not registered on this account".to_string(),
+                )),
+            },
+        }
+    }
+
+    /// Verify a challenge signature and, for a new account, register the
+    /// device as its first member
+    pub fn authenticate(
+        &mut self,
+        account: &str,
+        device_id: &str,
+        public_key: &str,
+        message: &[u8],
+        signature: &str,
+    ) -> Result<()> {
+        self.check_key(account, device_id, public_key)?;
+
+        let signature = hex::decode(signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or_else(|| RelayError::Auth("Malformed signature".to_string()))?;
+        parse_key(public_key)?
+            .verify(message, &signature)
+            .map_err(|_| RelayError::Auth("Bad signature".to_string()))?;
+
+        if !self.accounts.contains_key(account) {
+            log::info!("Created account {} for device {}", account, device_id);
+            self.add_device(account, device_id, public_key)?;
+        }
+        Ok(())
+    }
+
+    pub fn add_device(&mut self, account: &str, device_id: &str, public_key: &str) -> Result<()> {
+        parse_key(public_key)?;
+        self.accounts
+            .entry(account.to_string())
+            .or_default()
+            .insert(device_id.to_string(), public_key.to_ascii_lowercase());
+        self.save()
+    }
+
+    pub fn remove_device(&mut self, account: &str, device_id: &str) -> Result<()> {
+        if let Some(devices) = self.accounts.get_mut(account) {
+            devices.remove(device_id);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let bytes = serde_json::to_vec_pretty(&self.accounts).map_err(io::Error::other)?;
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+}
+
+fn parse_key(public_key: &str) -> Result<VerifyingKey> {
+    hex::decode(public_key)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| RelayError::Auth("Malformed public key".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn key() -> (SigningKey, String) {
+        let key = SigningKey::generate(&mut rand::rngs::OsRng);
+        let public = hex::encode(key.verifying_key().as_bytes());
+        (key, public)
+    }
+
+    #[test]
+    fn test_first_device_creates_the_account() {
+        let mut registry = Registry::in_memory();
+        let (laptop, laptop_public) = key();
+        let signature = hex::encode(laptop.sign(b"challenge").to_bytes());
+
+        registry
+            .authenticate("acct", "laptop", &laptop_public, b"challenge", &signature)
+            .unwrap();
+        assert_eq!(registry.account("acct").unwrap().len(), 1);
+
+        // Later devices must be added by a registered one
+        let (_, phone_public) = key();
+        assert!(registry.check_key("acct", "phone", &phone_public).is_err());
+        registry.add_device("acct", "phone", &phone_public).unwrap();
+        registry.check_key("acct", "phone", &phone_public).unwrap();
+    }
+
+    #[test]
+    fn test_bad_signature_is_rejected() {
+        let mut registry = Registry::in_memory();
+        let (_, public) = key();
+        let (other, _) = key();
+        let signature = hex::encode(other.sign(b"challenge").to_bytes());
+
+        assert!(registry
+            .authenticate("acct", "laptop", &public, b"challenge", &signature)
+            .is_err());
+        assert!(registry.account("acct").is_none());
+    }
+}
@@ -0,0 +1,412 @@
+use crate::protocol::{auth_message, ClientFrame, DeviceStatus, ServerFrame};
+use crate::queue::OfflineQueue;
+use crate::registry::Registry;
+use crate::{RelayError, Result, ServerConfig};
+use futures_util::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/// Time allowed for the WebSocket handshake and for answering the challenge
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+type Outgoing = mpsc::UnboundedSender<Message>;
+
+/// An authenticated connection
+struct Session {
+    id: u64,
+    tx: Outgoing,
+}
+
+struct Shared {
+    config: ServerConfig,
+    registry: Mutex<Registry>,
+    queue: Mutex<OfflineQueue>,
+    /// Connected devices by account, then device id
+    online: Mutex<HashMap<String, HashMap<String, Session>>>,
+    next_session: AtomicU64,
+}
+
+/// WebSocket relay between the devices of an account
+pub struct RelayServer {
+    listener: TcpListener,
+    shared: Arc<Shared>,
+}
+
+impl RelayServer {
+    pub async fn bind(config: ServerConfig) -> Result<Self> {
+        let registry = match &config.registry_path {
+            Some(path) => Registry::load(path.clone())?,
+            None => Registry::in_memory(),
+        };
+        let listener = TcpListener::bind(config.listen).await?;
+
+        Ok(Self {
+            listener,
+            shared: Arc::new(Shared {
+                queue: Mutex::new(OfflineQueue::new(config.queue_ttl, config.max_queued)),
+                config,
+                registry: Mutex::new(registry),
+                online: Mutex::new(HashMap::new()),
+                next_session: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accept connections until `shutdown` completes
+    pub async fn run_until<F>(self, shutdown: F) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        log::info!("Relay listening on {}", self.local_addr()?);
+        tokio::pin!(shutdown);
+        let mut purge = tokio::time::interval(PURGE_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = purge.tick() => self.shared.queue.lock().purge(),
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, addr)) => {
+                        let shared = Arc::clone(&self.shared);
+                        tokio::spawn(async move {
+                            if let Err(e) = handle_connection(shared, stream).await {
+                                log::debug!("Connection from {} closed: {}", addr, e);
+                            }
+                        });
+                    }
+                    Err(e) => log::error!("Error accepting connection: {}", e),
+                },
+            }
+        }
+
+        log::info!("Relay stopped");
+        Ok(())
+    }
+}
+
+/// The identity a connection authenticated as
+struct Device {
+    account: String,
+    device_id: String,
+}
+
+async fn handle_connection(shared: Arc<Shared>, stream: TcpStream) -> Result<()> {
+    let ws_config = WebSocketConfig {
+        max_message_size: Some(shared.config.max_message_size),
+        max_frame_size: Some(shared.config.max_message_size),
+        ..Default::default()
+    };
+    let mut ws = tokio::time::timeout(
+        HANDSHAKE_TIMEOUT,
+        tokio_tungstenite::accept_async_with_config(stream, Some(ws_config)),
+    )
+    .await
+    .map_err(|_| RelayError::Protocol("Handshake timed out".to_string()))??;
+
+    let device = match tokio::time::timeout(HANDSHAKE_TIMEOUT, authenticate(&shared, &mut ws))
+        .await
+        .unwrap_or_else(|_| Err(RelayError::Auth("Timed out".to_string())))
+    {
+        Ok(device) => device,
+        Err(e) => {
+            let _ = send(
+                &mut ws,
+                &ServerFrame::Error {
+                    message: e.to_string(),
+                },
+            )
+            .await;
+            let _ = ws.close(None).await;
+            return Err(e);
+        }
+    };
+    log::info!(
+        "Device {} of {} connected",
+        device.device_id,
+        device.account
+    );
+
+    let (mut sink, mut stream) = ws.split();
+    let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+    let writer = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            let close = matches!(message, Message::Close(_));
+            if sink.send(message).await.is_err() || close {
+                break;
+            }
+        }
+    });
+
+    let session_id = shared.next_session.fetch_add(1, Ordering::Relaxed);
+    let mut devices = device_list(&shared, &device.account);
+    for status in devices.iter_mut() {
+        status.online |= status.device_id == device.device_id;
+    }
+
+    // Flush the queue and go online under one lock so no frame routed in
+    // between is lost
+    let replaced = {
+        let mut online = shared.online.lock();
+        queue_frame(&tx, &ServerFrame::Welcome { devices });
+        for message in shared.queue.lock().take(&device.account, &device.device_id) {
+            queue_frame(&tx, &ServerFrame::Frame { message });
+        }
+
+        // A device signing in again replaces its old connection
+        online.entry(device.account.clone()).or_default().insert(
+            device.device_id.clone(),
+            Session {
+                id: session_id,
+                tx: tx.clone(),
+            },
+        )
+    };
+    if let Some(old) = replaced {
+        let _ = old.tx.send(Message::Close(None));
+    }
+    push_device_list(&shared, &device.account);
+
+    while let Some(message) = stream.next().await {
+        let text = match message {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) | Err(_) => break,
+            Ok(_) => continue,
+        };
+
+        let result = match serde_json::from_str::<ClientFrame>(&text) {
+            Ok(frame) => handle_frame(&shared, &device, frame),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            queue_frame(
+                &tx,
+                &ServerFrame::Error {
+                    message: e.to_string(),
+                },
+            );
+        }
+    }
+
+    // Only remove our own session; a newer one may have replaced it
+    {
+        let mut online = shared.online.lock();
+        if let Some(devices) = online.get_mut(&device.account) {
+            if devices
+                .get(&device.device_id)
+                .is_some_and(|session| session.id == session_id)
+            {
+                devices.remove(&device.device_id);
+            }
+            if devices.is_empty() {
+                online.remove(&device.account);
+            }
+        }
+    }
+    push_device_list(&shared, &device.account);
+    log::info!(
+        "Device {} of {} disconnected",
+        device.device_id,
+        device.account
+    );
+
+    drop(tx);
+    let _ = writer.await;
+    Ok(())
+}
+
+/// Run the hello/challenge/auth exchange
+async fn authenticate(shared: &Shared, ws: &mut WebSocketStream<TcpStream>) -> Result<Device> {
+    let ClientFrame::Hello {
+        account,
+        device_id,
+        public_key,
+    } = read_frame(ws).await?
+    else {
+        return Err(RelayError::Protocol("Expected hello".to_string()));
+    };
+    if account.is_empty() || device_id.is_empty() {
+        return Err(RelayError::Protocol(
+            "Missing account or device id".to_string(),
+        ));
+    }
+    shared
+        .registry
+        .lock()
+        .check_key(&account, &device_id, &public_key)?;
+
+    let nonce = uuid::Uuid::new_v4().to_string();
+    send(
+        ws,
+        &ServerFrame::Challenge {
+            nonce: nonce.clone(),
+        },
+    )
+    .await?;
+
+    let ClientFrame::Auth { signature } = read_frame(ws).await? else {
+        return Err(RelayError::Protocol("Expected auth".to_string()));
+    };
+    let message = auth_message(&nonce, &account, &device_id);
+    shared.registry.lock().authenticate(
+        &account,
+        &device_id,
+        &public_key,
+        message.as_bytes(),
+        &signature,
+    )?;
+
+    Ok(Device { account, device_id })
+}
+
+fn handle_frame(shared: &Shared, device: &Device, frame: ClientFrame) -> Result<()> {
+    match frame {
+        ClientFrame::Frame { message } => route(shared, device, message),
+        ClientFrame::AddDevice {
+            device_id,
+            public_key,
+        } => {
+            shared
+                .registry
+                .lock()
+                .add_device(&device.account, &device_id, &public_key)?;
+            log::info!("Device {} added to {}", device_id, device.account);
+            push_device_list(shared, &device.account);
+            Ok(())
+        }
+        ClientFrame::RemoveDevice { device_id } => {
+            shared
+                .registry
+                .lock()
+                .remove_device(&device.account, &device_id)?;
+            shared.queue.lock().forget(&device.account, &device_id);
+            let removed = shared
+                .online
+                .lock()
+                .get_mut(&device.account)
+                .and_then(|devices| devices.remove(&device_id));
+            if let Some(session) = removed {
+                let _ = session.tx.send(Message::Close(None));
+            }
+            log::info!("Device {} removed from {}", device_id, device.account);
+            push_device_list(shared, &device.account);
+            Ok(())
+        }
+        ClientFrame::Hello { .. } | ClientFrame::Auth { .. } => {
+            Err(RelayError::Protocol("Already authenticated".to_string()))
+        }
+    }
+}
+
+/// Forward a message to the devices in its `to` field, or to every other
+/// device of the account. Offline devices get it when they reconnect.
+fn route(shared: &Shared, device: &Device, message: serde_json::Value) -> Result<()> {
+    if message.get("from").and_then(|from| from.as_str()) != Some(device.device_id.as_str()) {
+        return Err(RelayError::Protocol(
+            "Message sender does not match device".to_string(),
+        ));
+    }
+    let to: Option<Vec<String>> = match message.get("to") {
+        None | Some(serde_json::Value::Null) => None,
+        Some(to) => Some(serde_json::from_value(to.clone())?),
+    };
+
+    let targets: Vec<String> = match shared.registry.lock().account(&device.account) {
+        Some(devices) => devices
+            .keys()
+            .filter(|id| **id != device.device_id)
+            .filter(|id| match &to {
+                Some(to) => to.contains(id),
+                None => true,
+            })
+            .cloned()
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let text = serde_json::to_string(&ServerFrame::Frame {
+        message: message.clone(),
+    })?;
+    let online = shared.online.lock();
+    let sessions = online.get(&device.account);
+    for target in targets {
+        let session = sessions.and_then(|devices| devices.get(&target));
+        let delivered = session.is_some_and(|s| s.tx.send(Message::Text(text.clone())).is_ok());
+        if !delivered {
+            shared
+                .queue
+                .lock()
+                .push(&device.account, &target, message.clone());
+        }
+    }
+
+    Ok(())
+}
+
+fn device_list(shared: &Shared, account: &str) -> Vec<DeviceStatus> {
+    let registry = shared.registry.lock();
+    let online = shared.online.lock();
+    let connected = online.get(account);
+
+    registry
+        .account(account)
+        .map(|devices| {
+            devices
+                .iter()
+                .map(|(device_id, public_key)| DeviceStatus {
+                    device_id: device_id.clone(),
+                    public_key: public_key.clone(),
+                    online: connected.is_some_and(|c| c.contains_key(device_id)),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Tell every connected device of the account about its devices
+fn push_device_list(shared: &Shared, account: &str) {
+    let devices = device_list(shared, account);
+    let frame = ServerFrame::Devices { devices };
+    if let Some(sessions) = shared.online.lock().get(account) {
+        for session in sessions.values() {
+            queue_frame(&session.tx, &frame);
+        }
+    }
+}
+
+fn queue_frame(tx: &Outgoing, frame: &ServerFrame) {
+    if let Ok(text) = serde_json::to_string(frame) {
+        let _ = tx.send(Message::Text(text));
+    }
+}
+
+async fn send(ws: &mut WebSocketStream<TcpStream>, frame: &ServerFrame) -> Result<()> {
+    ws.send(Message::Text(serde_json::to_string(frame)?))
+        .await?;
+    Ok(())
+}
+
+async fn read_frame(ws: &mut WebSocketStream<TcpStream>) -> Result<ClientFrame> {
+    while let Some(message) = ws.next().await {
+        match message? {
+            Message::Text(text) => return Ok(serde_json::from_str(&text)?),
+            Message::Close(_) => break,
+            _ => continue,
+        }
+    }
+    Err(RelayError::Protocol("Connection closed".to_string()))
+}
@@ -0,0 +1,173 @@
+use clipbridge_relay::protocol::{auth_message, ClientFrame, ServerFrame};
+use clipbridge_relay::{RelayServer, ServerConfig};
+use ed25519_dalek::{Signer, SigningKey};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn start_relay() -> SocketAddr {
+    let config = ServerConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        ..Default::default()
+    };
+    let server = RelayServer::bind(config).await.unwrap();
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run_until(std::future::pending()));
+    addr
+}
+
+fn public_key(key: &SigningKey) -> String {
+    hex::encode(key.verifying_key().as_bytes())
+}
+
+async fn send(client: &mut Client, frame: &ClientFrame) {
+    let text = serde_json::to_string(frame).unwrap();
+    client.send(Message::Text(text)).await.unwrap();
+}
+
+async fn recv(client: &mut Client) -> ServerFrame {
+    let next = tokio::time::timeout(Duration::from_secs(5), client.next());
+    match next.await.expect("timed out waiting for relay") {
+        Some(Ok(Message::Text(text))) => serde_json::from_str(&text).unwrap(),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
+
+/// Connect and authenticate, returning the first frame after the challenge
+async fn connect(addr: SocketAddr, device_id: &str, key: &SigningKey) -> (Client, ServerFrame) {
+    let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+        .await
+        .unwrap();
+    send(
+        &mut client,
+        &ClientFrame::Hello {
+            account: "acct".to_string(),
+            device_id: device_id.to_string(),
+            public_key: public_key(key),
+        },
+    )
+    .await;
+
+    let nonce = match recv(&mut client).await {
+        ServerFrame::Challenge { nonce } => nonce,
+        other => return (client, other),
+    };
+    let signature = key.sign(auth_message(&nonce, "acct", device_id).as_bytes());
+    send(
+        &mut client,
+        &ClientFrame::Auth {
+            signature: hex::encode(signature.to_bytes()),
+        },
+    )
+    .await;
+
+    let reply = recv(&mut client).await;
+    (client, reply)
+}
+
+fn clipboard_message(from: &str, to: Option<Vec<&str>>) -> serde_json::Value {
+    json!({
+        "msg_type": "ClipboardUpdate",
+        "from": from,
+        "to": to,
+        "payload": { "content": "sealed:0011" },
+        "timestamp": 0,
+        "nonce": uuid::Uuid::new_v4().to_string(),
+    })
+}
+
+/// Read frames until a relayed message arrives
+async fn recv_message(client: &mut Client) -> serde_json::Value {
+    loop {
+        if let ServerFrame::Frame { message } = recv(client).await {
+            return message;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_routes_and_queues_between_devices() {
+    let addr = start_relay().await;
+    let laptop_key = SigningKey::generate(&mut rand::rngs::OsRng);
+    let phone_key = SigningKey::generate(&mut rand::rngs::OsRng);
+
+    let (mut laptop, welcome) = connect(addr, "laptop", &laptop_key).await;
+    assert!(matches!(welcome, ServerFrame::Welcome { .. }));
+    send(
+        &mut laptop,
+        &ClientFrame::AddDevice {
+            device_id: "phone".to_string(),
+            public_key: public_key(&phone_key),
+        },
+    )
+    .await;
+
+    // The phone is offline, so this is queued
+    let queued = clipboard_message("laptop", None);
+    send(
+        &mut laptop,
+        &ClientFrame::Frame {
+            message: queued.clone(),
+        },
+    )
+    .await;
+
+    let (mut phone, welcome) = connect(addr, "phone", &phone_key).await;
+    let ServerFrame::Welcome { devices } = welcome else {
+        panic!("expected welcome, got {:?}", welcome);
+    };
+    assert_eq!(devices.len(), 2);
+    assert!(devices.iter().all(|d| d.online));
+    assert_eq!(recv_message(&mut phone).await, queued);
+
+    // Live delivery, forwarded unchanged
+    let live = clipboard_message("phone", Some(vec!["laptop"]));
+    send(
+        &mut phone,
+        &ClientFrame::Frame {
+            message: live.clone(),
+        },
+    )
+    .await;
+    assert_eq!(recv_message(&mut laptop).await, live);
+
+    // Spoofed senders are refused
+    send(
+        &mut phone,
+        &ClientFrame::Frame {
+            message: clipboard_message("laptop", None),
+        },
+    )
+    .await;
+    loop {
+        match recv(&mut phone).await {
+            ServerFrame::Error { .. } => break,
+            ServerFrame::Devices { .. } => continue,
+            other => panic!("expected error, got {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_rejects_unknown_keys() {
+    let addr = start_relay().await;
+    let owner = SigningKey::generate(&mut rand::rngs::OsRng);
+    let stranger = SigningKey::generate(&mut rand::rngs::OsRng);
+
+    let (_laptop, welcome) = connect(addr, "laptop", &owner).await;
+    assert!(matches!(welcome, ServerFrame::Welcome { .. }));
+
+    // Same device id, different key
+    let (_, reply) = connect(addr, "laptop", &stranger).await;
+    assert!(matches!(reply, ServerFrame::Error { .. }));
+
+    // A device nobody added
+    let (_, reply) = connect(addr, "tablet", &stranger).await;
+    assert!(matches!(reply, ServerFrame::Error { .. }));
+}